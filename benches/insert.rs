@@ -0,0 +1,142 @@
+// Compares the prepared statement BulkWriter against the hand built multi-row INSERT strings
+// the indexer used before it. run with `cargo bench --bench insert`
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libsql::Connection;
+use rustsearch::database::{create_schema, BulkWriter, FileRecord};
+
+const BATCH_SIZE: usize = 500;
+
+fn make_records(count: usize) -> Vec<FileRecord> {
+    (0..count)
+        .map(|i| FileRecord {
+            path: PathBuf::from(format!(
+                "/home/theo/Projects/project-{}/src/module_{}/file's_{}.rs",
+                i % 37,
+                i % 113,
+                i
+            )),
+            filename: format!("file's_{}.rs", i).into(),
+            extension: "rs".to_string(),
+            size: (i * 31) as i64,
+            modified_at: 1_700_000_000 + i as i64,
+            created_at: Some(1_600_000_000 + i as i64),
+            accessed_at: Some(1_700_000_000 + i as i64),
+            indexed_at: None,
+            device: None,
+            inode: None,
+            link_target: None,
+            permissions: None,
+            mime: None,
+            xattrs: Default::default(),
+        })
+        .collect()
+}
+
+async fn fresh_database(dir: &tempfile::TempDir, name: &str) -> Connection {
+    let database = libsql::Builder::new_local(dir.path().join(name))
+        .build()
+        .await
+        .unwrap();
+    let conn = database.connect().unwrap();
+    create_schema(conn.clone(), None).await.unwrap();
+    conn
+}
+
+// the previous approach, kept here only as the baseline to beat
+async fn insert_with_strings(conn: &Connection, records: &[FileRecord]) {
+    for batch in records.chunks(BATCH_SIZE) {
+        let mut query = String::with_capacity(BATCH_SIZE * 200);
+        query.push_str("INSERT INTO files (path, filename, extension, size, modified_at) VALUES ");
+        for (i, record) in batch.iter().enumerate() {
+            if i > 0 {
+                query.push_str(", ");
+            }
+            query.push_str(&format!(
+                "('{}', '{}', '{}', {}, {})",
+                record.path.display().to_string().replace('\'', "''"),
+                record.filename.to_string_lossy().replace('\'', "''"),
+                record.extension.replace('\'', "''"),
+                record.size,
+                record.modified_at
+            ));
+        }
+        query.push_str(
+            " ON CONFLICT(path) DO UPDATE SET filename = excluded.filename,
+            extension = excluded.extension, size = excluded.size, modified_at = excluded.modified_at",
+        );
+        conn.execute("BEGIN", ()).await.unwrap();
+        conn.execute_batch(&query).await.unwrap();
+        conn.execute("COMMIT", ()).await.unwrap();
+    }
+}
+
+async fn insert_with_bulk_writer(conn: &Connection, records: &[FileRecord]) {
+    let mut writer = BulkWriter::new(conn).await.unwrap();
+    for batch in records.chunks(BATCH_SIZE) {
+        conn.execute("BEGIN", ()).await.unwrap();
+        let errors = writer.write(batch).await;
+        assert!(errors.is_empty());
+        conn.execute("COMMIT", ()).await.unwrap();
+    }
+}
+
+fn bench_inserts(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("insert_files");
+    group.sample_size(10);
+
+    for count in [5_000, 20_000] {
+        let records = make_records(count);
+        group.throughput(Throughput::Elements(count as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("string_batches", count),
+            &records,
+            |b, records| {
+                b.iter_custom(|iterations| {
+                    runtime.block_on(async {
+                        let mut total = Duration::ZERO;
+                        for i in 0..iterations {
+                            let dir = tempfile::tempdir().unwrap();
+                            let conn = fresh_database(&dir, &format!("strings-{}.db", i)).await;
+                            let started = Instant::now();
+                            insert_with_strings(&conn, records).await;
+                            total += started.elapsed();
+                        }
+                        total
+                    })
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("bulk_writer", count),
+            &records,
+            |b, records| {
+                b.iter_custom(|iterations| {
+                    runtime.block_on(async {
+                        let mut total = Duration::ZERO;
+                        for i in 0..iterations {
+                            let dir = tempfile::tempdir().unwrap();
+                            let conn = fresh_database(&dir, &format!("bulk-{}.db", i)).await;
+                            let started = Instant::now();
+                            insert_with_bulk_writer(&conn, records).await;
+                            total += started.elapsed();
+                        }
+                        total
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_inserts);
+criterion_main!(benches);
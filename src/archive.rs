@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use libsql::{Connection, Value};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::Config,
+    database::{self, BulkWriter, FileRecord},
+    paths::{display_path, os_str_bytes, path_from_bytes},
+};
+
+// Exporting the index to a file and importing it somewhere else
+//
+// there are two formats, both zstd compressed and both starting with an ArchiveHeader:
+//
+//   ndjson    one JSON object per line, the header first and then one per file.
+//             paths are {"text": ...} or {"bytes": "<base64>"}, as in `search --json`
+//   snapshot  "\0rsindex", a u32 format version, a u32 length and the header as JSON,
+//             then the files: a u32 path length, the path bytes, and size, modified_at,
+//             created_at and accessed_at as i64 (i64::MIN for a missing time). little endian
+//
+// only what the walker reads from disk is exported, history and selections stay local
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const SNAPSHOT_MAGIC: &[u8] = b"\0rsindex";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const MISSING_TIME: i64 = i64::MIN;
+// lengths in a snapshot come from the file, anything past these is taken for damage rather than
+// allocated. paths can be longer than PATH_MAX when a walk goes deep enough, so this is roomier
+const MAX_HEADER_LENGTH: usize = 1024 * 1024;
+const MAX_PATH_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Ndjson,
+    Snapshot,
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "ndjson" | "json" => Ok(ArchiveFormat::Ndjson),
+            "snapshot" | "binary" => Ok(ArchiveFormat::Snapshot),
+            _ => Err(format!(
+                "unknown archive format '{}', expected ndjson or snapshot",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub format_version: u32,
+    // PRAGMA user_version of the database it came from
+    pub schema_version: i64,
+    // the directories it covers, "/" for the whole index
+    pub roots: Vec<String>,
+    // indexes built with different skip rules hold different files, see config_hash
+    pub config_hash: String,
+    pub exported_at: i64,
+}
+
+// sha256 of the indexing settings
+pub fn config_hash(config: &Config) -> String {
+    let settings = serde_json::to_vec(config).expect("config is always serializable");
+    format!("{:x}", Sha256::digest(settings))
+}
+
+// a line of the ndjson format
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line {
+    Header(ArchiveHeader),
+    File(ArchivedFile),
+}
+
+// a file as it's kept in an archive, the rest of FileRecord comes from the path
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    #[serde(
+        serialize_with = "crate::paths::serialize_path",
+        deserialize_with = "crate::paths::deserialize_path"
+    )]
+    pub path: PathBuf,
+    pub size: i64,
+    pub modified_at: i64,
+    pub created_at: Option<i64>,
+    pub accessed_at: Option<i64>,
+}
+
+impl ArchivedFile {
+    pub fn into_record(self) -> FileRecord {
+        FileRecord {
+            size: self.size,
+            modified_at: self.modified_at,
+            created_at: self.created_at,
+            accessed_at: self.accessed_at,
+            ..FileRecord::from_path(self.path)
+        }
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub struct ArchiveWriter {
+    format: ArchiveFormat,
+    out: zstd::stream::Encoder<'static, BufWriter<File>>,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path, format: ArchiveFormat, header: &ArchiveHeader) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        if format == ArchiveFormat::Snapshot {
+            let header = serde_json::to_vec(header)?;
+            file.write_all(SNAPSHOT_MAGIC)?;
+            file.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+            file.write_all(&(header.len() as u32).to_le_bytes())?;
+            file.write_all(&header)?;
+        }
+
+        let mut writer = Self {
+            format,
+            out: zstd::stream::Encoder::new(file, 3)?,
+        };
+        if format == ArchiveFormat::Ndjson {
+            serde_json::to_writer(&mut writer.out, &Line::Header(header.clone()))?;
+            writer.out.write_all(b"\n")?;
+        }
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, file: ArchivedFile) -> io::Result<()> {
+        match self.format {
+            ArchiveFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, &Line::File(file))?;
+                self.out.write_all(b"\n")
+            }
+            ArchiveFormat::Snapshot => {
+                let record = file;
+                let path = os_str_bytes(record.path.as_os_str());
+                self.out.write_all(&(path.len() as u32).to_le_bytes())?;
+                self.out.write_all(path)?;
+                for value in [
+                    record.size,
+                    record.modified_at,
+                    record.created_at.unwrap_or(MISSING_TIME),
+                    record.accessed_at.unwrap_or(MISSING_TIME),
+                ] {
+                    self.out.write_all(&value.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // ends the compressed stream, an archive that isn't finished is cut off
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = self.out.finish()?;
+        file.flush()
+    }
+}
+
+pub struct ArchiveReader {
+    header: ArchiveHeader,
+    format: ArchiveFormat,
+    input: Box<dyn BufRead + Send>,
+    line: String,
+}
+
+impl ArchiveReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let start = file.fill_buf()?;
+
+        if start.starts_with(SNAPSHOT_MAGIC) {
+            let mut fixed = [0; 16];
+            file.read_exact(&mut fixed)?;
+            let version = u32::from_le_bytes(fixed[8..12].try_into().unwrap());
+            check_version(version)?;
+            let length = read_length(&fixed[12..16], MAX_HEADER_LENGTH, "header")?;
+            let mut header = vec![0; length];
+            file.read_exact(&mut header)?;
+            let header: ArchiveHeader = serde_json::from_slice(&header)?;
+            check_version(header.format_version)?;
+
+            let input = BufReader::new(zstd::stream::Decoder::with_buffer(file)?);
+            return Ok(Self {
+                header,
+                format: ArchiveFormat::Snapshot,
+                input: Box::new(input),
+                line: String::new(),
+            });
+        }
+
+        // ndjson is read whether or not it was compressed, so a hand edited file works too
+        let mut input: Box<dyn BufRead + Send> = if start.starts_with(ZSTD_MAGIC) {
+            Box::new(BufReader::new(zstd::stream::Decoder::with_buffer(file)?))
+        } else {
+            Box::new(file)
+        };
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        let header = match serde_json::from_str(&line) {
+            Ok(Line::Header(header)) => header,
+            _ => return Err(invalid_data("not a rustsearch archive")),
+        };
+        check_version(header.format_version)?;
+
+        Ok(Self {
+            header,
+            format: ArchiveFormat::Ndjson,
+            input,
+            line,
+        })
+    }
+
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    fn next_ndjson(&mut self) -> io::Result<Option<ArchivedFile>> {
+        loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if self.line.trim().is_empty() {
+                continue;
+            }
+            return match serde_json::from_str(&self.line)? {
+                Line::File(file) => Ok(Some(file)),
+                Line::Header(_) => {
+                    Err(invalid_data("a second header in the middle of the archive"))
+                }
+            };
+        }
+    }
+
+    fn next_snapshot(&mut self) -> io::Result<Option<ArchivedFile>> {
+        let mut length = [0; 4];
+        // a clean end of file can only come where a record would start
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        self.input.read_exact(&mut length)?;
+        let mut path = vec![0; read_length(&length, MAX_PATH_LENGTH, "path")?];
+        self.input.read_exact(&mut path)?;
+
+        let mut values = [0i64; 4];
+        for value in &mut values {
+            let mut bytes = [0; 8];
+            self.input.read_exact(&mut bytes)?;
+            *value = i64::from_le_bytes(bytes);
+        }
+        let time = |value: i64| (value != MISSING_TIME).then_some(value);
+
+        Ok(Some(ArchivedFile {
+            path: path_from_bytes(&path),
+            size: values[0],
+            modified_at: values[1],
+            created_at: time(values[2]),
+            accessed_at: time(values[3]),
+        }))
+    }
+}
+
+impl Iterator for ArchiveReader {
+    type Item = io::Result<ArchivedFile>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            ArchiveFormat::Ndjson => self.next_ndjson(),
+            ArchiveFormat::Snapshot => self.next_snapshot(),
+        }
+        .transpose()
+    }
+}
+
+// a snapshot's u32 length field, if it's within `limit`
+fn read_length(bytes: &[u8], limit: usize, what: &str) -> io::Result<usize> {
+    let length = u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    if length > limit {
+        return Err(invalid_data(format!(
+            "snapshot {} length {} is over the limit of {} bytes",
+            what, length, limit
+        )));
+    }
+    Ok(length)
+}
+
+fn check_version(version: u32) -> io::Result<()> {
+    if version > ARCHIVE_VERSION {
+        return Err(invalid_data(format!(
+            "archive format version {} is newer than this rustsearch supports ({})",
+            version, ARCHIVE_VERSION
+        )));
+    }
+    Ok(())
+}
+
+// writes every indexed file under `roots` to `path`, all of them if `roots` is empty
+// returns how many files were written
+pub async fn export(
+    conn: &Connection,
+    path: &Path,
+    format: ArchiveFormat,
+    roots: &[PathBuf],
+    config: &Config,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let roots: Vec<String> = roots
+        .iter()
+        .map(|root| display_path(root.as_os_str()))
+        .map(|root| match root.trim_end_matches('/') {
+            "" => "/".to_string(),
+            root => root.to_string(),
+        })
+        .collect();
+
+    let mut sql = String::from(
+        "SELECT path, path_bytes, size, CAST(modified_at AS INTEGER), created_at, accessed_at \
+         FROM files",
+    );
+    let mut params = Vec::new();
+    // "/" covers everything, so there's nothing to filter on
+    if !roots.is_empty() && !roots.iter().any(|root| root == "/") {
+        let conditions: Vec<String> = roots
+            .iter()
+            .map(|root| {
+                params.push(Value::Text(root.clone()));
+                params.push(Value::Text(format!("{}/", root)));
+                format!(
+                    "(path = ?{} OR substr(path, 1, length(?{n})) = ?{n})",
+                    params.len() - 1,
+                    n = params.len()
+                )
+            })
+            .collect();
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" OR "));
+    }
+
+    let header = ArchiveHeader {
+        format_version: ARCHIVE_VERSION,
+        schema_version: database::get_schema_version(conn).await?,
+        roots: if roots.is_empty() {
+            vec!["/".to_string()]
+        } else {
+            roots
+        },
+        config_hash: config_hash(config),
+        exported_at: chrono::Utc::now().timestamp(),
+    };
+    let mut writer = ArchiveWriter::create(path, format, &header)?;
+
+    let mut files = 0;
+    let mut rows = conn.query(&sql, params).await?;
+    while let Some(row) = rows.next().await? {
+        let path = match row.get::<Option<Vec<u8>>>(1)? {
+            Some(bytes) => path_from_bytes(&bytes),
+            None => PathBuf::from(row.get::<String>(0)?),
+        };
+        writer.write(ArchivedFile {
+            path,
+            size: row.get(2)?,
+            modified_at: row.get(3)?,
+            created_at: row.get(4)?,
+            accessed_at: row.get(5)?,
+        })?;
+        files += 1;
+    }
+    writer.finish()?;
+    Ok(files)
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveImport {
+    pub header: ArchiveHeader,
+    pub files: u64,
+}
+
+// merges an archive into the index, files already indexed take the archive's sizes and times
+pub async fn import(
+    conn: &Mutex<Connection>,
+    path: &Path,
+) -> Result<ArchiveImport, Box<dyn std::error::Error>> {
+    let reader = ArchiveReader::open(path)?;
+    let header = reader.header().clone();
+
+    let mut writer = {
+        let conn = conn.lock().await;
+        BulkWriter::importing(&conn).await?
+    };
+    let records = reader.map(|file| file.map(ArchivedFile::into_record));
+    database::write_in_batches(conn, &mut writer, records).await?;
+
+    Ok(ArchiveImport {
+        header,
+        files: writer.changes(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ArchiveChange {
+    Added {
+        file: ArchivedFile,
+    },
+    Removed {
+        file: ArchivedFile,
+    },
+    // the size or modified time is different
+    Changed {
+        before: ArchivedFile,
+        after: ArchivedFile,
+    },
+}
+
+impl ArchiveChange {
+    pub fn path(&self) -> &Path {
+        match self {
+            ArchiveChange::Added { file } | ArchiveChange::Removed { file } => &file.path,
+            ArchiveChange::Changed { after, .. } => &after.path,
+        }
+    }
+}
+
+// what changed from `before` to `after`, in path order. either can be in either format
+pub fn diff(before: &Path, after: &Path) -> io::Result<Vec<ArchiveChange>> {
+    let mut remaining: HashMap<PathBuf, ArchivedFile> = HashMap::new();
+    for file in ArchiveReader::open(before)? {
+        let file = file?;
+        remaining.insert(file.path.clone(), file);
+    }
+
+    let mut changes = Vec::new();
+    for file in ArchiveReader::open(after)? {
+        let file = file?;
+        match remaining.remove(&file.path) {
+            None => changes.push(ArchiveChange::Added { file }),
+            Some(before) if before.size != file.size || before.modified_at != file.modified_at => {
+                changes.push(ArchiveChange::Changed {
+                    before,
+                    after: file,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(
+        remaining
+            .into_values()
+            .map(|file| ArchiveChange::Removed { file }),
+    );
+
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(changes)
+}
+
+// for `rustsearch export` without --format, archive.snapshot is a snapshot and anything else ndjson
+pub fn format_for_path(path: &Path) -> ArchiveFormat {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("snapshot") => ArchiveFormat::Snapshot,
+        _ => ArchiveFormat::Ndjson,
+    }
+}
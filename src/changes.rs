@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use libsql::{Connection, Result as SQLResult, Value};
+use serde::Serialize;
+
+use crate::{paths::path_from_bytes, search::SearchQuery};
+
+// The changes log, for `rustsearch changes --since 1h [query]`
+//
+// the rows are written by the files_changes_* triggers (see database.rs) whenever re-indexing or
+// the watcher sees a file created, modified, deleted or renamed. the query's filters work the same
+// as in a search, on the file as it was after the change (before it, for a delete), and plain
+// words have to appear somewhere in the path. what a change doesn't log, like its owner and mode,
+// is taken from the file as it is now, see CURRENT_COLUMNS
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl ChangeKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "created" => Some(ChangeKind::Created),
+            "modified" => Some(ChangeKind::Modified),
+            "deleted" => Some(ChangeKind::Deleted),
+            "renamed" => Some(ChangeKind::Renamed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed => "renamed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    // when it was seen, which can be a while after it happened if it was found by re-indexing
+    pub changed_at: i64,
+    #[serde(serialize_with = "crate::paths::serialize_path")]
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified_at: i64,
+    // where a renamed file used to be
+    #[serde(serialize_with = "crate::paths::serialize_optional_path")]
+    pub old_path: Option<PathBuf>,
+}
+
+// path and old_path are display forms when the raw bytes are there, see paths.rs
+fn path_from_row(row: &libsql::Row, text: i32, bytes: i32) -> SQLResult<Option<PathBuf>> {
+    Ok(match row.get::<Option<Vec<u8>>>(bytes)? {
+        Some(bytes) => Some(path_from_bytes(&bytes)),
+        None => row.get::<Option<String>>(text)?.map(PathBuf::from),
+    })
+}
+
+// the files columns filters use that changes doesn't have. they're joined in from the file at the
+// change's path, so a file that's gone since never matches a filter on them
+const CURRENT_COLUMNS: &str =
+    "current.device, current.link_target, current.uid, current.gid, current.mode, current.mime, \
+     current.xattrs";
+
+// changes seen at or after `since`, oldest first. with a limit, it's the most recent ones
+pub async fn get_changes(
+    conn: &Connection,
+    since: i64,
+    query: &SearchQuery,
+) -> SQLResult<Vec<Change>> {
+    let mut params = vec![Value::Integer(since)];
+    // aliased to files so the search filters can be used as they are
+    let mut sql = format!(
+        "SELECT kind, changed_at, path, path_bytes, size, modified_at, old_path, old_path_bytes \
+         FROM (SELECT changes.*, {} FROM changes \
+             LEFT JOIN files AS current ON current.path = changes.path) AS files \
+         WHERE changed_at >= ?1",
+        CURRENT_COLUMNS
+    );
+    for word in query.get_query().split_whitespace() {
+        params.push(Value::Text(format!("%{}%", word)));
+        sql.push_str(&format!(" AND files.path LIKE ?{}", params.len()));
+    }
+    if let Some(filters) = query.filter_sql(&mut params) {
+        sql.push_str(" AND ");
+        sql.push_str(&filters);
+    }
+    sql.push_str(" ORDER BY id DESC");
+    if let Some(limit) = query.get_limit() {
+        sql.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    let mut rows = conn.query(&sql, params).await?;
+    let mut changes = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let kind: String = row.get(0)?;
+        let Some(kind) = ChangeKind::parse(&kind) else {
+            continue;
+        };
+        changes.push(Change {
+            kind,
+            changed_at: row.get(1)?,
+            path: path_from_row(&row, 2, 3)?.unwrap_or_default(),
+            size: row.get::<i64>(4)? as u64,
+            modified_at: row.get(5)?,
+            old_path: path_from_row(&row, 6, 7)?,
+        });
+    }
+    changes.reverse();
+    Ok(changes)
+}
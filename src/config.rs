@@ -1,18 +1,59 @@
-use std::io::Read;
+use std::{io::Read, path::PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     pub max_file_size_mb: u64,
     pub max_depth: u64,
     pub include_hidden: bool,
+    // compiled programs and libraries, needs detect_mime. see content.rs
     pub skip_binary: bool,
     pub index_limit: u64,
     pub skip_directories: Vec<String>,
     pub skip_extensions: Vec<String>,
     pub skip_patterns: Vec<String>,
     pub force_include: Vec<String>,
+    // where the walker starts, they shouldn't be inside each other
+    #[serde(default = "default_roots")]
+    pub roots: Vec<PathBuf>,
+    #[serde(default)]
+    pub follow_symlinks: FollowSymlinks,
+    // stay on each root's filesystem, like find -xdev
+    #[serde(default)]
+    pub one_file_system: bool,
+    // filesystem types from /proc/self/mountinfo, an empty include list means all of them
+    #[serde(default)]
+    pub include_filesystems: Vec<String>,
+    #[serde(default = "default_exclude_filesystems")]
+    pub exclude_filesystems: Vec<String>,
+    // both open every file, so they're off unless asked for
+    #[serde(default)]
+    pub detect_mime: bool,
+    #[serde(default)]
+    pub index_xattrs: bool,
+}
+
+// which symlinks to directories the walker goes into. a link is always indexed itself,
+// and one leading back into a directory it's already inside is never followed
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FollowSymlinks {
+    #[default]
+    Never,
+    // only when the target is under one of the roots
+    WithinRoots,
+    Always,
+}
+
+fn default_roots() -> Vec<PathBuf> {
+    vec![PathBuf::from("/")]
+}
+
+fn default_exclude_filesystems() -> Vec<String> {
+    ["proc", "sysfs", "tmpfs", "fuse.sshfs"]
+        .map(String::from)
+        .to_vec()
 }
 
 impl Default for Config {
@@ -27,6 +68,13 @@ impl Default for Config {
             skip_extensions: vec!["exe".to_string(), "dll".to_string()],
             skip_patterns: vec![],
             force_include: vec![],
+            roots: default_roots(),
+            follow_symlinks: FollowSymlinks::Never,
+            one_file_system: false,
+            include_filesystems: vec![],
+            exclude_filesystems: default_exclude_filesystems(),
+            detect_mime: false,
+            index_xattrs: false,
         }
     }
 }
@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, fs::File, io::Read, path::Path};
+
+use rayon::prelude::*;
+use tracing::trace;
+
+use crate::{config::Config, database::FileRecord};
+
+// What the walker reads from inside files
+//
+// with detect_mime, each file's type is sniffed from its first bytes and stored in files.mime,
+// and skip_binary leaves out compiled programs and libraries. with index_xattrs, the file's
+// user.* extended attributes are stored in files.xattrs as a JSON object. both are done a batch
+// at a time, in parallel, just before the batch is written
+
+// enough for every format infer knows
+const SNIFF_BYTES: usize = 8192;
+// bigger values, e.g. thumbnails, aren't worth searching
+const MAX_XATTR_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentOptions {
+    pub detect_mime: bool,
+    pub skip_binary: bool,
+    pub index_xattrs: bool,
+}
+
+impl ContentOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            detect_mime: config.detect_mime,
+            skip_binary: config.skip_binary && config.detect_mime,
+            index_xattrs: config.index_xattrs,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.detect_mime || self.index_xattrs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sniffed {
+    pub mime: String,
+    // an executable, shared library or other compiled code
+    pub binary: bool,
+}
+
+// like file --mime-type, types infer doesn't know are text/plain when they look like text
+pub fn sniff(path: &Path) -> Option<Sniffed> {
+    let metadata = std::fs::symlink_metadata(path).ok()?;
+    if metadata.file_type().is_symlink() {
+        return Some(Sniffed {
+            mime: "inode/symlink".to_string(),
+            binary: false,
+        });
+    }
+    let mut buf = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut buf)
+        .ok()?;
+
+    if let Some(kind) = infer::get(&buf) {
+        return Some(Sniffed {
+            mime: kind.mime_type().to_string(),
+            binary: kind.matcher_type() == infer::MatcherType::App,
+        });
+    }
+    let mime = if buf.is_empty() {
+        "inode/x-empty"
+    } else if !buf.contains(&0) && looks_like_utf8(&buf) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    };
+    Some(Sniffed {
+        mime: mime.to_string(),
+        binary: false,
+    })
+}
+
+// the read can stop part way through a character
+fn looks_like_utf8(buf: &[u8]) -> bool {
+    match std::str::from_utf8(buf) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+// the user namespace only, the others hold ACLs, capabilities and SELinux labels
+pub fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    let Ok(names) = xattr::list(path) else {
+        return BTreeMap::new();
+    };
+    names
+        .filter_map(|name| {
+            let name = name.to_str()?.to_string();
+            if !name.starts_with("user.") {
+                return None;
+            }
+            let value = xattr::get(path, &name).ok()??;
+            if value.len() > MAX_XATTR_BYTES {
+                return None;
+            }
+            Some((name, String::from_utf8_lossy(&value).into_owned()))
+        })
+        .collect()
+}
+
+// fills in mime and xattrs for a batch, dropping binaries when skip_binary is set
+pub fn read_content(batch: &mut Vec<FileRecord>, options: ContentOptions) {
+    if !options.is_enabled() {
+        return;
+    }
+    let binaries: Vec<bool> = batch
+        .par_iter_mut()
+        .map(|record| {
+            let mut binary = false;
+            if options.detect_mime {
+                if let Some(sniffed) = sniff(&record.path) {
+                    binary = sniffed.binary;
+                    record.mime = Some(sniffed.mime);
+                }
+            }
+            if options.index_xattrs {
+                record.xattrs = read_xattrs(&record.path);
+            }
+            binary
+        })
+        .collect();
+
+    if options.skip_binary {
+        let mut binaries = binaries.into_iter();
+        batch.retain(|record| {
+            let binary = binaries.next().unwrap_or(false);
+            if binary {
+                trace!(path = %record.path.display(), "Skipping binary");
+            }
+            !binary
+        });
+    }
+}
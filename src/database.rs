@@ -1,65 +1,540 @@
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use jwalk::WalkDirGeneric;
-use libsql::{params, Connection, Database, Result as SQLResult};
+use jwalk::{ClientState, DirEntry};
+use libsql::{params, Connection, Result as SQLResult, Statement, Value};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, instrument, warn};
 
-use crate::search;
+use crate::{
+    content::{read_content, ContentOptions},
+    paths::{display_path, os_str_bytes, path_from_bytes, path_from_display, raw_path_bytes},
+    permissions::Permissions,
+    progress::IndexProgress,
+};
+
+// the schema version this build creates, stored in PRAGMA user_version
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+// MIGRATIONS[n] upgrades a database from version n to n + 1
+// only ever append to this list, a migration that has shipped must not change
+// databases from before versioning existed report user_version 0, so the first
+// migration has to cope with the tables already being there
+const MIGRATIONS: &[&str] = &[
+    // 1: files table, external content FTS5 index and the triggers keeping it in sync
+    "CREATE TABLE IF NOT EXISTS files (
+        id          INTEGER PRIMARY KEY,
+        path        TEXT NOT NULL,
+        filename    TEXT NOT NULL,
+        extension   TEXT,
+        size        INTEGER NOT NULL,
+        modified_at TEXT NOT NULL,
+        UNIQUE(path)
+    );
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
+        filename,
+        path,
+        extension,
+        content='files',
+        content_rowid='id'
+    );
+
+    -- keep files_fts in sync with files, so searches never see stale data
+    -- external content tables need the old values to delete an entry
+    CREATE TRIGGER IF NOT EXISTS files_fts_insert AFTER INSERT ON files BEGIN
+        INSERT INTO files_fts(rowid, filename, path, extension)
+        VALUES (new.id, new.filename, new.path, new.extension);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS files_fts_delete AFTER DELETE ON files BEGIN
+        INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+        VALUES ('delete', old.id, old.filename, old.path, old.extension);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS files_fts_update AFTER UPDATE ON files BEGIN
+        INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+        VALUES ('delete', old.id, old.filename, old.path, old.extension);
+        INSERT INTO files_fts(rowid, filename, path, extension)
+        VALUES (new.id, new.filename, new.path, new.extension);
+    END;
+
+    -- older databases were only synced by a manual rebuild, so the index may be stale
+    INSERT INTO files_fts(files_fts) VALUES('rebuild');",
+    // 2: index runs and the walk checkpoints used to resume an interrupted run
+    "CREATE TABLE index_runs (
+        id            INTEGER PRIMARY KEY,
+        started_at    INTEGER NOT NULL,
+        finished_at   INTEGER,
+        status        TEXT NOT NULL,
+        files_indexed INTEGER NOT NULL DEFAULT 0
+    );
+
+    -- directories whose whole subtree has been committed, skipped when resuming
+    CREATE TABLE index_checkpoints (
+        run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+        directory TEXT NOT NULL,
+        PRIMARY KEY (run_id, directory)
+    );",
+    // 3: raw bytes for paths that aren't valid UTF-8, path becomes an always readable display form
+    // rows written as blobs by the previous bulk writer can't be converted in SQL,
+    // they are dropped and come back with the next index
+    "ALTER TABLE files ADD COLUMN path_bytes BLOB;
+    DELETE FROM files WHERE typeof(path) = 'blob' OR typeof(filename) = 'blob';",
+    // 4: indexes for every sort key, the expressions have to match Sort::to_sql in search.rs exactly
+    // path already has the UNIQUE index, relevance comes from files_fts
+    "CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+    CREATE INDEX files_size ON files(size);
+    CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+    CREATE INDEX files_extension_lower ON files(LOWER(extension));
+    CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));",
+    // 5: creation and access times, NULL where the filesystem doesn't record them
+    "ALTER TABLE files ADD COLUMN created_at INTEGER;
+    ALTER TABLE files ADD COLUMN accessed_at INTEGER;
+    CREATE INDEX files_created_at ON files(created_at);
+    CREATE INDEX files_accessed_at ON files(accessed_at);",
+    // 6: search history and named searches
+    "CREATE TABLE queries (
+        id           INTEGER PRIMARY KEY,
+        query        TEXT NOT NULL,
+        ran_at       INTEGER NOT NULL,
+        result_count INTEGER NOT NULL
+    );
+
+    CREATE TABLE saved_searches (
+        name       TEXT PRIMARY KEY,
+        query      TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );",
+    // 7: results people opened, for frecency ranking
+    "CREATE TABLE selections (
+        path             TEXT PRIMARY KEY,
+        count            INTEGER NOT NULL,
+        last_selected_at INTEGER NOT NULL
+    );",
+    // 8: a log of the changes seen by re-indexing and the watcher
+    // indexed_at is when the walker or watcher last read the file, NULL when the row was last written
+    // by an import. only rows read from disk are logged, and nothing is until the first index has
+    // finished, or every file would show up as created
+    "ALTER TABLE files ADD COLUMN indexed_at INTEGER;
+
+    CREATE TABLE changes (
+        id             INTEGER PRIMARY KEY,
+        kind           TEXT NOT NULL,
+        changed_at     INTEGER NOT NULL,
+        path           TEXT NOT NULL,
+        path_bytes     BLOB,
+        filename       TEXT NOT NULL,
+        extension      TEXT,
+        size           INTEGER NOT NULL,
+        modified_at    INTEGER NOT NULL,
+        created_at     INTEGER,
+        accessed_at    INTEGER,
+        old_path       TEXT,
+        old_path_bytes BLOB
+    );
+    CREATE INDEX changes_changed_at ON changes(changed_at);
+
+    CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+    WHEN new.indexed_at IS NOT NULL
+        AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+    BEGIN
+        INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension, size,
+            modified_at, created_at, accessed_at)
+        VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename, new.extension,
+            new.size, new.modified_at, new.created_at, new.accessed_at);
+    END;
+
+    -- only when it was read from disk both times, an import writing over a row doesn't count
+    CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+    WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+        AND (new.size != old.size OR new.modified_at != old.modified_at)
+    BEGIN
+        INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension, size,
+            modified_at, created_at, accessed_at)
+        VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename, new.extension,
+            new.size, new.modified_at, new.created_at, new.accessed_at);
+    END;
+
+    CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+    WHEN new.path != old.path
+    BEGIN
+        INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension, size,
+            modified_at, created_at, accessed_at, old_path, old_path_bytes)
+        VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename, new.extension,
+            new.size, new.modified_at, new.created_at, new.accessed_at, old.path, old.path_bytes);
+    END;
+
+    CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+    WHEN old.indexed_at IS NOT NULL
+    BEGIN
+        INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension, size,
+            modified_at, created_at, accessed_at)
+        VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename, old.extension,
+            old.size, old.modified_at, old.created_at, old.accessed_at);
+    END;",
+    // 9: device and inode, so a file that moved keeps its row, and with it its history and frecency
+    "ALTER TABLE files ADD COLUMN device INTEGER;
+    ALTER TABLE files ADD COLUMN inode INTEGER;
+    CREATE INDEX files_device_inode ON files(device, inode);
+
+    -- a file's frecency follows it to its new path, over whatever was opened there before
+    CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+    WHEN new.path != old.path
+    BEGIN
+        UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+    END;",
+    // 10: where a symlink points, for target:. only links have one, so the index stays small
+    "ALTER TABLE files ADD COLUMN link_target TEXT;
+    CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;",
+    // 11: the filesystems files.device refers to, for fs: and mount:. see mounts.rs
+    "CREATE TABLE mounts (
+        device      INTEGER PRIMARY KEY,
+        mount_point TEXT NOT NULL,
+        fstype      TEXT NOT NULL,
+        source      TEXT
+    );
+    CREATE INDEX mounts_fstype ON mounts(fstype);",
+    // 12: owner, group and mode of files and directories, for searching as another user. see
+    // permissions.rs
+    "ALTER TABLE files ADD COLUMN uid INTEGER;
+    ALTER TABLE files ADD COLUMN gid INTEGER;
+    ALTER TABLE files ADD COLUMN mode INTEGER;
+    CREATE TABLE directories (
+        path TEXT PRIMARY KEY,
+        uid  INTEGER NOT NULL,
+        gid  INTEGER NOT NULL,
+        mode INTEGER NOT NULL
+    );",
+    // 13: owner: and group:
+    "CREATE INDEX files_uid ON files(uid);
+    CREATE INDEX files_gid ON files(gid);",
+    // 14: detect_mime and index_xattrs, xattrs is a JSON object of name to value
+    "ALTER TABLE files ADD COLUMN mime TEXT;
+    ALTER TABLE files ADD COLUMN xattrs TEXT;
+    CREATE INDEX files_mime ON files(mime);",
+    // 15: display paths double their backslashes, see display_path. in rows that also have raw
+    // bytes a backslash can't be told apart from an \xNN escape, those are left for the next index
+    "CREATE TEMP TABLE last_change AS SELECT COALESCE(MAX(id), 0) AS id FROM changes;
+    -- before files, whose rename trigger moves the selections of the paths it changes
+    UPDATE OR REPLACE selections SET path = replace(path, '\\', '\\\\')
+    WHERE instr(path, '\\') > 0 AND path NOT GLOB '*\\x[0-9A-F][0-9A-F]*';
+    UPDATE changes SET
+        path_bytes = CAST(path AS BLOB),
+        path = replace(path, '\\', '\\\\'),
+        filename = replace(filename, '\\', '\\\\')
+    WHERE path_bytes IS NULL AND instr(path, '\\') > 0;
+    UPDATE changes SET
+        old_path_bytes = CAST(old_path AS BLOB),
+        old_path = replace(old_path, '\\', '\\\\')
+    WHERE old_path_bytes IS NULL AND instr(old_path, '\\') > 0;
+    UPDATE files SET
+        path_bytes = CAST(path AS BLOB),
+        path = replace(path, '\\', '\\\\'),
+        filename = replace(filename, '\\', '\\\\')
+    WHERE path_bytes IS NULL AND instr(path, '\\') > 0;
+    UPDATE files SET link_target = replace(link_target, '\\', '\\\\')
+    WHERE instr(link_target, '\\') > 0 AND link_target NOT GLOB '*\\x[0-9A-F][0-9A-F]*';
+    UPDATE OR REPLACE directories SET path = replace(path, '\\', '\\\\')
+    WHERE instr(path, '\\') > 0 AND path NOT GLOB '*\\x[0-9A-F][0-9A-F]*';
+    UPDATE mounts SET mount_point = replace(mount_point, '\\', '\\\\')
+    WHERE instr(mount_point, '\\') > 0 AND mount_point NOT GLOB '*\\x[0-9A-F][0-9A-F]*';
+    -- nothing moved on disk, so the renames the trigger logged aren't changes
+    DELETE FROM changes WHERE id > (SELECT id FROM last_change);
+    DROP TABLE last_change;",
+];
 
 pub async fn create_schema(connection: Connection, reset: Option<bool>) -> SQLResult<()> {
     if reset.unwrap_or(false) {
+        // files_fts first, it takes its shadow tables with it
         if let Err(e) = connection
             .execute("DROP TABLE IF EXISTS files_fts", ())
             .await
         {
-            eprintln!("Warning: Failed to drop files_fts table: {}", e);
+            warn!("Failed to drop files_fts table: {}", e);
+        }
+        // then everything else the migrations created, or they'd fail on tables that are still there
+        let mut rows = connection
+            .query(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+                (),
+            )
+            .await?;
+        let mut tables = Vec::new();
+        while let Some(row) = rows.next().await? {
+            tables.push(row.get::<String>(0)?);
         }
-        if let Err(e) = connection.execute("DROP TABLE IF EXISTS files", ()).await {
-            eprintln!("Warning: Failed to drop files table: {}", e);
+        for table in tables {
+            if let Err(e) = connection
+                .execute(&format!("DROP TABLE IF EXISTS \"{}\"", table), ())
+                .await
+            {
+                warn!("Failed to drop {} table: {}", table, e);
+            }
         }
+        connection.execute("PRAGMA user_version = 0", ()).await?;
     }
 
-    match connection
+    if let Err(e) = migrate(&connection).await {
+        error!("Failed to create database tables: {}", e);
+        return Err(e);
+    }
+
+    // WAL lets the search connections read while the indexer writes
+    // unlike the other pragmas this is stored in the database file, so it only needs setting once
+    connection
         .execute_batch(
-            "CREATE TABLE IF NOT EXISTS files (
-        id          INTEGER PRIMARY KEY,
-        path        TEXT NOT NULL,
-        filename    TEXT NOT NULL,
-        extension   TEXT,
-        size        INTEGER NOT NULL,
-        modified_at TEXT NOT NULL,
-        UNIQUE(path)
-        );
+            "PRAGMA journal_mode = WAL;
+        PRAGMA journal_size_limit = 1000000;
+        PRAGMA optimize;
+        ",
+        )
+        .await?;
+    configure_connection(&connection).await?;
 
-        CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
-            filename, 
-            path, 
-            extension,
-            content='files',
-            content_rowid='id'
-     
-        );
+    debug!("Database and FTS table created successfully.");
+    Ok(())
+}
 
-        PRAGMA journal_mode = OFF;
-        PRAGMA synchronous = OFF;
-        PRAGMA journal_size_limit = 1000000;
+// per connection settings, every connection opened on the database should run this
+// synchronous = NORMAL is safe with WAL, a crash can lose the last commits but never corrupts the file
+pub async fn configure_connection(connection: &Connection) -> SQLResult<()> {
+    connection
+        .execute_batch(
+            "PRAGMA synchronous = NORMAL;
+        PRAGMA busy_timeout = 5000;
         PRAGMA cache_size = 100000;
         PRAGMA temp_store = memory;
-        PRAGMA locking_mode = EXCLUSIVE;
         PRAGMA mmap_size = 268435456;
-        PRAGMA optimize;
         ",
         )
-        .await
-    {
-        Ok(_) => {
-            println!("Database and FTS table created successfully.");
-            Ok(())
+        .await?;
+    Ok(())
+}
+
+pub async fn get_schema_version(connection: &Connection) -> SQLResult<i64> {
+    let mut rows = connection.query("PRAGMA user_version", ()).await?;
+    match rows.next().await? {
+        Some(row) => row.get::<i64>(0),
+        None => Ok(0),
+    }
+}
+
+// bring the database up to SCHEMA_VERSION, one migration at a time
+// each step runs in its own transaction so a failure leaves the last good version behind
+pub async fn migrate(connection: &Connection) -> SQLResult<()> {
+    let version = get_schema_version(connection).await?;
+    if version > SCHEMA_VERSION {
+        return Err(libsql::Error::Misuse(format!(
+            "Database schema version {} is newer than the supported version {}, \
+             it was created by a newer rustsearch",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let target = index as i64 + 1;
+        info!("Migrating database to schema version {}...", target);
+
+        let batch = format!(
+            "BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+            migration, target
+        );
+        if let Err(e) = connection.execute_batch(&batch).await {
+            // the failed statement leaves the transaction open
+            let _ = connection.execute("ROLLBACK", ()).await;
+            return Err(e);
         }
-        Err(e) => {
-            eprintln!("Failed to create database tables: {}", e);
-            Err(e)
+    }
+
+    Ok(())
+}
+
+// regenerate the whole FTS index from the files table
+pub async fn rebuild_fts(connection: &Connection) -> SQLResult<()> {
+    connection
+        .execute("INSERT INTO files_fts(files_fts) VALUES('rebuild');", ())
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct IntegrityReport {
+    // problems reported by PRAGMA integrity_check, empty if the database is fine
+    pub database_errors: Vec<String>,
+    // the error from the FTS5 integrity-check, if files_fts doesn't match files
+    pub fts_error: Option<String>,
+    // whether the FTS index was rebuilt to fix a mismatch
+    pub repaired: bool,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.database_errors.is_empty() && (self.fts_error.is_none() || self.repaired)
+    }
+}
+
+// check the database file and that files_fts matches the files table
+// if `repair` is set, a mismatched FTS index is rebuilt
+pub async fn check_integrity(connection: &Connection, repair: bool) -> SQLResult<IntegrityReport> {
+    let mut database_errors = Vec::new();
+    let mut rows = connection.query("PRAGMA integrity_check", ()).await?;
+    while let Some(row) = rows.next().await? {
+        let message: String = row.get(0)?;
+        if message != "ok" {
+            database_errors.push(message);
         }
     }
+
+    // rank = 1 also compares the index against the external content table
+    let fts_error = match connection
+        .execute(
+            "INSERT INTO files_fts(files_fts, rank) VALUES('integrity-check', 1);",
+            (),
+        )
+        .await
+    {
+        Ok(_) => None,
+        Err(e) => Some(e.to_string()),
+    };
+
+    let repaired = if fts_error.is_some() && repair {
+        rebuild_fts(connection).await?;
+        true
+    } else {
+        false
+    };
+
+    Ok(IntegrityReport {
+        database_errors,
+        fts_error,
+        repaired,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RootStats {
+    pub root: String,
+    pub files: i64,
+    pub directories: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionStats {
+    pub extension: String,
+    pub files: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseStats {
+    pub files: i64,
+    // only directories that contain indexed files, empty ones aren't stored
+    pub directories: i64,
+    // split by top level directory, e.g. /home and /srv
+    pub roots: Vec<RootStats>,
+    pub database_bytes: i64,
+    pub fts_bytes: i64,
+    pub last_run: Option<IndexRun>,
+    pub top_extensions_by_count: Vec<ExtensionStats>,
+    pub top_extensions_by_bytes: Vec<ExtensionStats>,
+}
+
+const TOP_EXTENSIONS: usize = 10;
+
+// the directory a file is in, path and filename are both display forms so the lengths line up
+const PARENT_SQL: &str = "substr(path, 1, length(path) - length(filename) - 1)";
+
+pub async fn get_database_stats(connection: &Connection) -> SQLResult<DatabaseStats> {
+    let mut rows = connection
+        .query(
+            &format!("SELECT COUNT(*), COUNT(DISTINCT {}) FROM files", PARENT_SQL),
+            (),
+        )
+        .await?;
+    let (files, directories) = match rows.next().await? {
+        Some(row) => (row.get::<i64>(0)?, row.get::<i64>(1)?),
+        None => (0, 0),
+    };
+
+    let mut roots = Vec::new();
+    let mut rows = connection
+        .query(
+            &format!(
+                "SELECT CASE WHEN instr(substr(path, 2), '/') = 0 THEN '/'
+                    ELSE substr(path, 1, instr(substr(path, 2), '/')) END AS root,
+                 COUNT(*), COUNT(DISTINCT {}), COALESCE(SUM(size), 0)
+                 FROM files GROUP BY root ORDER BY root",
+                PARENT_SQL
+            ),
+            (),
+        )
+        .await?;
+    while let Some(row) = rows.next().await? {
+        roots.push(RootStats {
+            root: row.get(0)?,
+            files: row.get(1)?,
+            directories: row.get(2)?,
+            bytes: row.get(3)?,
+        });
+    }
+
+    // dbstat gives the space actually used by each table, including the FTS shadow tables
+    let mut rows = connection
+        .query(
+            "SELECT (SELECT page_count FROM pragma_page_count()) * (SELECT page_size FROM pragma_page_size()),
+                    (SELECT COALESCE(SUM(pgsize), 0) FROM dbstat WHERE name LIKE 'files_fts%')",
+            (),
+        )
+        .await?;
+    let (database_bytes, fts_bytes) = match rows.next().await? {
+        Some(row) => (row.get::<i64>(0)?, row.get::<i64>(1)?),
+        None => (0, 0),
+    };
+
+    Ok(DatabaseStats {
+        files,
+        directories,
+        roots,
+        database_bytes,
+        fts_bytes,
+        last_run: get_last_completed_run(connection).await?,
+        top_extensions_by_count: get_top_extensions(connection, "COUNT(*)").await?,
+        top_extensions_by_bytes: get_top_extensions(connection, "SUM(size)").await?,
+    })
+}
+
+async fn get_top_extensions(
+    connection: &Connection,
+    order: &str,
+) -> SQLResult<Vec<ExtensionStats>> {
+    let mut rows = connection
+        .query(
+            &format!(
+                "SELECT COALESCE(extension, ''), COUNT(*), COALESCE(SUM(size), 0) FROM files
+                 GROUP BY extension ORDER BY {} DESC LIMIT {}",
+                order, TOP_EXTENSIONS
+            ),
+            (),
+        )
+        .await?;
+
+    let mut extensions = Vec::new();
+    while let Some(row) = rows.next().await? {
+        extensions.push(ExtensionStats {
+            extension: row.get(0)?,
+            files: row.get(1)?,
+            bytes: row.get(2)?,
+        });
+    }
+    Ok(extensions)
 }
 
 pub async fn get_database_count(connection: &Connection) -> SQLResult<usize> {
@@ -74,9 +549,307 @@ pub async fn get_database_count(connection: &Connection) -> SQLResult<usize> {
     Ok(count)
 }
 
-async fn debug_database_state(conn: &Connection) {
-    // Print the number of rows in the 'files' table
-    // (You can add debug logic here if needed)
+// an upsert rather than INSERT OR REPLACE
+// REPLACE deletes the old row without firing the delete trigger, which would leave files_fts stale
+const UPSERT_FILES_CLAUSE: &str = " ON CONFLICT(path) DO UPDATE SET
+    path_bytes = excluded.path_bytes,
+    filename = excluded.filename,
+    extension = excluded.extension,
+    size = excluded.size,
+    modified_at = excluded.modified_at,
+    created_at = excluded.created_at,
+    accessed_at = excluded.accessed_at,
+    indexed_at = excluded.indexed_at,
+    device = excluded.device,
+    inode = excluded.inode,
+    link_target = excluded.link_target,
+    uid = excluded.uid,
+    gid = excluded.gid,
+    mode = excluded.mode,
+    mime = excluded.mime,
+    xattrs = excluded.xattrs";
+
+// how many rows each execution of the bulk insert statement writes
+// single row statements are dominated by per call overhead, 100 rows is 1700 parameters,
+// well under sqlite's limit of 32766
+const ROWS_PER_STATEMENT: usize = 100;
+const COLUMNS_PER_ROW: usize = 17;
+
+// rows imported from a locate database must not overwrite ones the walker already filled in
+const KEEP_EXISTING_FILES_CLAUSE: &str = " ON CONFLICT(path) DO NOTHING";
+
+// an archive only has sizes and times, everything else the walker stored is kept. indexed_at
+// especially, delete_unseen_files and the changes triggers go by it, so a file the archive has
+// a different size or time for is logged as modified, the same as a re-index would
+const IMPORT_FILES_CLAUSE: &str = " ON CONFLICT(path) DO UPDATE SET
+    size = excluded.size,
+    modified_at = excluded.modified_at,
+    created_at = excluded.created_at,
+    accessed_at = excluded.accessed_at";
+
+fn upsert_files_query(rows: usize, conflict_clause: &str) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let placeholders: Vec<String> = (1..=COLUMNS_PER_ROW)
+                .map(|column| format!("?{}", row * COLUMNS_PER_ROW + column))
+                .collect();
+            format!("({})", placeholders.join(", "))
+        })
+        .collect();
+    format!(
+        "INSERT INTO files (path, path_bytes, filename, extension, size, modified_at, \
+         created_at, accessed_at, indexed_at, device, inode, link_target, uid, gid, mode, \
+         mime, xattrs) VALUES {}{}",
+        values.join(", "),
+        conflict_clause
+    )
+}
+
+// one row of the files table, as read from the filesystem
+#[derive(Debug, Clone)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    pub filename: OsString,
+    pub extension: String,
+    pub size: i64,
+    pub modified_at: i64,
+    pub created_at: Option<i64>,
+    pub accessed_at: Option<i64>,
+    // when the metadata was read, None for files only known from an import
+    pub indexed_at: Option<i64>,
+    // identify the file across renames, None where the platform doesn't have them
+    pub device: Option<i64>,
+    pub inode: Option<i64>,
+    // for a symlink, the absolute path it points to, see link_target
+    pub link_target: Option<PathBuf>,
+    // owner, group and mode, None where the platform doesn't have them
+    pub permissions: Option<Permissions>,
+    // filled in by content.rs when detect_mime and index_xattrs are on
+    pub mime: Option<String>,
+    pub xattrs: BTreeMap<String, String>,
+}
+
+impl FileRecord {
+    pub fn new(path: PathBuf, metadata: &Metadata) -> Self {
+        let filename = path.file_name().unwrap_or_default().to_os_string();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // convert the modified time to seconds since UNIX epoch
+        // files without one (or from before 1970) get 0
+        let modified_at = unix_seconds(metadata.modified()).unwrap_or(0);
+        let (device, inode) = file_id(metadata);
+        let link_target = if metadata.file_type().is_symlink() {
+            link_target(&path)
+        } else {
+            None
+        };
+
+        Self {
+            path,
+            filename,
+            extension,
+            size: metadata.len() as i64,
+            modified_at,
+            // std reads the birth time with statx on Linux, filesystems that don't keep one give None
+            created_at: unix_seconds(metadata.created()),
+            accessed_at: unix_seconds(metadata.accessed()),
+            indexed_at: Some(unix_now()),
+            device,
+            inode,
+            link_target,
+            permissions: Permissions::from_metadata(metadata),
+            mime: None,
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    // a file only known by its path, e.g. from a locate database
+    // size and times stay empty until the walker gets to it
+    pub fn from_path(path: PathBuf) -> Self {
+        let filename = path.file_name().unwrap_or_default().to_os_string();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self {
+            path,
+            filename,
+            extension,
+            size: 0,
+            modified_at: 0,
+            created_at: None,
+            accessed_at: None,
+            indexed_at: None,
+            device: None,
+            inode: None,
+            link_target: None,
+            permissions: None,
+            mime: None,
+            xattrs: BTreeMap::new(),
+        }
+    }
+}
+
+// relative targets are resolved against the link's directory, without following anything further,
+// so a link still has its target when that doesn't exist
+fn link_target(path: &Path) -> Option<PathBuf> {
+    let target = std::fs::read_link(path).ok()?;
+    let target = path.parent().unwrap_or(Path::new("/")).join(target);
+    let mut resolved = PathBuf::new();
+    for component in target.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    Some(resolved)
+}
+
+// sqlite only has signed integers, so these are stored as their bit patterns
+#[cfg(unix)]
+pub(crate) fn file_id(metadata: &Metadata) -> (Option<i64>, Option<i64>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(metadata.dev() as i64), Some(metadata.ino() as i64))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_id(_metadata: &Metadata) -> (Option<i64>, Option<i64>) {
+    (None, None)
+}
+
+fn unix_seconds(time: std::io::Result<std::time::SystemTime>) -> Option<i64> {
+    let since_epoch = time
+        .ok()?
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .ok()?;
+    Some(since_epoch.as_secs() as i64)
+}
+
+#[derive(Debug)]
+pub struct RowError {
+    pub path: PathBuf,
+    pub error: libsql::Error,
+}
+
+// Writes files through prepared upserts that are reused for every batch
+//
+// binding the values means nothing needs escaping. rows go in ROWS_PER_STATEMENT at a time,
+// if a chunk fails sqlite undoes just that statement and its rows are retried one by one,
+// so a bad row is reported on its own instead of taking the rest of its batch down with it.
+// the caller owns the transaction, see commit_batch
+pub struct BulkWriter {
+    chunk_statement: Statement,
+    row_statement: Statement,
+    // rows inserted or updated so far, rows left alone by keeping_existing aren't counted
+    changes: u64,
+}
+
+impl BulkWriter {
+    pub async fn new(conn: &Connection) -> SQLResult<Self> {
+        Self::with_conflict_clause(conn, UPSERT_FILES_CLAUSE).await
+    }
+
+    // inserts new paths but leaves rows that are already there alone
+    pub async fn keeping_existing(conn: &Connection) -> SQLResult<Self> {
+        Self::with_conflict_clause(conn, KEEP_EXISTING_FILES_CLAUSE).await
+    }
+
+    // inserts new paths and takes only the sizes and times of rows that are already there
+    pub async fn importing(conn: &Connection) -> SQLResult<Self> {
+        Self::with_conflict_clause(conn, IMPORT_FILES_CLAUSE).await
+    }
+
+    async fn with_conflict_clause(conn: &Connection, conflict_clause: &str) -> SQLResult<Self> {
+        Ok(Self {
+            chunk_statement: conn
+                .prepare(&upsert_files_query(ROWS_PER_STATEMENT, conflict_clause))
+                .await?,
+            row_statement: conn
+                .prepare(&upsert_files_query(1, conflict_clause))
+                .await?,
+            changes: 0,
+        })
+    }
+
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    // returns the rows that could not be written, everything else was
+    pub async fn write(&mut self, records: &[FileRecord]) -> Vec<RowError> {
+        let mut errors = Vec::new();
+        for chunk in records.chunks(ROWS_PER_STATEMENT) {
+            if chunk.len() == ROWS_PER_STATEMENT {
+                let params: Vec<Value> = chunk.iter().flat_map(record_params).collect();
+                let result = self.chunk_statement.execute(params).await;
+                self.chunk_statement.reset();
+                if let Ok(changes) = result {
+                    self.changes += changes as u64;
+                    continue;
+                }
+            }
+
+            // a short final chunk, or one with a row sqlite rejected
+            for record in chunk {
+                let result = self
+                    .row_statement
+                    .execute(record_params(record).to_vec())
+                    .await;
+                self.row_statement.reset();
+
+                match result {
+                    Ok(changes) => self.changes += changes as u64,
+                    Err(error) => errors.push(RowError {
+                        path: record.path.clone(),
+                        error,
+                    }),
+                }
+            }
+        }
+        errors
+    }
+}
+
+// path is the display form and path_bytes the raw path, only set when the two differ
+// see paths.rs
+fn record_params(record: &FileRecord) -> [Value; COLUMNS_PER_ROW] {
+    [
+        Value::Text(display_path(record.path.as_os_str())),
+        raw_path_bytes(record.path.as_os_str()).map_or(Value::Null, Value::Blob),
+        Value::Text(display_path(&record.filename)),
+        Value::Text(record.extension.clone()),
+        Value::Integer(record.size),
+        Value::Integer(record.modified_at),
+        record.created_at.map_or(Value::Null, Value::Integer),
+        record.accessed_at.map_or(Value::Null, Value::Integer),
+        record.indexed_at.map_or(Value::Null, Value::Integer),
+        record.device.map_or(Value::Null, Value::Integer),
+        record.inode.map_or(Value::Null, Value::Integer),
+        record.link_target.as_ref().map_or(Value::Null, |target| {
+            Value::Text(display_path(target.as_os_str()))
+        }),
+        record.permissions.map_or(Value::Null, |permissions| {
+            Value::Integer(permissions.uid.into())
+        }),
+        record.permissions.map_or(Value::Null, |permissions| {
+            Value::Integer(permissions.gid.into())
+        }),
+        record.permissions.map_or(Value::Null, |permissions| {
+            Value::Integer(permissions.mode.into())
+        }),
+        record.mime.clone().map_or(Value::Null, Value::Text),
+        if record.xattrs.is_empty() {
+            Value::Null
+        } else {
+            serde_json::to_string(&record.xattrs).map_or(Value::Null, Value::Text)
+        },
+    ]
 }
 
 //
@@ -85,118 +858,100 @@ async fn debug_database_state(conn: &Connection) {
 //
 // / = +
 // so home/theo = home + theo
-pub async fn insert_files_to_db(
-    search_result: WalkDirGeneric<(usize, bool)>,
+#[instrument(name = "index", skip_all, fields(run_id))]
+pub async fn insert_files_to_db<C: ClientState>(
+    search_result: impl IntoIterator<Item = jwalk::Result<DirEntry<C>>>,
     conn_thread: Arc<Mutex<Connection>>,
+    run_id: i64,
+    progress: Arc<IndexProgress>,
+    content: ContentOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let now = std::time::Instant::now();
-    println!("Inserting files into database...");
-    println!("This may take a while depending on the number of files.");
-
-    // Lock the connection when starting the transaction
-    {
-        let conn = conn_thread.lock().unwrap();
-
-        conn.execute_batch(
-            "PRAGMA journal_mode = OFF;
-        PRAGMA synchronous = OFF;
-        PRAGMA journal_size_limit = 1000000;
-        PRAGMA cache_size = 100000;
-        PRAGMA temp_store = memory;
-        PRAGMA locking_mode = EXCLUSIVE;
-        PRAGMA mmap_size = 268435456;
-        PRAGMA optimize;",
-        )
-        .await?;
-
-        conn.execute("BEGIN TRANSACTION;", params![]).await?;
-    }
+    let mut writer = {
+        let conn = conn_thread.lock().await;
+        BulkWriter::new(&conn).await?
+    };
 
-    let mut count = 0;
-    let mut batch_count = 0;
+    let mut directories_scanned = 0;
+    let mut files_queued = 0;
     let batch_size = 500;
-    let mut new_query = String::with_capacity(batch_size * 200); // Pre-allocate ~200 chars per record
-    new_query.push_str(
-        "INSERT OR REPLACE INTO files (path, filename, extension, size, modified_at) VALUES ",
-    );
-    let mut first = true;
+    let mut batch: Vec<FileRecord> = Vec::with_capacity(batch_size);
+
+    // jwalk yields entries depth first, so a directory is finished as soon as an entry
+    // at the same depth or shallower comes along. the stack holds the directories still being walked,
+    // finished ones wait in `completed` and are checkpointed with the batch holding their last file
+    let mut open_directories: Vec<(usize, String)> = Vec::new();
+    let mut completed: Vec<String> = Vec::new();
+    // what couldn't be read isn't gone, see delete_unseen_files
+    let mut unreadable: Vec<PathBuf> = Vec::new();
+    // where each walk started, imported rows under them the walk didn't read are gone too
+    let mut roots: Vec<PathBuf> = Vec::new();
+    let mut directories: Vec<(PathBuf, Permissions)> = Vec::new();
 
     // for every file in the search result
     // insert it into the database in batches of X
-    for entry in search_result {
-        if let Ok(dir_entry) = entry {
-            if let Ok(metadata) = dir_entry.metadata() {
-                if metadata.is_file() {
-                    // Get the path, filename, extension, size, and modified_at
-                    let path_str = dir_entry.path().display().to_string();
-                    let filename = dir_entry.file_name().to_string_lossy().to_string();
-                    let extension = dir_entry
-                        .path()
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("")
-                        .to_string();
-                    let size = metadata.len() as i64;
-
-                    // convert the modified time to seconds since UNIX epoch
-                    // using the modified time as a fallback if not available
-                    let modified_at = metadata
-                        .modified()
-                        .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
-                        .duration_since(std::time::SystemTime::UNIX_EPOCH) // Change this line
-                        .unwrap_or_default()
-                        .as_secs() as i64;
-
-                    // Escape the single quotes in path and filename as sqlite requires it
-                    // this isn't done automatically as using compound parameters
-                    let formatted = format!(
-                        "('{}', '{}', '{}', {}, {})",
-                        path_str.replace("'", "''"),
-                        filename.replace("'", "''"),
-                        extension.replace("'", "''"),
-                        size,
-                        modified_at
-                    );
+    for dir_entry in search_result {
+        let dir_entry = match dir_entry {
+            Ok(dir_entry) => dir_entry,
+            Err(e) => {
+                unreadable.extend(e.path().map(|p| p.to_path_buf()));
+                progress.error(e.path().map(|p| p.to_path_buf()), e.to_string());
+                continue;
+            }
+        };
 
-                    // append the values to the query
-                    // query should be like:
-                    // INSERT INTO files (path, filename, extension, size, modified_at) VALUES (v1), (v2), (v3), ...
-                    // where v1, v2, v3 are the values for each file
-                    if first {
-                        new_query.push_str(&formatted);
-                        first = false;
-                    } else {
-                        new_query.push_str(", ");
-                        new_query.push_str(&formatted);
-                    }
+        if dir_entry.depth == 0 {
+            roots.push(dir_entry.path());
+        }
+        while open_directories
+            .last()
+            .is_some_and(|(depth, _)| *depth >= dir_entry.depth)
+        {
+            completed.push(open_directories.pop().unwrap().1);
+        }
+        // who can get into it, for searching as another user. followed symlinks count as the
+        // directory they lead to
+        if dir_entry.read_children_path.is_some() {
+            if let Some(permissions) = std::fs::metadata(dir_entry.path())
+                .ok()
+                .and_then(|metadata| Permissions::from_metadata(&metadata))
+            {
+                directories.push((dir_entry.path(), permissions));
+            }
+        }
+        if dir_entry.file_type().is_dir() {
+            directories_scanned += 1;
+            open_directories.push((dir_entry.depth, display_path(dir_entry.path().as_os_str())));
+            continue;
+        }
 
-                    count += 1;
-                    batch_count += 1;
-
-                    // insert files in batches of X size
-                    // very small batches are slow due to overhead of executing many small queries
-                    // large batches don't seem to always work due to memory limits
-                    if batch_count == batch_size {
-                        // aquire lock again to execute the query
-                        // this is to avoid holding the lock for too long
-                        let conn = conn_thread.lock().unwrap();
-                        // println!("Inserting batch of {} files into database...", batch_count);
-                        let _res = conn.execute_batch(&new_query).await;
-
-                        if let Err(e) = _res {
-                            eprintln!("Error inserting batch: {}", e);
-                            println!("Query: {}", new_query);
-                        };
-
-                        // Reset for next batch
-                        new_query.clear();
-
-                        new_query.push_str(
-                            "INSERT OR REPLACE INTO files (path, filename, extension, size, modified_at) VALUES ",
-                        );
-                        first = true;
-                        batch_count = 0;
-                    }
+        // a symlink is indexed as itself, along with where it points, whether or not it was followed
+        if let Ok(metadata) = dir_entry.metadata() {
+            if metadata.is_file() || metadata.file_type().is_symlink() {
+                batch.push(FileRecord::new(dir_entry.path(), &metadata));
+                files_queued += 1;
+
+                // insert files in batches of X size
+                // one transaction per batch keeps the commit overhead down,
+                // and the checkpoint goes in with it so a crash loses at most one batch
+                if batch.len() == batch_size {
+                    progress.queued(directories_scanned, files_queued);
+                    read_content(&mut batch, content);
+                    // aquire lock again to execute the query
+                    // this is to avoid holding the lock for too long
+                    let conn = conn_thread.lock().await;
+                    commit_batch(
+                        &conn,
+                        &mut writer,
+                        &batch,
+                        &directories,
+                        &completed,
+                        run_id,
+                        &progress,
+                    )
+                    .await?;
+                    completed.clear();
+                    directories.clear();
+                    batch.clear();
                 }
             }
         }
@@ -204,74 +959,928 @@ pub async fn insert_files_to_db(
 
     {
         // Lock the connection again to finalize the insertion
-        let mut conn = conn_thread.lock().unwrap();
+        let conn = conn_thread.lock().await;
 
         // Insert any remaining files in the last batch
-        if batch_count > 0 && !first {
-            println!(
-                "Inserting final batch of {} files into database...",
-                batch_count
-            );
-            let _res = conn.execute_batch(&new_query).await;
-        }
-
-        let elapsed = now.elapsed();
-        println!(
-            "Inserted {} files into database in: {:.10?}",
-            count, elapsed
-        );
+        if !batch.is_empty() || !directories.is_empty() {
+            progress.queued(directories_scanned, files_queued);
+            read_content(&mut batch, content);
+            commit_batch(
+                &conn,
+                &mut writer,
+                &batch,
+                &directories,
+                &[],
+                run_id,
+                &progress,
+            )
+            .await?;
+        }
+
+        let deleted = delete_unseen_files(&conn, run_id, &roots, &unreadable).await?;
+        debug!(deleted, "removed files that are no longer there");
+        prune_changes(&conn).await?;
+
+        // files_fts is kept up to date by the triggers, no rebuild needed
+        finish_index_run(&conn, run_id, IndexRunStatus::Completed).await?;
+    }
+    progress.finished(IndexRunStatus::Completed);
+
+    Ok(())
+}
+
+// write one batch of files along with the directories finished since the last batch
+// both go in the same transaction, so a checkpointed directory always has all of its files committed
+// rows that fail are reported to `progress` and skipped
+async fn commit_batch(
+    conn: &Connection,
+    writer: &mut BulkWriter,
+    batch: &[FileRecord],
+    directories: &[(PathBuf, Permissions)],
+    completed: &[String],
+    run_id: i64,
+    progress: &IndexProgress,
+) -> SQLResult<()> {
+    let now = std::time::Instant::now();
+    conn.execute("BEGIN", ()).await?;
+
+    // files that moved since they were indexed keep their rows
+    if let Err(e) = move_renamed_files(conn, batch).await {
+        let _ = conn.execute("ROLLBACK", ()).await;
+        return Err(e);
+    }
+    let errors = writer.write(batch).await;
+    let written = batch.len() - errors.len();
+
+    let result = async {
+        write_directories(conn, directories).await?;
+        for directory in completed {
+            conn.execute(
+                "INSERT OR IGNORE INTO index_checkpoints (run_id, directory) VALUES (?1, ?2)",
+                params![run_id, directory.as_str()],
+            )
+            .await?;
+        }
+        conn.execute(
+            "UPDATE index_runs SET files_indexed = files_indexed + ?1 WHERE id = ?2",
+            params![written as i64, run_id],
+        )
+        .await?;
+        conn.execute("COMMIT", ()).await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = conn.execute("ROLLBACK", ()).await;
+        return result;
+    }
+
+    debug!(
+        files = written,
+        failed = errors.len(),
+        checkpoints = completed.len(),
+        elapsed = ?now.elapsed(),
+        "committed batch"
+    );
+
+    // only reported once committed, a rolled back batch is written again on resume
+    for row_error in errors {
+        progress.error(Some(row_error.path), row_error.error.to_string());
+    }
+    progress.committed(written);
+    Ok(())
+}
+
+// files this run's walk didn't read have been deleted (or are skipped now), except the ones under
+// something the walk couldn't read. a resumed run only knows what it couldn't read since resuming
+// rows that only came from an import (indexed_at is NULL) are only gone if they're under one of
+// the `roots` that was walked, the walk never gets to the others
+async fn delete_unseen_files(
+    conn: &Connection,
+    run_id: i64,
+    roots: &[PathBuf],
+    unreadable: &[PathBuf],
+) -> SQLResult<u64> {
+    let display = |paths: &[PathBuf]| -> Vec<PathBuf> {
+        paths
+            .iter()
+            .map(|path| PathBuf::from(display_path(path.as_os_str())))
+            .collect()
+    };
+    let (roots, unreadable) = (display(roots), display(unreadable));
+
+    let mut rows = conn
+        .query(
+            "SELECT id, path, indexed_at IS NULL FROM files
+             WHERE indexed_at < (SELECT started_at FROM index_runs WHERE id = ?1)
+                OR indexed_at IS NULL",
+            params![run_id],
+        )
+        .await?;
+    let mut unseen = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let path: String = row.get(1)?;
+        let path = Path::new(&path);
+        let imported = row.get::<bool>(2)?;
+        if imported && !roots.iter().any(|root| path.starts_with(root)) {
+            continue;
+        }
+        if !unreadable.iter().any(|dir| path.starts_with(dir)) {
+            unseen.push(Value::Integer(row.get(0)?));
+        }
+    }
+
+    let mut deleted = 0;
+    conn.execute("BEGIN", ()).await?;
+    let result = async {
+        for chunk in unseen.chunks(ROWS_PER_STATEMENT) {
+            let placeholders: Vec<String> = (1..=chunk.len()).map(|i| format!("?{}", i)).collect();
+            deleted += conn
+                .execute(
+                    &format!(
+                        "DELETE FROM files WHERE id IN ({})",
+                        placeholders.join(", ")
+                    ),
+                    chunk.to_vec(),
+                )
+                .await?;
+        }
+        conn.execute("COMMIT", ()).await?;
+        Ok(deleted)
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = conn.execute("ROLLBACK", ()).await;
+    }
+    result
+}
+
+// only the most recent changes are kept
+const CHANGES_LIMIT: i64 = 100_000;
+
+pub async fn prune_changes(conn: &Connection) -> SQLResult<()> {
+    conn.execute(
+        "DELETE FROM changes WHERE id <= (SELECT MAX(id) FROM changes) - ?1",
+        params![CHANGES_LIMIT],
+    )
+    .await?;
+    Ok(())
+}
+
+// a single file read again by the watcher, outside of any index run
+pub async fn write_file(conn: &Connection, record: &FileRecord) -> SQLResult<()> {
+    conn.execute(
+        &upsert_files_query(1, UPSERT_FILES_CLAUSE),
+        record_params(record).to_vec(),
+    )
+    .await?;
+    Ok(())
+}
+
+// removes a path and everything under it, for when the watcher sees it go
+// the changes triggers log each file as deleted
+pub async fn delete_files_under(conn: &Connection, path: &Path) -> SQLResult<u64> {
+    let path = display_path(path.as_os_str());
+    conn.execute(
+        "DELETE FROM directories WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+        params![path.as_str(), format!("{}/", path.trim_end_matches('/'))],
+    )
+    .await?;
+    conn.execute(
+        "DELETE FROM files WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+        params![path.as_str(), format!("{}/", path.trim_end_matches('/'))],
+    )
+    .await
+}
 
-        conn.execute("COMMIT;", params![]).await?;
+// the owner, group and mode of directories, replacing what was there. no transaction of its own
+pub async fn write_directories(
+    conn: &Connection,
+    directories: &[(PathBuf, Permissions)],
+) -> SQLResult<()> {
+    for (path, permissions) in directories {
         conn.execute(
-            "INSERT INTO files_fts(files_fts) VALUES('rebuild');",
-            params![],
+            "INSERT OR REPLACE INTO directories (path, uid, gid, mode) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                display_path(path.as_os_str()),
+                permissions.uid,
+                permissions.gid,
+                permissions.mode
+            ],
         )
         .await?;
     }
-    println!("Database insertion completed successfully.");
+    Ok(())
+}
+
+// moves a file, or a directory and everything under it, to a new path in one statement
+// the rows keep their ids, so each is logged as renamed and frecency follows it, see migration 9.
+// whatever was indexed at the new path has been replaced, so it's deleted first
+// returns how many rows moved
+pub async fn rename_path(conn: &Connection, from: &Path, to: &Path) -> SQLResult<u64> {
+    if from == to {
+        return Ok(0);
+    }
+    let from_display = display_path(from.as_os_str());
+    let to_display = display_path(to.as_os_str());
+    let under = |display: &str| format!("{}/", display.trim_end_matches('/'));
 
+    conn.execute(
+        "DELETE FROM files WHERE (path = ?1 OR substr(path, 1, length(?2)) = ?2)
+         AND EXISTS (SELECT 1 FROM files WHERE path = ?3 OR substr(path, 1, length(?4)) = ?4)",
+        params![
+            to_display.as_str(),
+            under(&to_display),
+            from_display.as_str(),
+            under(&from_display)
+        ],
+    )
+    .await?;
+
+    conn.execute(
+        "UPDATE OR REPLACE directories SET path = ?1 || substr(path, length(?2) + 1)
+         WHERE path = ?2 OR substr(path, 1, length(?3)) = ?3",
+        params![
+            to_display.as_str(),
+            from_display.as_str(),
+            under(&from_display)
+        ],
+    )
+    .await?;
+
+    // path_bytes is rebuilt from the raw bytes, it's only needed if the new path isn't UTF-8 or the
+    // rest of the old one wasn't. a row that ends up with bytes it doesn't need still reads back fine
+    let record = FileRecord::from_path(to.to_path_buf());
+    conn.execute(
+        "UPDATE files SET
+            path = ?1 || substr(path, length(?2) + 1),
+            path_bytes = CASE WHEN path_bytes IS NULL AND ?3 IS NULL THEN NULL
+                ELSE CAST(COALESCE(?3, CAST(?1 AS BLOB))
+                    || substr(COALESCE(path_bytes, CAST(path AS BLOB)), ?4 + 1) AS BLOB) END,
+            filename = CASE WHEN path = ?2 THEN ?5 ELSE filename END,
+            extension = CASE WHEN path = ?2 THEN ?6 ELSE extension END
+         WHERE path = ?2 OR substr(path, 1, length(?7)) = ?7",
+        params![
+            to_display.as_str(),
+            from_display.as_str(),
+            raw_path_bytes(to.as_os_str()).map_or(Value::Null, Value::Blob),
+            os_str_bytes(from.as_os_str()).len() as i64,
+            display_path(&record.filename),
+            record.extension,
+            under(&from_display)
+        ],
+    )
+    .await
+}
+
+// files found at a path that isn't indexed yet, but whose device and inode are indexed at another
+// path, have been moved there. their rows are moved rather than a new one added and the old one
+// deleted, so history and frecency carry over. inode numbers are reused once a file is deleted,
+// so the old path has to be gone and the modified time has to match. returns how many moved
+pub async fn move_renamed_files(conn: &Connection, records: &[FileRecord]) -> SQLResult<u64> {
+    let mut by_id = HashMap::new();
+    let mut params = Vec::new();
+    let mut values = Vec::new();
+    for record in records {
+        if let (Some(device), Some(inode)) = (record.device, record.inode) {
+            by_id.insert((device, inode), record);
+            params.push(Value::Integer(device));
+            params.push(Value::Integer(inode));
+            values.push(format!("(?{}, ?{})", params.len() - 1, params.len()));
+        }
+    }
+    if values.is_empty() {
+        return Ok(0);
+    }
+
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT path, path_bytes, device, inode, CAST(modified_at AS INTEGER) FROM files
+                 WHERE (device, inode) IN (VALUES {})",
+                values.join(", ")
+            ),
+            params,
+        )
+        .await?;
+    let mut moves = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let Some(record) = by_id.get(&(row.get(2)?, row.get(3)?)) else {
+            continue;
+        };
+        let old_path = match row.get::<Option<Vec<u8>>>(1)? {
+            Some(bytes) => path_from_bytes(&bytes),
+            None => PathBuf::from(row.get::<String>(0)?),
+        };
+        // a hard link is the same inode at two paths that both exist
+        if old_path != record.path
+            && row.get::<i64>(4)? == record.modified_at
+            && std::fs::symlink_metadata(&old_path).is_err()
+        {
+            moves.push((old_path, record.path.clone()));
+        }
+    }
+
+    let mut moved = 0;
+    for (from, to) in moves {
+        debug!(from = %from.display(), to = %to.display(), "file moved");
+        moved += rename_path(conn, &from, &to).await?;
+    }
+    Ok(moved)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexRunStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl IndexRunStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IndexRunStatus::Running => "running",
+            IndexRunStatus::Completed => "completed",
+            IndexRunStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexRun {
+    pub id: i64,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub status: String,
+    pub files_indexed: i64,
+}
+
+impl IndexRun {
+    fn from_row(row: &libsql::Row) -> SQLResult<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            finished_at: row.get(2)?,
+            status: row.get(3)?,
+            files_indexed: row.get(4)?,
+        })
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+pub async fn start_index_run(conn: &Connection) -> SQLResult<i64> {
+    conn.execute(
+        "INSERT INTO index_runs (started_at, status, files_indexed) VALUES (?1, ?2, 0)",
+        params![unix_now(), IndexRunStatus::Running.as_str()],
+    )
+    .await?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub async fn finish_index_run(
+    conn: &Connection,
+    run_id: i64,
+    status: IndexRunStatus,
+) -> SQLResult<()> {
+    conn.execute(
+        "UPDATE index_runs SET finished_at = ?1, status = ?2 WHERE id = ?3",
+        params![unix_now(), status.as_str(), run_id],
+    )
+    .await?;
+    // the checkpoints are only needed to resume this run
+    conn.execute(
+        "DELETE FROM index_checkpoints WHERE run_id = ?1",
+        params![run_id],
+    )
+    .await?;
     Ok(())
 }
 
-pub fn run_search() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
-    println!("Searching for files in home directory... ");
-    let now = std::time::Instant::now();
-    let walk_dir = WalkDirGeneric::<(usize, bool)>::new("/".to_string())
-        .process_read_dir(|_depth, _path, _read_dir_state, children| {
-            // 3. Custom skip
-            children.iter_mut().for_each(|dir_entry_result| {
-                if let Ok(dir_entry) = dir_entry_result {
-                    if dir_entry.file_name() == "AppData" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "node_modules" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "target" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "vendor" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "build" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == ".cache" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.depth == 10 {
-                        dir_entry.read_children_path = None;
+// a run that is still marked as running was killed before it could finish
+pub async fn get_interrupted_run(conn: &Connection) -> SQLResult<Option<IndexRun>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, started_at, finished_at, status, files_indexed FROM index_runs
+             WHERE status = ?1 ORDER BY id DESC LIMIT 1",
+            params![IndexRunStatus::Running.as_str()],
+        )
+        .await?;
+
+    match rows.next().await? {
+        Some(row) => Ok(Some(IndexRun::from_row(&row)?)),
+        None => Ok(None),
+    }
+}
+
+// the size of the last finished index, a good guess for how big the next one will be
+pub async fn get_last_completed_run(conn: &Connection) -> SQLResult<Option<IndexRun>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, started_at, finished_at, status, files_indexed FROM index_runs
+             WHERE status = ?1 ORDER BY id DESC LIMIT 1",
+            params![IndexRunStatus::Completed.as_str()],
+        )
+        .await?;
+
+    match rows.next().await? {
+        Some(row) => Ok(Some(IndexRun::from_row(&row)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn get_completed_directories(
+    conn: &Connection,
+    run_id: i64,
+) -> SQLResult<HashSet<PathBuf>> {
+    let mut rows = conn
+        .query(
+            "SELECT directory FROM index_checkpoints WHERE run_id = ?1",
+            params![run_id],
+        )
+        .await?;
+
+    // checkpoints are display paths, so directories that aren't valid UTF-8 come back exactly
+    let mut directories = HashSet::new();
+    while let Some(row) = rows.next().await? {
+        directories.insert(path_from_display(&row.get::<String>(0)?));
+    }
+    Ok(directories)
+}
+
+// only the most recent queries are kept
+const HISTORY_LIMIT: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryRecord {
+    pub id: i64,
+    pub query: String,
+    pub ran_at: i64,
+    pub result_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    pub created_at: i64,
+}
+
+pub async fn record_query(conn: &Connection, query: &str, result_count: usize) -> SQLResult<()> {
+    conn.execute(
+        "INSERT INTO queries (query, ran_at, result_count) VALUES (?1, ?2, ?3)",
+        params![query, unix_now(), result_count as i64],
+    )
+    .await?;
+    conn.execute(
+        "DELETE FROM queries WHERE id <= (SELECT MAX(id) FROM queries) - ?1",
+        params![HISTORY_LIMIT],
+    )
+    .await?;
+    Ok(())
+}
+
+// newest first
+pub async fn get_query_history(conn: &Connection, limit: usize) -> SQLResult<Vec<QueryRecord>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, query, ran_at, result_count FROM queries ORDER BY id DESC LIMIT ?1",
+            params![limit as i64],
+        )
+        .await?;
+
+    let mut history = Vec::new();
+    while let Some(row) = rows.next().await? {
+        history.push(QueryRecord {
+            id: row.get(0)?,
+            query: row.get(1)?,
+            ran_at: row.get(2)?,
+            result_count: row.get(3)?,
+        });
+    }
+    Ok(history)
+}
+
+pub async fn get_query(conn: &Connection, id: i64) -> SQLResult<Option<String>> {
+    let mut rows = conn
+        .query("SELECT query FROM queries WHERE id = ?1", params![id])
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+// saving under an existing name replaces it
+pub async fn save_search(conn: &Connection, name: &str, query: &str) -> SQLResult<()> {
+    conn.execute(
+        "INSERT INTO saved_searches (name, query, created_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET query = excluded.query, created_at = excluded.created_at",
+        params![name, query, unix_now()],
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_saved_search(conn: &Connection, name: &str) -> SQLResult<Option<String>> {
+    let mut rows = conn
+        .query(
+            "SELECT query FROM saved_searches WHERE name = ?1",
+            params![name],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn get_saved_searches(conn: &Connection) -> SQLResult<Vec<SavedSearch>> {
+    let mut rows = conn
+        .query(
+            "SELECT name, query, created_at FROM saved_searches ORDER BY name",
+            (),
+        )
+        .await?;
+
+    let mut searches = Vec::new();
+    while let Some(row) = rows.next().await? {
+        searches.push(SavedSearch {
+            name: row.get(0)?,
+            query: row.get(1)?,
+            created_at: row.get(2)?,
+        });
+    }
+    Ok(searches)
+}
+
+// returns whether there was a search with that name
+pub async fn delete_saved_search(conn: &Connection, name: &str) -> SQLResult<bool> {
+    let deleted = conn
+        .execute("DELETE FROM saved_searches WHERE name = ?1", params![name])
+        .await?;
+    Ok(deleted > 0)
+}
+
+// remembers that a result was opened, search ranks it higher the more often and recently that happens
+// keyed on the same display path as files.path so it survives the file being re-indexed
+pub async fn record_selection(conn: &Connection, path: &Path) -> SQLResult<()> {
+    conn.execute(
+        "INSERT INTO selections (path, count, last_selected_at) VALUES (?1, 1, ?2)
+         ON CONFLICT(path) DO UPDATE SET count = count + 1, last_selected_at = excluded.last_selected_at",
+        params![display_path(path.as_os_str()), unix_now()],
+    )
+    .await?;
+    Ok(())
+}
+
+// for imports: a transaction per batch, so the writer isn't held for the whole import.
+// rows sqlite rejects are logged and skipped, a record that can't be read stops it
+pub async fn write_in_batches<E: Into<Box<dyn std::error::Error>>>(
+    conn: &Mutex<Connection>,
+    writer: &mut BulkWriter,
+    records: impl Iterator<Item = Result<FileRecord, E>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batch_size = 500;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut records = records.peekable();
+
+    while records.peek().is_some() {
+        for record in records.by_ref() {
+            batch.push(record.map_err(Into::into)?);
+            if batch.len() == batch_size {
+                break;
+            }
+        }
+
+        let conn = conn.lock().await;
+        conn.execute("BEGIN", ()).await?;
+        let errors = writer.write(&batch).await;
+        if let Err(e) = conn.execute("COMMIT", ()).await {
+            let _ = conn.execute("ROLLBACK", ()).await;
+            return Err(e.into());
+        }
+        for error in errors {
+            warn!(path = %error.path.display(), "Failed to import file: {}", error.error);
+        }
+        batch.clear();
+    }
+    Ok(())
+}
+
+// Importing mlocate and plocate databases, for an instant first index
+//
+// mlocate.db is big endian: "\0mlocate", a u32 config block size, a version byte, a visibility byte
+// and 2 bytes of padding, then the root path and the config block. after that comes one record per
+// directory: its mtime (u64 seconds, u32 nanoseconds, 4 bytes padding), its path, and its entries,
+// each a type byte (0 file, 1 directory, 2 end of directory) followed by the name
+//
+// plocate.db is native endian: "\0plocate" and a header pointing at a table of u64 offsets, one
+// per block plus one for the end. each block is zstd compressed, with the header's dictionary if it
+// has one, and holds up to 32 NUL terminated full paths. files and directories aren't told apart
+//
+// neither format keeps sizes or times, see FileRecord::from_path
+
+pub const LOCATE_DB_PATHS: &[&str] =
+    &["/var/lib/plocate/plocate.db", "/var/lib/mlocate/mlocate.db"];
+
+const MLOCATE_MAGIC: &[u8] = b"\0mlocate";
+const PLOCATE_MAGIC: &[u8] = b"\0plocate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocateDbFormat {
+    Mlocate,
+    Plocate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocateImport {
+    pub format: LocateDbFormat,
+    pub paths_read: u64,
+    // new rows, paths that were skipped or already indexed aren't counted
+    pub files_imported: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocateEntry {
+    pub path: PathBuf,
+    // plocate doesn't say, so this is only ever true for mlocate
+    pub is_dir: bool,
+}
+
+fn invalid_data(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+// offsets and lengths come from the file, so a damaged or crafted one mustn't overflow them
+fn add_offset(offset: usize, length: usize) -> std::io::Result<usize> {
+    offset
+        .checked_add(length)
+        .ok_or_else(|| invalid_data("offset out of range in locate database"))
+}
+
+fn to_offset(value: u64) -> std::io::Result<usize> {
+    usize::try_from(value).map_err(|_| invalid_data("offset out of range in locate database"))
+}
+
+pub struct LocateDbReader<'a> {
+    format: LocateDbFormat,
+    data: &'a [u8],
+    // mlocate: where the next record starts. plocate: the next block
+    position: usize,
+    // mlocate: the directory whose entries are being read
+    directory: Option<Vec<u8>>,
+    // plocate: the offset table, how many blocks it has, and the current block decompressed
+    index_offset: usize,
+    blocks: usize,
+    dictionary: Option<&'a [u8]>,
+    block: Vec<u8>,
+    block_position: usize,
+    failed: bool,
+}
+
+impl<'a> LocateDbReader<'a> {
+    pub fn new(data: &'a [u8]) -> std::io::Result<Self> {
+        let mut reader = Self {
+            format: LocateDbFormat::Mlocate,
+            data,
+            position: 0,
+            directory: None,
+            index_offset: 0,
+            blocks: 0,
+            dictionary: None,
+            block: Vec::new(),
+            block_position: 0,
+            failed: false,
+        };
+
+        if data.starts_with(MLOCATE_MAGIC) {
+            let header = data
+                .get(..16)
+                .ok_or_else(|| invalid_data("mlocate header is truncated"))?;
+            let config_size = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+            if header[12] != 0 {
+                return Err(invalid_data(format!(
+                    "unsupported mlocate version {}",
+                    header[12]
+                )));
+            }
+            // the root path is repeated as the first directory record, so it's only skipped here
+            let (_, after_root) = read_cstr(data, 16)?;
+            reader.position = add_offset(after_root, config_size)?;
+        } else if data.starts_with(PLOCATE_MAGIC) {
+            reader.format = LocateDbFormat::Plocate;
+            let version = read_u32_ne(data, 8)?;
+            if version > 2 {
+                return Err(invalid_data(format!(
+                    "unsupported plocate version {}",
+                    version
+                )));
+            }
+            reader.blocks = read_u32_ne(data, 20)? as usize;
+            reader.index_offset = to_offset(read_u64_ne(data, 32)?)?;
+            // version 0 came before dictionaries
+            if version >= 1 {
+                let length = read_u32_ne(data, 44)? as usize;
+                let offset = to_offset(read_u64_ne(data, 48)?)?;
+                if length > 0 {
+                    reader.dictionary = Some(
+                        data.get(offset..add_offset(offset, length)?)
+                            .ok_or_else(|| invalid_data("plocate dictionary is truncated"))?,
+                    );
+                }
+            }
+        } else {
+            return Err(invalid_data("not an mlocate or plocate database"));
+        }
+        Ok(reader)
+    }
+
+    pub fn format(&self) -> LocateDbFormat {
+        self.format
+    }
+
+    fn next_mlocate(&mut self) -> std::io::Result<Option<LocateEntry>> {
+        loop {
+            let Some(directory) = &self.directory else {
+                if self.position == self.data.len() {
+                    return Ok(None);
+                }
+                let header_end = add_offset(self.position, 16)?;
+                if header_end > self.data.len() {
+                    return Err(invalid_data("mlocate directory record is truncated"));
+                }
+                let (path, next) = read_cstr(self.data, header_end)?;
+                self.directory = Some(path.to_vec());
+                self.position = next;
+                continue;
+            };
+
+            let kind = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| invalid_data("mlocate directory has no end"))?;
+            self.position += 1;
+            match kind {
+                0 | 1 => {
+                    let (name, next) = read_cstr(self.data, self.position)?;
+                    self.position = next;
+                    let mut path = directory.clone();
+                    if path != b"/" {
+                        path.push(b'/');
                     }
+                    path.extend_from_slice(name);
+                    return Ok(Some(LocateEntry {
+                        path: path_from_bytes(&path),
+                        is_dir: kind == 1,
+                    }));
                 }
-                // println!("Processing directory: {}", _path.display());
-            });
-        })
-        .skip_hidden(false);
+                2 => self.directory = None,
+                kind => return Err(invalid_data(format!("unknown mlocate entry type {}", kind))),
+            }
+        }
+    }
 
-    let elapsed = now.elapsed();
-    println!("Search completed in: {:.10?}", elapsed);
-    // print!("Search completed. ");
-    // Implement search logic here
+    fn next_plocate(&mut self) -> std::io::Result<Option<LocateEntry>> {
+        while self.block_position >= self.block.len() {
+            if self.position == self.blocks {
+                return Ok(None);
+            }
+            let entry = self
+                .position
+                .checked_mul(8)
+                .ok_or_else(|| invalid_data("offset out of range in locate database"))?;
+            let offset = add_offset(self.index_offset, entry)?;
+            let start = to_offset(read_u64_ne(self.data, offset)?)?;
+            let end = to_offset(read_u64_ne(self.data, add_offset(offset, 8)?)?)?;
+            let compressed = self
+                .data
+                .get(start..end)
+                .ok_or_else(|| invalid_data("plocate block is truncated"))?;
 
-    Ok(walk_dir)
+            self.block.clear();
+            match self.dictionary {
+                Some(dictionary) => {
+                    let mut decoder =
+                        zstd::stream::Decoder::with_dictionary(compressed, dictionary)?;
+                    std::io::Read::read_to_end(&mut decoder, &mut self.block)?;
+                }
+                None => self.block = zstd::stream::decode_all(compressed)?,
+            }
+            self.block_position = 0;
+            self.position += 1;
+        }
+
+        let (path, next) = read_cstr(&self.block, self.block_position)?;
+        let entry = LocateEntry {
+            path: path_from_bytes(path),
+            is_dir: false,
+        };
+        self.block_position = next;
+        Ok(Some(entry))
+    }
+}
+
+impl Iterator for LocateDbReader<'_> {
+    type Item = std::io::Result<LocateEntry>;
+
+    // stops after the first error, the rest of a damaged database can't be trusted
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = match self.format {
+            LocateDbFormat::Mlocate => self.next_mlocate(),
+            LocateDbFormat::Plocate => self.next_plocate(),
+        };
+        if next.is_err() {
+            self.failed = true;
+        }
+        next.transpose()
+    }
+}
+
+// the bytes up to the next NUL, and where the ones after it start
+fn read_cstr(data: &[u8], start: usize) -> std::io::Result<(&[u8], usize)> {
+    let rest = data
+        .get(start..)
+        .ok_or_else(|| invalid_data("unexpected end of locate database"))?;
+    let length = rest
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| invalid_data("unterminated string in locate database"))?;
+    Ok((&rest[..length], start + length + 1))
+}
+
+fn read_u32_ne(data: &[u8], offset: usize) -> std::io::Result<u32> {
+    data.get(offset..add_offset(offset, 4)?)
+        .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("plocate header is truncated"))
+}
+
+fn read_u64_ne(data: &[u8], offset: usize) -> std::io::Result<u64> {
+    data.get(offset..add_offset(offset, 8)?)
+        .map(|bytes| u64::from_ne_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("plocate header is truncated"))
+}
+
+// loads the files from a locate database, paths `skip` returns true for are left out.
+// paths already in the index are kept as they are
+#[instrument(skip_all, fields(db = %db_path.display()))]
+pub async fn import_locate_db(
+    conn: &Mutex<Connection>,
+    db_path: &Path,
+    skip: impl Fn(&Path) -> bool,
+) -> Result<LocateImport, Box<dyn std::error::Error>> {
+    let data = std::fs::read(db_path)?;
+    let format = LocateDbReader::new(&data)?.format();
+
+    // plocate lists directories like files, anything that is some other path's parent is one.
+    // empty directories can't be told apart and come in as files
+    let mut directories = HashSet::new();
+    if format == LocateDbFormat::Plocate {
+        for entry in LocateDbReader::new(&data)? {
+            if let Some(parent) = entry?.path.parent() {
+                directories.insert(parent.to_path_buf());
+            }
+        }
+    }
+
+    let mut writer = {
+        let conn = conn.lock().await;
+        BulkWriter::keeping_existing(&conn).await?
+    };
+    let mut paths_read = 0;
+    let records = LocateDbReader::new(&data)?.filter_map(|entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        paths_read += 1;
+        if entry.is_dir || directories.contains(&entry.path) || skip(&entry.path) {
+            return None;
+        }
+        Some(Ok(FileRecord::from_path(entry.path)))
+    });
+    write_in_batches(conn, &mut writer, records).await?;
+    let files_imported = writer.changes();
+
+    info!(
+        ?format,
+        paths_read, files_imported, "imported locate database"
+    );
+    Ok(LocateImport {
+        format,
+        paths_read,
+        files_imported,
+    })
 }
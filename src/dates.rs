@@ -0,0 +1,211 @@
+use chrono::{
+    DateTime, Datelike, Days, Local, LocalResult, Months, NaiveDate, NaiveDateTime, TimeZone,
+};
+
+// Time expressions for the modified:, created: and accessed: filters
+//
+//   today, yesterday, this-week, last-week, this-month, last-month, this-year, last-year
+//   2025, 2025-01, 2025-01-31, 2025-01-31T12:30   the whole year / month / day / minute
+//   2h, 3d, 2w                                    within the last 2 hours, 3 days, 2 weeks
+//   >2025-01-01, <=last-week, <2h                 after or before a point in time
+//   2h..1d, 2025-01-01..2025-02-01                between two points, either end can be left off
+//
+// everything is in local time, and weeks start on Monday
+
+// unix seconds, start inclusive and end exclusive, None is unbounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+// a single expression on its own, before any comparison or range is applied
+enum Point {
+    // a stretch of time like a day or a month
+    Span(i64, i64),
+    // a moment relative to now, e.g. 2h
+    Ago(i64),
+}
+
+impl Point {
+    fn start(&self) -> i64 {
+        match self {
+            Point::Span(start, _) => *start,
+            Point::Ago(at) => *at,
+        }
+    }
+
+    fn end(&self) -> i64 {
+        match self {
+            Point::Span(_, end) => *end,
+            Point::Ago(at) => *at,
+        }
+    }
+}
+
+impl TimeRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::parse_at(value, Local::now())
+    }
+
+    // relative expressions are resolved against `now`
+    pub fn parse_at(value: &str, now: DateTime<Local>) -> Option<Self> {
+        let value = value.to_lowercase();
+
+        if let Some((from, to)) = value.split_once("..") {
+            let from = (!from.is_empty()).then(|| parse_point(from, now));
+            let to = (!to.is_empty()).then(|| parse_point(to, now));
+            return match (from, to) {
+                (Some(from), Some(to)) => {
+                    let (from, to) = (from?, to?);
+                    // 2h..1d reads naturally but 1d ago comes first, so order the ends
+                    Some(Self {
+                        start: Some(from.start().min(to.start())),
+                        end: Some(from.end().max(to.end())),
+                    })
+                }
+                (Some(from), None) => Some(Self::from(from?.start())),
+                (None, Some(to)) => Some(Self::until(to?.end())),
+                (None, None) => None,
+            };
+        }
+
+        // the two character operators have to be checked first
+        if let Some(rest) = value.strip_prefix(">=") {
+            return Some(Self::from(parse_point(rest, now)?.start()));
+        }
+        if let Some(rest) = value.strip_prefix("<=") {
+            return Some(Self::until(parse_point(rest, now)?.end()));
+        }
+        if let Some(rest) = value.strip_prefix('>') {
+            return Some(Self::from(parse_point(rest, now)?.end()));
+        }
+        if let Some(rest) = value.strip_prefix('<') {
+            return Some(Self::until(parse_point(rest, now)?.start()));
+        }
+
+        match parse_point(&value, now)? {
+            Point::Span(start, end) => Some(Self {
+                start: Some(start),
+                end: Some(end),
+            }),
+            Point::Ago(at) => Some(Self::from(at)),
+        }
+    }
+
+    fn from(start: i64) -> Self {
+        Self {
+            start: Some(start),
+            end: None,
+        }
+    }
+
+    fn until(end: i64) -> Self {
+        Self {
+            start: None,
+            end: Some(end),
+        }
+    }
+}
+
+fn parse_point(value: &str, now: DateTime<Local>) -> Option<Point> {
+    if let Some(point) = parse_named(value, now) {
+        return Some(point);
+    }
+    if let Some(seconds) = parse_duration(value) {
+        return Some(Point::Ago(now.timestamp() - seconds));
+    }
+    parse_date(value)
+}
+
+fn parse_named(value: &str, now: DateTime<Local>) -> Option<Point> {
+    let today = now.date_naive();
+    let monday = today - Days::new(today.weekday().num_days_from_monday() as u64);
+    let first_of_month = today.with_day(1)?;
+    let first_of_year = today.with_ordinal(1)?;
+
+    let (start, end) = match value {
+        "today" => (today, today + Days::new(1)),
+        "yesterday" => (today - Days::new(1), today),
+        "this-week" => (monday, monday + Days::new(7)),
+        "last-week" => (monday - Days::new(7), monday),
+        "this-month" => (first_of_month, first_of_month + Months::new(1)),
+        "last-month" => (first_of_month - Months::new(1), first_of_month),
+        "this-year" => (first_of_year, first_of_year + Months::new(12)),
+        "last-year" => (first_of_year - Months::new(12), first_of_year),
+        _ => return None,
+    };
+    Some(Point::Span(local_midnight(start), local_midnight(end)))
+}
+
+// 90s, 15m, 2h, 3d, 2w, 6mo, 1y
+// months and years are taken as 30 and 365 days, close enough for "roughly how long ago"
+fn parse_duration(value: &str) -> Option<i64> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().ok()?;
+
+    let unit_seconds = match unit {
+        "s" | "sec" | "secs" => 1,
+        "m" | "min" | "mins" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        "mo" | "month" | "months" => 30 * 24 * 60 * 60,
+        "y" | "year" | "years" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    amount.checked_mul(unit_seconds)
+}
+
+// 2025, 2025-01, 2025-01-31, 2025-01-31t12:30 or 2025-01-31t12:30:15 (the value is already lowercased)
+fn parse_date(value: &str) -> Option<Point> {
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dt%H:%M") {
+        let start = local_timestamp(time);
+        return Some(Point::Span(start, start + 60));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dt%H:%M:%S") {
+        let start = local_timestamp(time);
+        return Some(Point::Span(start, start + 1));
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(Point::Span(
+            local_midnight(day),
+            local_midnight(day + Days::new(1)),
+        ));
+    }
+
+    let mut parts = value.splitn(2, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    if year < 1000 {
+        return None;
+    }
+    match parts.next() {
+        Some(month) => {
+            let first = NaiveDate::from_ymd_opt(year, month.parse().ok()?, 1)?;
+            Some(Point::Span(
+                local_midnight(first),
+                local_midnight(first + Months::new(1)),
+            ))
+        }
+        None => {
+            let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            Some(Point::Span(
+                local_midnight(first),
+                local_midnight(first + Months::new(12)),
+            ))
+        }
+    }
+}
+
+fn local_midnight(day: NaiveDate) -> i64 {
+    local_timestamp(day.and_hms_opt(0, 0, 0).unwrap())
+}
+
+// a local time that falls in a DST gap doesn't exist, those are read as UTC rather than failing
+fn local_timestamp(time: NaiveDateTime) -> i64 {
+    match Local.from_local_datetime(&time) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.timestamp(),
+        LocalResult::None => time.and_utc().timestamp(),
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use serde::Serialize;
+
+// how much of a file is checked for NUL bytes before it is treated as binary and skipped
+const BINARY_CHECK_LEN: usize = 8192;
+
+#[derive(Debug, Clone)]
+pub struct GrepOptions {
+    pub pattern: Regex,
+    pub before_context: usize,
+    pub after_context: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Match,
+    Context,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GrepLine {
+    #[serde(rename = "type")]
+    pub kind: LineKind,
+    #[serde(serialize_with = "crate::paths::serialize_path")]
+    pub path: PathBuf,
+    pub line: usize,
+    // 1-based byte offset of the first match, None for context lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrepFileResult {
+    pub path: PathBuf,
+    // lines in file order, a None marks a break between non-adjacent groups
+    pub lines: Vec<Option<GrepLine>>,
+}
+
+impl GrepFileResult {
+    pub fn match_count(&self) -> usize {
+        self.lines
+            .iter()
+            .flatten()
+            .filter(|line| line.kind == LineKind::Match)
+            .count()
+    }
+}
+
+// search the contents of every candidate in parallel
+// results keep the order of the candidates so output is stable between runs
+pub fn grep_files(paths: &[PathBuf], options: &GrepOptions) -> Vec<GrepFileResult> {
+    paths
+        .par_iter()
+        .filter_map(|path| grep_file(path, options))
+        .collect()
+}
+
+pub fn grep_file(path: &Path, options: &GrepOptions) -> Option<GrepFileResult> {
+    // unreadable files are skipped, same as the indexer does
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::debug!(path = %path.display(), "Skipping unreadable file: {}", e);
+            return None;
+        }
+    };
+    let check_len = contents.len().min(BINARY_CHECK_LEN);
+    if contents[..check_len].contains(&0) {
+        return None;
+    }
+
+    let file_lines: Vec<&[u8]> = contents
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+
+    // a trailing newline leaves an empty final "line" that isn't really there
+    let line_count = match file_lines.last() {
+        Some([]) => file_lines.len() - 1,
+        _ => file_lines.len(),
+    };
+
+    let mut lines = Vec::new();
+    // index of the next line that hasn't been printed yet
+    let mut printed_until = 0;
+    // lines after this index still count as trailing context
+    let mut context_until = 0;
+
+    for (index, line) in file_lines.iter().take(line_count).enumerate() {
+        let Some(found) = options.pattern.find(line) else {
+            if index < context_until && index >= printed_until {
+                lines.push(Some(make_line(path, index, None, line)));
+                printed_until = index + 1;
+            }
+            continue;
+        };
+
+        let start = index
+            .saturating_sub(options.before_context)
+            .max(printed_until);
+        let has_context = options.before_context > 0 || options.after_context > 0;
+        if has_context && printed_until > 0 && start > printed_until {
+            lines.push(None);
+        }
+        for (context_index, context_line) in file_lines.iter().enumerate().take(index).skip(start) {
+            lines.push(Some(make_line(path, context_index, None, context_line)));
+        }
+
+        lines.push(Some(make_line(path, index, Some(found.start() + 1), line)));
+        printed_until = index + 1;
+        context_until = index + 1 + options.after_context;
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    Some(GrepFileResult {
+        path: path.to_path_buf(),
+        lines,
+    })
+}
+
+fn make_line(path: &Path, index: usize, column: Option<usize>, text: &[u8]) -> GrepLine {
+    GrepLine {
+        kind: if column.is_some() {
+            LineKind::Match
+        } else {
+            LineKind::Context
+        },
+        path: path.to_path_buf(),
+        line: index + 1,
+        column,
+        text: String::from_utf8_lossy(text).to_string(),
+    }
+}
+
+// ripgrep style output
+// matches are path:line:col:text, context lines are path-line-text and groups are split by --
+pub fn print_results(results: &[GrepFileResult], out: &mut impl Write) -> std::io::Result<()> {
+    for result in results {
+        for line in &result.lines {
+            match line {
+                Some(line) => match line.column {
+                    Some(column) => writeln!(
+                        out,
+                        "{}:{}:{}:{}",
+                        line.path.display(),
+                        line.line,
+                        column,
+                        line.text
+                    )?,
+                    None => writeln!(out, "{}-{}-{}", line.path.display(), line.line, line.text)?,
+                },
+                None => writeln!(out, "--")?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// one JSON object per line, so the output can be streamed into other tools
+pub fn print_results_json(results: &[GrepFileResult], out: &mut impl Write) -> std::io::Result<()> {
+    for line in results.iter().flat_map(|r| r.lines.iter().flatten()) {
+        serde_json::to_writer(&mut *out, line)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
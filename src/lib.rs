@@ -36,69 +36,121 @@
 // //! ```
 
 use std::{
-    sync::{Arc, Mutex},
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex as StdMutex},
     time::Duration,
 };
 
-use jwalk::{WalkDir, WalkDirGeneric};
-use libsql::{params, Connection, Database, Result as SQLResult};
+use jwalk::WalkDirGeneric;
+use libsql::{Connection, Database, Result as SQLResult};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, info_span, trace};
 
-use crate::{config::get_config, database::create_schema, search::FileEntry};
+use crate::{
+    config::get_config,
+    content::{read_content, ContentOptions},
+    database::{create_schema, DatabaseStats, IndexRunStatus},
+    grep::{GrepFileResult, GrepOptions},
+    permissions::{Credentials, Permissions},
+    pool::ReaderPool,
+    progress::{IndexEvent, IndexProgress, IndexStats},
+    search::FileEntry,
+};
 
-mod config;
-mod database;
-mod search;
+pub mod archive;
+pub mod changes;
+pub mod config;
+pub mod content;
+pub mod database;
+pub mod dates;
+pub mod grep;
+pub mod locate;
+pub mod mounts;
+pub mod paths;
+pub mod permissions;
+mod pool;
+pub mod progress;
+pub mod search;
+pub mod sizes;
 mod tests;
+pub mod users;
+
+pub use database::{IndexRun, IntegrityReport, LocateImport, QueryRecord, SavedSearch};
+
+// what the daemon will put up with from its clients, see SearchEngine::serve
+#[cfg(unix)]
+const MAX_CLIENTS: usize = 64;
+#[cfg(unix)]
+const MAX_QUERY_LENGTH: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub database: DatabaseStats,
+    // the current index if one is running in this process, otherwise the last one it ran
+    pub index: IndexStats,
+    // the directory being watched for changes, None if the watcher isn't running
+    pub watching: Option<PathBuf>,
+}
 
 pub struct SearchEngine {
+    // not used directly yet, but keeps the database alive for as long as the engine is
+    #[allow(dead_code)]
     database: Database,
-    connection: Arc<Mutex<Connection>>,
-    query_queue: Vec<String>,
+    // the only connection that writes, shared by the indexer and anything else that modifies the index
+    writer: Arc<Mutex<Connection>>,
+    // read-only connections for searching, these never wait on the writer
+    readers: Arc<ReaderPool>,
+    // shared with the indexing thread, which reports into it as it goes
+    progress: Arc<IndexProgress>,
+    // set by the watcher thread while it's running
+    watching: Arc<StdMutex<Option<PathBuf>>>,
     config: config::Config,
-    debug: bool,
 }
 impl SearchEngine {
-    pub async fn new(db_path: &str, debug: Option<bool>) -> Result<Self, std::io::Error> {
+    pub async fn new(db_path: &str) -> Result<Self, std::io::Error> {
         let config = get_config();
-        println!("Using config: {:?}", config);
+        debug!(?config, "loaded config");
 
-        let database = libsql::Builder::new_local("search.db")
+        let database = libsql::Builder::new_local(db_path)
             .build()
             .await
             .expect("Failed to build database");
 
-        // Use an Arc<Mutex<Connection>> to share the connection across threads
-        // this is to allow the indexing to run in the background, incrementally adding the files to the database
-        //
+        // The indexing runs in the background, incrementally adding the files to the database
         // while the main thread is free to accept user input and perform searches on the partially indexed database
         // this lets users search while the database is being indexed
-
-        // but why not just use a single connection?
-        // because indexing is slow, and users don't usually need the full indexed database to perform searches
-        // so we can use a separate connection for indexing and another for searching
-        // this is purely for startup performance and user experience
-
-        // conn_raw shouldn't be used directly, it should be wrapped in an Arc<Mutex<Connection>>
-        // to allow multiple threads to access it safely
-        let conn_raw = database.connect().expect("Failed to connect to database");
-        create_schema(conn_raw.clone(), None)
+        //
+        // the database is in WAL mode, so this is split into one writer connection (behind a mutex, sqlite only
+        // allows one writer at a time anyway) and a pool of read-only connections for searches.
+        // readers see every batch the indexer has committed and never wait for it to finish the next one
+        let writer = database.connect().expect("Failed to connect to database");
+        create_schema(writer.clone(), None)
             .await
             .expect("Failed to create schema");
-        // have one connection for the main thread
-        // and one for the worker thread that will insert files into the database
-        // this is to avoid deadlocks and allow the main thread to continue accepting user input
-        let connection = Arc::new(Mutex::new(conn_raw.clone()));
+        let writer = Arc::new(Mutex::new(writer));
+
+        // the schema has to exist before the readers are opened, as they aren't allowed to create it
+        let readers = ReaderPool::new(&database, ReaderPool::default_size())
+            .await
+            .expect("Failed to open reader connections");
 
         Ok(Self {
             database,
-            connection,
-            query_queue: Vec::new(),
+            writer,
+            readers: Arc::new(readers),
+            progress: Arc::new(IndexProgress::new()),
+            watching: Arc::new(StdMutex::new(None)),
             config,
-            debug: debug.unwrap_or(false),
         })
     }
 
+    // keeps the index up to date between runs, every change it sees lands in the changes log
     pub fn start_watcher(&self) {
+        let watching = self.watching.clone();
+        let writer = self.writer.clone();
+        let config = self.config.clone();
         // Spawn a thread to run the file watcher so it doesn't block the main thread
         std::thread::spawn(move || {
             use notify::{Event, RecursiveMode, Result, Watcher};
@@ -118,36 +170,133 @@ impl SearchEngine {
             .unwrap();
             // Add a path to be watched. All files and directories at that path and
             // below will be monitored for changes.
-            watcher
-                .watch(Path::new("/home/theo"), RecursiveMode::Recursive)
-                .unwrap();
-            // Block forever, printing out events as they come in
-            for res in rx {
-                match res {
-                    Ok(event) => match event.kind {
-                        // notify::EventKind::Create(_) => {
-                        //     println!("File created: {:?}", event.paths);
-                        // }
-                        // notify::EventKind::Modify(_) => {
-                        //     println!("File modified: {:?}", event.paths);
-                        // }
-                        // notify::EventKind::Remove(_) => {
-                        //     println!("File removed: {:?}", event.paths);
-                        // }
-                        _ => {}
-                    },
-                    Err(_e) => {
-                        // Ignore error
-                    }
+            let path = Path::new("/home/theo");
+            if let Err(e) = watcher.watch(path, RecursiveMode::Recursive) {
+                error!("Failed to watch {}: {}", path.display(), e);
+                return;
+            }
+            *watching.lock().unwrap() = Some(path.to_path_buf());
+
+            // same as the indexer, the database calls are async but this thread isn't
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+            while let Ok(first) = rx.recv() {
+                // PollWatcher reports a move as a remove and a create, in no particular order.
+                // everything until it's been quiet for a moment is applied together, creates first,
+                // so the moved file is found at its new path before the old one is dropped
+                let mut events = vec![first];
+                while let Ok(res) = rx.recv_timeout(Duration::from_millis(500)) {
+                    events.push(res);
                 }
+                let mut events: Vec<Event> = events
+                    .into_iter()
+                    .filter_map(|res| res.map_err(|e| debug!("Watch error: {}", e)).ok())
+                    .collect();
+                events.sort_by_key(|event| event.kind.is_remove());
+
+                rt.block_on(async {
+                    let conn = writer.lock().await;
+                    for event in &events {
+                        if let Err(e) = apply_watch_event(&conn, &config, event).await {
+                            error!(paths = ?event.paths, "Failed to update the index: {}", e);
+                        }
+                    }
+                });
             }
+            *watching.lock().unwrap() = None;
         });
     }
 
+    pub async fn stats(&self) -> SQLResult<Stats> {
+        let conn = self.readers.get().await;
+        Ok(Stats {
+            database: database::get_database_stats(&conn).await?,
+            index: self.progress.stats(),
+            watching: self.watching.lock().unwrap().clone(),
+        })
+    }
+
+    // a previous index that was killed before finishing, index_directories picks it back up
+    pub async fn interrupted_index(&self) -> SQLResult<Option<IndexRun>> {
+        let conn = self.writer.lock().await;
+        database::get_interrupted_run(&conn).await
+    }
+
+    // events from every index started after this, see progress.rs
+    pub fn subscribe_progress(&self) -> tokio::sync::broadcast::Receiver<IndexEvent> {
+        self.progress.subscribe()
+    }
+
+    // a snapshot of the current (or last) index, cheap enough to poll
+    pub fn index_stats(&self) -> IndexStats {
+        self.progress.stats()
+    }
+
+    // indexes in the background, progress is reported through subscribe_progress and index_stats
     pub async fn index_directories(&self) {
-        let conn_worker = self.connection.clone();
+        let conn_worker = self.writer.clone();
         let config = self.config.clone();
-        let directories = get_directories(&config).expect("Failed to run search");
+        let progress = self.progress.clone();
+
+        // resume the last run if it never finished, skipping the directories it had already committed
+        let (run_id, completed, already_committed, expected_files) = {
+            let conn = self.writer.lock().await;
+            let expected_files = database::get_last_completed_run(&conn)
+                .await
+                .expect("Failed to read index runs")
+                .map(|run| run.files_indexed as u64);
+
+            match database::get_interrupted_run(&conn)
+                .await
+                .expect("Failed to read index runs")
+            {
+                Some(run) => {
+                    let completed = database::get_completed_directories(&conn, run.id)
+                        .await
+                        .expect("Failed to read index checkpoints");
+                    (
+                        run.id,
+                        completed,
+                        Some(run.files_indexed as u64),
+                        expected_files,
+                    )
+                }
+                None => (
+                    database::start_index_run(&conn)
+                        .await
+                        .expect("Failed to start index run"),
+                    HashSet::new(),
+                    None,
+                    expected_files,
+                ),
+            }
+        };
+        progress.started(run_id, already_committed, expected_files);
+
+        let mounts = mounts::read_mounts();
+        {
+            let conn = self.writer.lock().await;
+            if let Err(e) = mounts::save_mounts(&conn, &mounts).await {
+                progress.error(None, format!("Failed to save the mount table: {}", e));
+            }
+            // the walker doesn't go through the directories above the roots, but users have to
+            let above_roots: Vec<_> = config
+                .roots
+                .iter()
+                .flat_map(|root| root.ancestors().skip(1))
+                .filter_map(|directory| {
+                    let metadata = std::fs::metadata(directory).ok()?;
+                    Some((
+                        directory.to_path_buf(),
+                        Permissions::from_metadata(&metadata)?,
+                    ))
+                })
+                .collect();
+            if let Err(e) = database::write_directories(&conn, &above_roots).await {
+                progress.error(None, format!("Failed to save directory permissions: {}", e));
+            }
+        }
+        let directories =
+            get_directories(&config, &mounts, completed).expect("Failed to run search");
 
         tokio::task::spawn_blocking(move || {
             // this technically doesn't need to be async, but it just makes it easier to work with
@@ -157,66 +306,544 @@ impl SearchEngine {
             let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
             rt.block_on(async {
-                database::insert_files_to_db(directories, conn_worker)
-                    .await
-                    .expect("Failed to insert files into database");
+                if let Err(e) = database::insert_files_to_db(
+                    directories.into_iter().flatten(),
+                    conn_worker.clone(),
+                    run_id,
+                    progress.clone(),
+                    ContentOptions::from_config(&config),
+                )
+                .await
+                {
+                    progress.error(None, format!("Failed to insert files into database: {}", e));
+                    let conn = conn_worker.lock().await;
+                    let _ = database::finish_index_run(&conn, run_id, IndexRunStatus::Failed).await;
+                    progress.finished(IndexRunStatus::Failed);
+                }
             });
         });
     }
 
+    // an instant first index from the system's plocate or mlocate database, the walker fills in
+    // sizes and times when it gets to each file. None picks whichever database exists
+    pub async fn import_locate_db(
+        &self,
+        db_path: Option<&Path>,
+    ) -> Result<LocateImport, Box<dyn std::error::Error>> {
+        let db_path = match db_path {
+            Some(path) => path.to_path_buf(),
+            None => database::LOCATE_DB_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .ok_or("no plocate or mlocate database found")?,
+        };
+
+        // only what a walk could have found, so nothing outside the roots, on a skipped mount or
+        // in a skipped directory
+        let walked = walked_roots(&self.config, &mounts::read_mounts());
+        database::import_locate_db(&self.writer, &db_path, |path| {
+            !walks_into(&walked, path) || in_skipped_directory(&self.config, path)
+        })
+        .await
+    }
+
+    // writes the files under `roots` (everything if it's empty) to an archive, see archive.rs
+    pub async fn export(
+        &self,
+        path: &Path,
+        format: archive::ArchiveFormat,
+        roots: &[PathBuf],
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let conn = self.readers.get().await;
+        archive::export(&conn, path, format, roots, &self.config).await
+    }
+
+    pub async fn import(
+        &self,
+        path: &Path,
+    ) -> Result<archive::ArchiveImport, Box<dyn std::error::Error>> {
+        let import = archive::import(&self.writer, path).await?;
+        if import.header.config_hash != archive::config_hash(&self.config) {
+            info!("The archive was indexed with different settings, it may hold files this index skips");
+        }
+        Ok(import)
+    }
+
+    pub async fn file_count(&self) -> SQLResult<usize> {
+        let conn = self.readers.get().await;
+        database::get_database_count(&conn).await
+    }
+
     // Add implementation for the search method to use search::search_files under the hood
-    pub async fn search_files(&self, query: &str) -> SQLResult<Vec<FileEntry>> {
-        // Lock connection exclusively, pauses indexing
-        if let Ok(mut conn) = self.connection.lock() {
-            println!("Locked connection successfully.");
-            let mut query_formatter = search::SearchQuery::new();
-            let query = query_formatter.make_query(query.trim());
-
-            let res = search::search_files(&query, conn)
+    // `sort` is the order unless the query has its own sort:, None keeps the index order
+    pub async fn search_files(
+        &self,
+        query: &str,
+        sort: Option<search::Sort>,
+    ) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
+        let mut query_formatter = search::SearchQuery::new().with_sort(sort);
+        query_formatter.make_query(query.trim())?;
+        Ok(self.search(&query_formatter).await?)
+    }
+
+    // a search as another user would see it, for a daemon sharing one index between users.
+    // files under directories they can't get into are left out, see permissions.rs
+    pub async fn search_files_as(
+        &self,
+        query: &str,
+        sort: Option<search::Sort>,
+        credentials: &Credentials,
+    ) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
+        let mut query_formatter = search::SearchQuery::new().with_sort(sort);
+        query_formatter.make_query(query.trim())?;
+        let conn = self.readers.get().await;
+        Ok(permissions::search_visible(&conn, &query_formatter, credentials).await?)
+    }
+
+    // shares the index over a unix socket. every line a client writes is a query, answered with
+    // one line of JSON, {"results": [...]} or {"error": "..."}. the client only sees what
+    // search_files_as lets them, and who they are comes from the socket, not from what they send.
+    // anyone can connect, so clients past MAX_CLIENTS wait to be accepted
+    #[cfg(unix)]
+    pub async fn serve(self: Arc<Self>, listener: tokio::net::UnixListener) -> std::io::Result<()> {
+        let clients = Arc::new(tokio::sync::Semaphore::new(MAX_CLIENTS));
+        loop {
+            let permit = clients
+                .clone()
+                .acquire_owned()
                 .await
-                .expect("Failed to perform search");
+                .expect("the semaphore is never closed");
+            let (stream, _) = listener.accept().await?;
+            let engine = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = engine.serve_client(stream).await {
+                    debug!("Client connection ended: {}", e);
+                }
+                drop(permit);
+            });
+        }
+    }
+
+    // a query longer than MAX_QUERY_LENGTH gets an error and the connection is closed, rather than
+    // buffering however much a client sends without a newline
+    #[cfg(unix)]
+    async fn serve_client(&self, stream: tokio::net::UnixStream) -> std::io::Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 
-            if res.is_empty() {
-                println!("No results found for query: {}", query.trim());
-                return Ok(vec![]);
+        let credentials = Credentials::of_peer(&stream)?;
+        debug!(uid = credentials.uid, "Client connected");
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut query = Vec::new();
+        loop {
+            query.clear();
+            let read = (&mut reader)
+                .take(MAX_QUERY_LENGTH + 1)
+                .read_until(b'\n', &mut query)
+                .await?;
+            if read == 0 {
+                return Ok(());
+            }
+            let too_long = query.last() != Some(&b'\n') && read as u64 > MAX_QUERY_LENGTH;
+            let response = if too_long {
+                serde_json::json!({
+                    "error": format!("queries can be at most {} bytes", MAX_QUERY_LENGTH)
+                })
             } else {
-                Ok(res)
+                match std::str::from_utf8(&query) {
+                    Ok(query) => match self.search_files_as(query, None, &credentials).await {
+                        Ok(results) => serde_json::json!({ "results": results }),
+                        Err(e) => serde_json::json!({ "error": e.to_string() }),
+                    },
+                    Err(_) => serde_json::json!({ "error": "queries have to be UTF-8" }),
+                }
+            };
+            let mut line = serde_json::to_vec(&response)?;
+            line.push(b'\n');
+            writer.write_all(&line).await?;
+            if too_long {
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn search(&self, query: &search::SearchQuery) -> SQLResult<Vec<FileEntry>> {
+        let conn = self.readers.get().await;
+        trace!("Acquired reader connection.");
+
+        search::search_files(query, &conn).await
+    }
+
+    // runs the query like search does, but also reports how it was planned and where the time went
+    pub async fn explain(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        sort: Option<search::Sort>,
+    ) -> Result<search::SearchExplain, Box<dyn std::error::Error>> {
+        let conn = self.readers.get().await;
+        search::explain_search(query, limit, sort, &conn).await
+    }
+
+    // history and saved searches are written through the writer, the readers can't write
+    pub async fn record_query(&self, query: &str, result_count: usize) -> SQLResult<()> {
+        let conn = self.writer.lock().await;
+        database::record_query(&conn, query, result_count).await
+    }
+
+    // the most recent queries, newest first
+    pub async fn query_history(&self, limit: usize) -> SQLResult<Vec<QueryRecord>> {
+        let conn = self.readers.get().await;
+        database::get_query_history(&conn, limit).await
+    }
+
+    pub async fn history_query(&self, id: i64) -> SQLResult<Option<String>> {
+        let conn = self.readers.get().await;
+        database::get_query(&conn, id).await
+    }
+
+    pub async fn save_search(&self, name: &str, query: &str) -> SQLResult<()> {
+        let conn = self.writer.lock().await;
+        database::save_search(&conn, name, query).await
+    }
+
+    pub async fn saved_search(&self, name: &str) -> SQLResult<Option<String>> {
+        let conn = self.readers.get().await;
+        database::get_saved_search(&conn, name).await
+    }
+
+    pub async fn saved_searches(&self) -> SQLResult<Vec<SavedSearch>> {
+        let conn = self.readers.get().await;
+        database::get_saved_searches(&conn).await
+    }
+
+    // a result the user picked, it ranks higher in later searches
+    pub async fn record_selection(&self, path: &Path) -> SQLResult<()> {
+        let conn = self.writer.lock().await;
+        database::record_selection(&conn, path).await
+    }
+
+    pub async fn delete_saved_search(&self, name: &str) -> SQLResult<bool> {
+        let conn = self.writer.lock().await;
+        database::delete_saved_search(&conn, name).await
+    }
+
+    // check the database and that the FTS index matches the files table
+    pub async fn integrity_check(&self, repair: bool) -> SQLResult<IntegrityReport> {
+        // goes through the writer, as repairing rebuilds the index
+        let conn = self.writer.lock().await;
+        database::check_integrity(&conn, repair).await
+    }
+
+    // what re-indexing and the watcher have seen change since `since`, see changes.rs
+    pub async fn changes(
+        &self,
+        since: i64,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<changes::Change>, Box<dyn std::error::Error>> {
+        let mut query_formatter = search::SearchQuery::new().with_limit(limit);
+        query_formatter.make_query(query.trim())?;
+        let conn = self.readers.get().await;
+        Ok(changes::get_changes(&conn, since, &query_formatter).await?)
+    }
+
+    // grep the contents of every indexed file matching `query`
+    // the index narrows down the candidates, then the files themselves are read in parallel
+    pub async fn grep(
+        &self,
+        query: &str,
+        options: GrepOptions,
+    ) -> Result<Vec<GrepFileResult>, Box<dyn std::error::Error>> {
+        let mut query_formatter = search::SearchQuery::new().with_limit(None);
+        query_formatter.make_query(query.trim())?;
+
+        let candidates: Vec<PathBuf> = self
+            .search(&query_formatter)
+            .await?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+
+        let results = tokio::task::spawn_blocking(move || grep::grep_files(&candidates, &options))
+            .await
+            .expect("Grep worker panicked");
+        Ok(results)
+    }
+
+    // `rustsearch locate`, answered straight from the files table
+    pub async fn locate<E: From<libsql::Error>>(
+        &self,
+        locator: &locate::Locator,
+        found: impl FnMut(PathBuf) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        let conn = self.readers.get().await;
+        locator.run(&conn, found).await
+    }
+}
+
+// whether any directory above `path` is one the walker skips
+fn in_skipped_directory(config: &config::Config, path: &Path) -> bool {
+    path.parent().is_some_and(|parent| {
+        parent.iter().any(|name| {
+            let name = name.to_string_lossy();
+            config
+                .skip_directories
+                .iter()
+                .any(|dir| name.contains(dir.as_str()))
+        })
+    })
+}
+
+// the walker's skip rules for a single file, see get_directories
+fn is_skipped(config: &config::Config, path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if config
+        .force_include
+        .iter()
+        .any(|pattern| name.contains(pattern.as_str()))
+    {
+        return false;
+    }
+    in_skipped_directory(config, path)
+        || config
+            .skip_extensions
+            .iter()
+            .any(|ext| name.ends_with(ext.as_str()))
+        || config
+            .skip_patterns
+            .iter()
+            .any(|pattern| name.contains(pattern.as_str()))
+}
+
+// what the watcher saw, applied to the index. a rename moves the file, or a whole directory, when
+// the platform reports both paths. PollWatcher only sees a remove and a create, see start_watcher
+async fn apply_watch_event(
+    conn: &Connection,
+    config: &config::Config,
+    event: &notify::Event,
+) -> SQLResult<()> {
+    use notify::event::{EventKind, ModifyKind, RenameMode};
+
+    match (&event.kind, event.paths.as_slice()) {
+        (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+            if is_skipped(config, to) {
+                database::delete_files_under(conn, from).await?;
+            } else if database::rename_path(conn, from, to).await? == 0 {
+                // it wasn't indexed before
+                refresh_path(conn, config, to).await?;
+            }
+        }
+        (EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_), paths) => {
+            for path in paths {
+                refresh_path(conn, config, path).await?;
+            }
+        }
+        _ => return Ok(()),
+    }
+    database::prune_changes(conn).await
+}
+
+// reads a path the watcher reported again, a file is written, a directory's permissions are
+// updated and anything that's gone is removed along with whatever was under it
+async fn refresh_path(conn: &Connection, config: &config::Config, path: &Path) -> SQLResult<()> {
+    // a symlink is written as itself, whatever's behind it is only reported if it's watched
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata)
+            if (metadata.is_file() || metadata.file_type().is_symlink())
+                && !is_skipped(config, path) =>
+        {
+            trace!(path = %path.display(), "watched file changed");
+            let mut records = vec![database::FileRecord::new(path.to_path_buf(), &metadata)];
+            read_content(&mut records, ContentOptions::from_config(config));
+            // it became a binary skip_binary leaves out
+            let Some(record) = records.pop() else {
+                return database::delete_files_under(conn, path).await.map(|_| ());
+            };
+            database::move_renamed_files(conn, std::slice::from_ref(&record)).await?;
+            database::write_file(conn, &record).await
+        }
+        // a new directory, or one whose owner or mode changed
+        Ok(metadata) if metadata.is_dir() && !is_skipped(config, path) => {
+            match Permissions::from_metadata(&metadata) {
+                Some(permissions) => {
+                    database::write_directories(conn, &[(path.to_path_buf(), permissions)]).await
+                }
+                None => Ok(()),
             }
-        } else {
-            println!("[Main] Could not acquire DB connection.");
-            return Err(libsql::Error::ConnectionFailed(
-                "Failed to acquire database connection".into(),
-            ));
         }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            trace!(path = %path.display(), "watched path removed");
+            database::delete_files_under(conn, path).await.map(|_| ())
+        }
+        // their files are reported one by one
+        _ => Ok(()),
     }
 }
 
+// the ids of the directories a walker is inside, the walk state jwalk hands down to each
+// directory's children
+type WalkState = (Vec<(i64, i64)>, bool);
+
+// one walker per root, `completed` directories are not descended into, they were fully indexed by
+// an interrupted run
 fn get_directories(
     config: &config::Config,
-) -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
-    println!("Searching for files in home directory... ");
+    mounts: &[mounts::Mount],
+    completed: HashSet<PathBuf>,
+) -> Result<Vec<WalkDirGeneric<WalkState>>, std::io::Error> {
+    let completed = Arc::new(completed);
+    // targets are compared to the roots as real paths
+    let real_roots: Arc<Vec<PathBuf>> = Arc::new(
+        config
+            .roots
+            .iter()
+            .filter_map(|root| std::fs::canonicalize(root).ok())
+            .collect(),
+    );
+    config
+        .roots
+        .iter()
+        .map(|root| walk_root(config, mounts, root, completed.clone(), real_roots.clone()))
+        .collect()
+}
+
+// a symlink to a directory is only gone into when the policy allows it and the directory isn't
+// one the walker is already inside, which would go round in circles. with one_file_system,
+// `device` is the root's and links to other filesystems aren't followed either
+fn follow_symlink(
+    path: &Path,
+    policy: config::FollowSymlinks,
+    roots: &[PathBuf],
+    ancestors: &[(i64, i64)],
+    device: Option<i64>,
+) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    if !metadata.is_dir() {
+        return false;
+    }
+    let Some(id) = directory_id(&metadata) else {
+        // without an id there's no telling a loop apart
+        return false;
+    };
+    if ancestors.contains(&id) {
+        debug!(path = %path.display(), "Not following symlink back into its own tree");
+        return false;
+    }
+    if device.is_some_and(|device| device != id.0) {
+        debug!(path = %path.display(), "Not following symlink onto another filesystem");
+        return false;
+    }
+    match policy {
+        config::FollowSymlinks::Never => false,
+        config::FollowSymlinks::Always => true,
+        config::FollowSymlinks::WithinRoots => std::fs::canonicalize(path)
+            .is_ok_and(|target| roots.iter().any(|root| target.starts_with(root))),
+    }
+}
+
+fn directory_id(metadata: &std::fs::Metadata) -> Option<(i64, i64)> {
+    match database::file_id(metadata) {
+        (Some(device), Some(inode)) => Some((device, inode)),
+        _ => None,
+    }
+}
+
+// with one_file_system, the device the walk of `root` stays on
+fn root_device(config: &config::Config, root: &Path) -> Option<i64> {
+    if !config.one_file_system {
+        return None;
+    }
+    std::fs::metadata(root)
+        .ok()
+        .and_then(|metadata| directory_id(&metadata))
+        .map(|(device, _)| device)
+}
+
+// each root with the mounts its walk skips
+fn walked_roots(
+    config: &config::Config,
+    mounts: &[mounts::Mount],
+) -> Vec<(PathBuf, HashSet<PathBuf>)> {
+    config
+        .roots
+        .iter()
+        .map(|root| {
+            let skipped = mounts::skipped_mounts(config, mounts, root, root_device(config, root));
+            (root.clone(), skipped)
+        })
+        .collect()
+}
+
+// whether walking `walked` could get to `path`, skip rules aside
+fn walks_into(walked: &[(PathBuf, HashSet<PathBuf>)], path: &Path) -> bool {
+    walked.iter().any(|(root, skipped)| {
+        path.starts_with(root) && !skipped.iter().any(|mount| path.starts_with(mount))
+    })
+}
+
+fn walk_root(
+    config: &config::Config,
+    mounts: &[mounts::Mount],
+    root: &Path,
+    completed: Arc<HashSet<PathBuf>>,
+    roots: Arc<Vec<PathBuf>>,
+) -> Result<WalkDirGeneric<WalkState>, std::io::Error> {
+    debug!(root = %root.display(), "Searching for files... ");
     let now = std::time::Instant::now();
+    // the closure runs on jwalk's worker threads, which don't inherit the caller's span
+    let span = info_span!("walk");
 
     // Clone the skip_directories so it can be moved into the closure
     let skip_directories = config.skip_directories.clone();
     let skip_extensions = config.skip_extensions.clone();
     let skip_patterns = config.skip_patterns.clone();
     let force_include = config.force_include.clone();
+    let follow_symlinks = config.follow_symlinks;
+    let max_depth = config.max_depth as usize;
+    let root_device = root_device(config, root);
+    let skipped_mounts = mounts::skipped_mounts(config, mounts, root, root_device);
 
     let walk_dir =
-        WalkDirGeneric::<(usize, bool)>::new("/".to_string())
-            .process_read_dir(move |_depth, _path, _read_dir_state, children| {
+        WalkDirGeneric::<WalkState>::new(root)
+            .process_read_dir(move |depth, path, ancestors, children| {
+                let _span = span.enter();
+                // the directory being read, the root's parent isn't one the walker is inside
+                if follow_symlinks != config::FollowSymlinks::Never && depth.is_some() {
+                    if let Some(id) = std::fs::metadata(path).ok().and_then(|m| directory_id(&m)) {
+                        ancestors.push(id);
+                    }
+                }
                 // 3. Custom skip
                 let skip_directories = &skip_directories;
                 children.iter_mut().for_each(|dir_entry_result| {
                     if let Ok(dir_entry) = dir_entry_result {
+                        if (dir_entry.file_type().is_dir() || dir_entry.file_type().is_symlink())
+                            && !completed.is_empty()
+                            && completed.contains(&dir_entry.path())
+                        {
+                            dir_entry.read_children_path = None;
+                            return;
+                        }
+
+                        if dir_entry.file_type().is_dir()
+                            && !skipped_mounts.is_empty()
+                            && skipped_mounts.contains(&dir_entry.path())
+                        {
+                            dir_entry.read_children_path = None;
+                            return;
+                        }
+
                         // include force include patterns
                         if force_include.iter().any(|pattern| {
                             dir_entry.file_name().to_string_lossy().contains(pattern)
                         }) {
-                            print!(
-                                "Forcing include for file: {}\n",
-                                dir_entry.file_name().to_string_lossy()
+                            debug!(
+                                path = %dir_entry.path().display(),
+                                "Forcing include for file"
                             );
                             // Do not skip this entry, but continue to check others
                         } else {
@@ -227,9 +854,9 @@ fn get_directories(
                                 })
                             {
                                 dir_entry.read_children_path = None;
-                                print!(
-                                    "Skipping directory: {}\n",
-                                    dir_entry.file_name().to_string_lossy()
+                                debug!(
+                                    path = %dir_entry.path().display(),
+                                    "Skipping directory"
                                 );
                             }
 
@@ -239,28 +866,52 @@ fn get_directories(
                                     dir_entry.file_name().to_string_lossy().ends_with(ext)
                                 }) {
                                     dir_entry.read_children_path = None;
-                                    print!(
-                                        "Skipping file with extension: {}\n",
-                                        dir_entry.file_name().to_string_lossy()
+                                    debug!(
+                                        path = %dir_entry.path().display(),
+                                        "Skipping file with extension"
                                     );
                                 } else if skip_patterns.iter().any(|pattern| {
                                     dir_entry.file_name().to_string_lossy().contains(pattern)
                                 }) {
                                     dir_entry.read_children_path = None;
-                                    print!(
-                                        "Skipping file matching pattern: {}\n",
-                                        dir_entry.file_name().to_string_lossy()
+                                    debug!(
+                                        path = %dir_entry.path().display(),
+                                        "Skipping file matching pattern"
                                     );
                                 }
                             }
+
+                            // links are named like directories, so the same names are skipped
+                            if dir_entry.file_type().is_symlink()
+                                && skip_directories.iter().any(|dir| {
+                                    dir_entry.file_name().to_string_lossy().contains(dir)
+                                })
+                            {
+                                return;
+                            }
+                        }
+
+                        if follow_symlinks != config::FollowSymlinks::Never
+                            && dir_entry.file_type().is_symlink()
+                            && follow_symlink(
+                                &dir_entry.path(),
+                                follow_symlinks,
+                                &roots,
+                                ancestors,
+                                root_device,
+                            )
+                        {
+                            trace!(path = %dir_entry.path().display(), "Following symlink");
+                            dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
                         }
                     }
                 });
             })
-            .skip_hidden(false);
+            .skip_hidden(false)
+            .max_depth(max_depth);
 
     let elapsed = now.elapsed();
-    println!("Search completed in: {:.10?}", elapsed);
+    debug!("Search completed in: {:.10?}", elapsed);
     // print!("Search completed. ");
     // Implement search logic here
 
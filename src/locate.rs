@@ -0,0 +1,226 @@
+use std::path::PathBuf;
+
+use libsql::{Connection, Value};
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::paths::path_from_bytes;
+
+// locate/mlocate compatible matching, for `rustsearch locate`
+//
+// like mlocate, a pattern without glob characters matches anywhere in the path (it's read as
+// *PATTERN*), and one with *, ?, [ or \ has to match the whole path. -b matches the file name
+// instead. a path is printed if it matches any pattern, in the order it was indexed. patterns are
+// matched against the path itself, not its display form, which doubles backslashes
+
+#[derive(Debug, Clone, Default)]
+pub struct LocateOptions {
+    // globs or plain substrings
+    pub patterns: Vec<String>,
+    pub regexes: Vec<String>,
+    pub ignore_case: bool,
+    pub basename: bool,
+    pub limit: Option<usize>,
+    // skip paths that have been deleted since they were indexed
+    pub existing: bool,
+}
+
+pub struct Locator {
+    matchers: RegexSet,
+    basename: bool,
+    limit: Option<usize>,
+    existing: bool,
+    // a cheap condition every match has to pass, so most rows never leave SQLite
+    prefilter: Option<(String, Vec<Value>)>,
+}
+
+impl Locator {
+    pub fn new(options: &LocateOptions) -> Result<Self, regex::Error> {
+        let matchers = options
+            .patterns
+            .iter()
+            .map(|pattern| glob_to_regex(pattern))
+            .chain(options.regexes.iter().cloned());
+        let matchers = RegexSetBuilder::new(matchers)
+            .case_insensitive(options.ignore_case)
+            .build()?;
+
+        Ok(Self {
+            matchers,
+            basename: options.basename,
+            limit: options.limit,
+            existing: options.existing,
+            prefilter: prefilter(options),
+        })
+    }
+
+    // calls `found` with every matching path, returns how many there were
+    pub async fn run<E: From<libsql::Error>>(
+        &self,
+        conn: &Connection,
+        mut found: impl FnMut(PathBuf) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        let (sql, params) = match &self.prefilter {
+            Some((condition, params)) => (
+                format!("SELECT path, path_bytes FROM files WHERE {}", condition),
+                params.clone(),
+            ),
+            None => ("SELECT path, path_bytes FROM files".to_string(), Vec::new()),
+        };
+
+        let mut count = 0;
+        if self.limit == Some(0) {
+            return Ok(count);
+        }
+        let mut rows = conn.query(&sql, params).await?;
+        while let Some(row) = rows.next().await? {
+            let path = match row.get::<Option<Vec<u8>>>(1)? {
+                Some(bytes) => path_from_bytes(&bytes),
+                None => PathBuf::from(row.get::<String>(0)?),
+            };
+            let text = if self.basename {
+                path.file_name().unwrap_or_default().to_string_lossy()
+            } else {
+                path.to_string_lossy()
+            };
+            if !self.matchers.is_match(&text) {
+                continue;
+            }
+            if self.existing && !path.exists() {
+                continue;
+            }
+
+            found(path)?;
+            count += 1;
+            if Some(count) == self.limit {
+                break;
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '\\'])
+}
+
+// fnmatch without FNM_PATHNAME, so * and ? match / too. a [ without a closing ] is literal
+pub fn glob_to_regex(pattern: &str) -> String {
+    if !is_glob(pattern) {
+        return regex::escape(pattern);
+    }
+
+    let mut regex = String::from("^");
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '[' => match class_end(&chars, i) {
+                Some(end) => {
+                    regex.push('[');
+                    let mut start = i + 1;
+                    if matches!(chars[start], '!' | '^') {
+                        regex.push('^');
+                        start += 1;
+                    }
+                    for &c in &chars[start..end] {
+                        // everything but ranges is literal inside a glob class
+                        if c != '-' && !c.is_alphanumeric() {
+                            regex.push('\\');
+                        }
+                        regex.push(c);
+                    }
+                    regex.push(']');
+                    i = end;
+                }
+                None => regex.push_str(r"\["),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex.push('$');
+    regex
+}
+
+// index of the ] closing the class opened at `start`, a ] right after [ or [! is part of the class
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if matches!(chars.get(i), Some('!' | '^')) {
+        i += 1;
+    }
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    (i..chars.len()).find(|&i| chars[i] == ']')
+}
+
+// the longest run of plain characters in a glob, every path it matches contains it
+fn longest_literal(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut longest = String::new();
+    let mut current = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                current.push(chars[i]);
+            }
+            '*' | '?' | '[' => {
+                if current.len() > longest.len() {
+                    longest = std::mem::take(&mut current);
+                }
+                current.clear();
+                if chars[i] == '[' {
+                    i = class_end(&chars, i).unwrap_or(i);
+                }
+            }
+            c => current.push(c),
+        }
+        i += 1;
+    }
+    if current.len() > longest.len() {
+        longest = current;
+    }
+    longest
+}
+
+// OR of one condition per pattern. regexes can't be turned into one, and LIKE only folds the
+// case of ASCII, so either of those means every row has to be checked
+fn prefilter(options: &LocateOptions) -> Option<(String, Vec<Value>)> {
+    if !options.regexes.is_empty() || options.patterns.is_empty() {
+        return None;
+    }
+    let column = if options.basename { "filename" } else { "path" };
+
+    let mut params = Vec::new();
+    let mut conditions = Vec::new();
+    for pattern in &options.patterns {
+        // the column has the display form, see paths.rs
+        let literal = longest_literal(pattern).replace('\\', r"\\");
+        if literal.is_empty() {
+            return None;
+        }
+        if options.ignore_case {
+            if !literal.is_ascii() {
+                return None;
+            }
+            let escaped = literal
+                .replace('\\', r"\\")
+                .replace('%', r"\%")
+                .replace('_', r"\_");
+            params.push(Value::Text(format!("%{}%", escaped)));
+            conditions.push(format!("{} LIKE ?{} ESCAPE '\\'", column, params.len()));
+        } else {
+            params.push(Value::Text(literal));
+            conditions.push(format!("instr({}, ?{}) > 0", column, params.len()));
+        }
+    }
+    Some((conditions.join(" OR "), params))
+}
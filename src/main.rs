@@ -1,60 +1,239 @@
 use std::{
-    io::{self, stdin},
-    sync::{Arc, Mutex, MutexGuard},
-    thread,
+    ffi::OsString,
+    fs::File,
+    io::{self, stdin, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use diesel::row;
-use jwalk::WalkDirGeneric;
-use libsql::{params, Connection, Result as LibSqlResult};
-use search::FileEntry;
-use tokio;
-
-mod database;
-mod search;
-use crate::database::create_schema;
-
-fn get_directories() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
-    println!("Searching for files in home directory... ");
-    let now = std::time::Instant::now();
-    let walk_dir = WalkDirGeneric::<(usize, bool)>::new("/".to_string())
-        .process_read_dir(|_depth, _path, _read_dir_state, children| {
-            // 3. Custom skip
-            children.iter_mut().for_each(|dir_entry_result| {
-                if let Ok(dir_entry) = dir_entry_result {
-                    if dir_entry.file_name() == "AppData" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "node_modules" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "target" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "vendor" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == "build" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.file_name() == ".cache" {
-                        dir_entry.read_children_path = None;
-                    }
-                    if dir_entry.depth == 10 {
-                        dir_entry.read_children_path = None;
-                    }
-                }
-                // println!("Processing directory: {}", _path.display());
-            });
-        })
-        .skip_hidden(false);
+use clap::{Args, Parser, Subcommand};
+use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+use regex::bytes::RegexBuilder;
+use rustsearch::archive::{self, ArchiveChange, ArchiveFormat};
+use rustsearch::changes::Change;
+use rustsearch::database::IndexRunStatus;
+use rustsearch::dates::TimeRange;
+use rustsearch::grep::{self, GrepOptions};
+use rustsearch::locate::{LocateOptions, Locator};
+use rustsearch::paths;
+use rustsearch::permissions::format_mode;
+use rustsearch::progress::IndexEvent;
+use rustsearch::search::SearchQuery;
+use rustsearch::search::{FileEntry, SearchExplain, Sort};
+use rustsearch::sizes::format_size;
+use rustsearch::users::Users;
+use rustsearch::{QueryRecord, SavedSearch, SearchEngine, Stats};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing_subscriber::{filter::EnvFilter, fmt, prelude::*};
+
+#[derive(Parser)]
+#[command(name = "rustsearch", about = "Fast file search with a SQLite index")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Log more, -v for info, -vv for debug and -vvv for trace
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Log less, -q for errors only and -qq for nothing at all
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
+    /// Also write logs to this file, one JSON object per line
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+}
 
-    let elapsed = now.elapsed();
-    println!("Search completed in: {:.10?}", elapsed);
-    // print!("Search completed. ");
-    // Implement search logic here
+#[derive(Subcommand)]
+enum Command {
+    /// Index the filesystem, or resume an interrupted index, showing progress until it's done
+    Index,
+    /// Search the index and print the matching paths, one per line
+    Search {
+        query: String,
+        #[command(flatten)]
+        output: SearchOutput,
+    },
+    /// Open a file with its default app and remember it, so it ranks higher in later searches.
+    /// Anything that isn't an existing path is searched for and the top result is opened
+    Open {
+        path_or_query: String,
+        /// Print the path instead of opening it, e.g. `vim "$(rustsearch open --print main.rs)"`
+        #[arg(long)]
+        print: bool,
+    },
+    /// Save a search under a name, e.g. `rustsearch save big-logs 'ext:log size:>100mb'`
+    Save {
+        name: String,
+        /// The query to save, saving over an existing name replaces it
+        #[arg(required_unless_present = "delete")]
+        query: Option<String>,
+        /// Delete the saved search instead
+        #[arg(short, long, conflicts_with = "query")]
+        delete: bool,
+    },
+    /// Run a saved search, e.g. `rustsearch run big-logs`
+    Run {
+        name: String,
+        #[command(flatten)]
+        output: SearchOutput,
+    },
+    /// List the saved searches
+    Saved {
+        /// Print one JSON object per saved search
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the most recent searches, newest first
+    History {
+        /// Number of searches to show, 0 for all that are kept
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Print one JSON object per search
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find paths like locate/mlocate, also used when rustsearch is run through a `locate` symlink
+    Locate {
+        /// A path is printed if it matches any of these. Plain text matches anywhere in the path,
+        /// globs (*, ?, [...]) have to match the whole path
+        #[arg(required_unless_present = "regexp")]
+        patterns: Vec<String>,
+        /// Ignore case
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+        /// Match only the file name, not the whole path
+        #[arg(short = 'b', long)]
+        basename: bool,
+        /// Search for a regex, can be given more than once
+        #[arg(short = 'r', long = "regexp", value_name = "REGEXP")]
+        regexp: Vec<String>,
+        /// Read the patterns as regexes instead of globs
+        #[arg(long)]
+        regex: bool,
+        /// Only print how many paths matched
+        #[arg(short = 'c', long)]
+        count: bool,
+        /// Stop after this many paths
+        #[arg(short = 'l', short_alias = 'n', long, value_name = "N")]
+        limit: Option<usize>,
+        /// Separate paths with NUL instead of newline
+        #[arg(short = '0', long)]
+        null: bool,
+        /// Only print paths that still exist
+        #[arg(short = 'e', long)]
+        existing: bool,
+    },
+    /// Index once without any output, for cron jobs and scripts that call updatedb
+    Updatedb,
+    /// Load the paths from a plocate or mlocate database, for an index before the first one finishes.
+    /// Sizes and times are filled in when `index` gets to each file
+    ImportLocate {
+        /// The database to read, by default /var/lib/plocate/plocate.db or /var/lib/mlocate/mlocate.db
+        path: Option<PathBuf>,
+    },
+    /// Write the index to a compressed archive that `import` can load on another machine
+    Export {
+        output: PathBuf,
+        /// ndjson or snapshot, by default snapshot for a .snapshot file and ndjson otherwise
+        #[arg(short, long)]
+        format: Option<ArchiveFormat>,
+        /// Only export files under this directory, can be given more than once
+        #[arg(short, long = "root", value_name = "DIR")]
+        roots: Vec<PathBuf>,
+    },
+    /// Merge an archive made by `export` into the index
+    Import { archive: PathBuf },
+    /// Show the files added, removed and changed between two archives
+    Diff {
+        before: PathBuf,
+        after: PathBuf,
+        /// Print one JSON object per change
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show what re-indexing and the watcher saw change, e.g. `rustsearch changes --since today ~/Projects`
+    Changes {
+        /// Filters like a search, plain words have to appear somewhere in the path
+        #[arg(default_value = "")]
+        query: String,
+        /// How far back to look: 1h, 2d, today, last-week, 2025-01-01 ...
+        #[arg(long, default_value = "1d", value_parser = parse_since)]
+        since: i64,
+        /// Only show the most recent N changes
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+        /// Print one JSON object per change
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show what's in the index and how the last index went
+    Stats {
+        /// Print the stats as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Search the contents of indexed files, e.g. `rustsearch grep TODO 'ext:rs path:src'`
+    Grep {
+        /// Regex to search for
+        pattern: String,
+        /// Index query used to pick the candidate files
+        #[arg(default_value = "")]
+        query: String,
+        /// Case insensitive matching
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+        /// Lines of context after each match
+        #[arg(short = 'A', long, default_value_t = 0)]
+        after_context: usize,
+        /// Lines of context before each match
+        #[arg(short = 'B', long, default_value_t = 0)]
+        before_context: usize,
+        /// Lines of context before and after each match
+        #[arg(short = 'C', long)]
+        context: Option<usize>,
+        /// Print one JSON object per line instead of path:line:col:text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the database and that the search index matches the indexed files
+    IntegrityCheck {
+        /// Rebuild the search index if it is out of sync
+        #[arg(long)]
+        repair: bool,
+    },
+    #[cfg(unix)]
+    /// Share the index with other users over a unix socket, each only sees what they could find
+    /// themselves. Send one query per line, each is answered with one line of JSON
+    Serve {
+        /// Where to create the socket
+        #[arg(long, default_value = "rustsearch.sock")]
+        socket: PathBuf,
+    },
+}
 
-    Ok(walk_dir)
+// how search and run print their results
+#[derive(Args)]
+struct SearchOutput {
+    /// Separate paths with NUL instead of newline, the exact bytes of each path are written
+    #[arg(short = '0', long)]
+    print0: bool,
+    /// Print one JSON object per result, paths that aren't UTF-8 are base64 encoded bytes
+    #[arg(long, conflicts_with = "print0")]
+    json: bool,
+    /// Show the mode, owner, group, size and modified time in front of each path
+    #[arg(short, long, conflicts_with_all = ["print0", "json"])]
+    long: bool,
+    /// Maximum number of results, 0 for no limit
+    #[arg(short = 'n', long, default_value_t = 50)]
+    limit: usize,
+    /// Order results by relevance, name, path, size, modified, extension or depth,
+    /// e.g. size-desc. `sort:` in the query does the same
+    #[arg(short, long)]
+    sort: Option<Sort>,
+    /// Show how the query was parsed, the SQL it ran as, its query plan and timings on stderr
+    #[arg(long)]
+    explain: bool,
 }
 
 // async fn _main() {
@@ -91,7 +270,7 @@ fn get_directories() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
 //     use std::path::Path;
 //     let db_path = "search.db";
 //     if Path::new(db_path).exists() {
-//         println!("Database '{}' already exists. Overwrite? (y/N): ", db_path);
+//         eprintln!("Database '{}' already exists. Overwrite? (y/N): ", db_path);
 //         let mut answer = String::new();
 //         stdin().read_line(&mut answer).expect("Failed to read line");
 //         if answer.trim().eq_ignore_ascii_case("y") {
@@ -102,7 +281,7 @@ fn get_directories() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
 //     }
 
 //     if should_index {
-//         println!("Starting indexing of files...");
+//         eprintln!("Starting indexing of files...");
 //         let conn_worker = conn.clone();
 
 //         // create the database
@@ -116,7 +295,7 @@ fn get_directories() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
 //         )
 //         .await;
 //     } else {
-//         println!("Skipping indexing of files.");
+//         eprintln!("Skipping indexing of files.");
 //     }
 
 //     let mut query_formatter = search::SearchQuery::new();
@@ -189,23 +368,31 @@ fn get_directories() -> Result<WalkDirGeneric<(usize, bool)>, std::io::Error> {
 //     // }
 // }
 
-use rustsearch::SearchEngine;
-use std::path::Path;
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Rust Search Engine");
+    let cli = Cli::parse_from(personality_args());
+    init_logging(cli.verbose, cli.quiet, cli.log_file.as_deref())?;
+
+    if let Some(command) = cli.command {
+        return run_command(command).await;
+    }
+
+    // stdout is only for results, everything else goes to stderr
+    eprintln!("Rust Search Engine");
 
     // Create search engine
-    let engine = SearchEngine::new("search.db", Some(true)).await?;
+    let engine = SearchEngine::new("search.db").await?;
     engine.start_watcher();
 
     // Check if database exists
     let mut should_index = true;
     let db_path = "search.db";
 
-    if Path::new(db_path).exists() {
-        println!("Database '{}' already exists. Overwrite? (y/N): ", db_path);
+    // an index that was killed part way through is always resumed, no need to ask
+    if engine.interrupted_index().await?.is_some() {
+        eprintln!("Found an interrupted index, resuming it.");
+    } else if std::path::Path::new(db_path).exists() {
+        eprintln!("Database '{}' already exists. Overwrite? (y/N): ", db_path);
         let mut answer = String::new();
         stdin().read_line(&mut answer)?;
         if !answer.trim().eq_ignore_ascii_case("y") {
@@ -215,15 +402,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Index files if needed
     if should_index {
-        println!("Starting indexing of files...");
+        // a fresh index starts from the system's locate database so searches work straight away
+        if engine.file_count().await? == 0 {
+            match engine.import_locate_db(None).await {
+                Ok(import) => eprintln!(
+                    "Imported {} files from the {:?} database to search while indexing",
+                    import.files_imported, import.format
+                ),
+                Err(e) => tracing::info!("No locate database imported: {}", e),
+            }
+        }
+        eprintln!("Starting indexing of files...");
+        // a progress bar would fight with the prompt, so just say when it's done
+        let mut events = engine.subscribe_progress();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(IndexEvent::Finished {
+                        status,
+                        files_committed,
+                        errors,
+                        elapsed,
+                    }) => {
+                        print_index_summary(status, files_committed, errors, elapsed);
+                        break;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
         engine.index_directories().await;
     } else {
-        println!("Skipping indexing of files.");
+        eprintln!("Skipping indexing of files.");
     }
 
     // Command loop
+    let mut last_query: Option<String> = None;
+    let mut last_results: Vec<FileEntry> = Vec::new();
     loop {
-        println!("\nEnter search query (or 'exit' to quit):");
+        eprintln!("\nEnter search query (':help' for commands, 'exit' to quit):");
         let mut input = String::new();
         stdin().read_line(&mut input)?;
         let input = input.trim();
@@ -231,23 +449,862 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         if input == "exit" {
             break;
         }
+        if input == ":help" {
+            eprintln!(
+                ":stats         index stats\n\
+                 :history       recent searches\n\
+                 !!             run the last search again\n\
+                 !N             run search N from :history\n\
+                 :save NAME     save the last search as NAME\n\
+                 :run NAME      run a saved search\n\
+                 :saved         list the saved searches\n\
+                 :open N        open result N of the last search"
+            );
+            continue;
+        }
+        if input == ":stats" {
+            print_stats(&engine.stats().await?, &mut io::stdout().lock())?;
+            continue;
+        }
+        if input == ":history" {
+            // oldest first so the newest ends up next to the prompt
+            let mut history = engine.query_history(20).await?;
+            history.reverse();
+            print_history(&history, false, &mut io::stdout().lock())?;
+            continue;
+        }
+        if input == ":saved" {
+            print_saved_searches(
+                &engine.saved_searches().await?,
+                false,
+                &mut io::stdout().lock(),
+            )?;
+            continue;
+        }
+        if let Some(number) = input.strip_prefix(":open ") {
+            let entry = number
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| last_results.get(n.checked_sub(1)?));
+            match entry {
+                Some(entry) => {
+                    engine.record_selection(&entry.path).await?;
+                    if let Err(e) = open_path(&entry.path) {
+                        eprintln!("{}", e);
+                    }
+                }
+                None => eprintln!("No result {} in the last search", number.trim()),
+            }
+            continue;
+        }
+        if let Some(name) = input.strip_prefix(":save ") {
+            match &last_query {
+                Some(query) => {
+                    engine.save_search(name.trim(), query).await?;
+                    eprintln!("Saved '{}' as {}", query, name.trim());
+                }
+                None => eprintln!("Nothing to save yet, run a search first"),
+            }
+            continue;
+        }
+
+        // recalled searches are run as if they had been typed in
+        let query = match recall(&engine, input).await? {
+            Some(Recall::Found(query)) => {
+                eprintln!("> {}", query);
+                query
+            }
+            Some(Recall::Missing) => {
+                eprintln!("Nothing to recall for {}", input);
+                continue;
+            }
+            None => input.to_string(),
+        };
+        let input = query.as_str();
 
-        // Search files
-        let results = engine.search_files(input).await?;
+        // Search files, a mistyped filter shouldn't end the session
+        let results = match engine.search_files(input, None).await {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        engine.record_query(input, results.len()).await?;
+        last_query = Some(input.to_string());
+        last_results.clear();
 
         if results.is_empty() {
-            println!("No results found for query: {}", input);
+            eprintln!("No results found for query: {}", input);
             continue;
         }
 
-        println!("Found {} results:", results.len());
-        for entry in &results {
+        eprintln!("Found {} results:", results.len());
+        for (number, entry) in results.iter().enumerate() {
             println!(
-                "Path: {}\nFilename: {}\nExtension: {}\nSize: {}\nModified At: {}\n",
-                entry.path, entry.filename, entry.extension, entry.size, entry.modified_at
+                "[{}] Path: {}\nFilename: {}\nExtension: {}\nSize: {}\nModified At: {}\n",
+                number + 1,
+                entry.path.display(),
+                entry.filename,
+                entry.extension,
+                format_size(entry.size),
+                entry.modified_at
             );
         }
+        last_results = results;
+    }
+
+    Ok(())
+}
+
+// run as `locate` or `updatedb` through a symlink, rustsearch acts as that command
+fn personality_args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let personality = args
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .and_then(|name| name.to_str())
+        .filter(|name| matches!(*name, "locate" | "updatedb"))
+        .map(OsString::from);
+    if let Some(personality) = personality {
+        args[0] = OsString::from("rustsearch");
+        args.insert(1, personality);
+    }
+    args
+}
+
+enum Recall {
+    Found(String),
+    Missing,
+}
+
+// !! for the last search, !N for an entry in :history, :run NAME for a saved search
+// None when the input isn't a recall at all and should be searched for as it is
+async fn recall(engine: &SearchEngine, input: &str) -> Result<Option<Recall>, libsql::Error> {
+    let query = if input == "!!" {
+        engine
+            .query_history(1)
+            .await?
+            .pop()
+            .map(|record| record.query)
+    } else if let Some(id) = input.strip_prefix('!').and_then(|id| id.parse().ok()) {
+        engine.history_query(id).await?
+    } else if let Some(name) = input.strip_prefix(":run ") {
+        engine.saved_search(name.trim()).await?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(query.map_or(Recall::Missing, Recall::Found)))
+}
+
+async fn run_command(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Index => {
+            let engine = SearchEngine::new("search.db").await?;
+            let events = engine.subscribe_progress();
+            engine.index_directories().await;
+
+            if show_progress(events).await != Some(IndexRunStatus::Completed) {
+                std::process::exit(1);
+            }
+        }
+        Command::Search { query, output } => {
+            let engine = SearchEngine::new("search.db").await?;
+            run_search(&engine, &query, &output).await?;
+        }
+        Command::Open {
+            path_or_query,
+            print,
+        } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let path = if Path::new(&path_or_query).exists() {
+                std::path::absolute(&path_or_query)?
+            } else {
+                let mut query = SearchQuery::new().with_limit(Some(1));
+                query.make_query(path_or_query.trim())?;
+                let results = engine.search(&query).await?;
+                engine
+                    .record_query(path_or_query.trim(), results.len())
+                    .await?;
+                match results.into_iter().next() {
+                    Some(entry) => entry.path,
+                    None => {
+                        eprintln!("No results found for query: {}", path_or_query);
+                        std::process::exit(1);
+                    }
+                }
+            };
+
+            engine.record_selection(&path).await?;
+            if print {
+                let mut out = io::stdout().lock();
+                out.write_all(paths::os_str_bytes(path.as_os_str()))?;
+                writeln!(out)?;
+            } else {
+                open_path(&path)?;
+            }
+        }
+        Command::Save {
+            name,
+            query,
+            delete,
+        } => {
+            let engine = SearchEngine::new("search.db").await?;
+            if delete {
+                if !engine.delete_saved_search(&name).await? {
+                    eprintln!("No saved search called '{}'", name);
+                    std::process::exit(1);
+                }
+            } else if let Some(query) = query {
+                engine.save_search(&name, query.trim()).await?;
+            }
+        }
+        Command::Run { name, output } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let Some(query) = engine.saved_search(&name).await? else {
+                eprintln!("No saved search called '{}'", name);
+                std::process::exit(1);
+            };
+            run_search(&engine, &query, &output).await?;
+        }
+        Command::Saved { json } => {
+            let engine = SearchEngine::new("search.db").await?;
+            print_saved_searches(
+                &engine.saved_searches().await?,
+                json,
+                &mut io::stdout().lock(),
+            )?;
+        }
+        Command::History { limit, json } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let limit = if limit == 0 { usize::MAX } else { limit };
+            let history = engine.query_history(limit).await?;
+            print_history(&history, json, &mut io::stdout().lock())?;
+        }
+        Command::Locate {
+            patterns,
+            ignore_case,
+            basename,
+            regexp,
+            regex,
+            count,
+            limit,
+            null,
+            existing,
+        } => {
+            let (patterns, mut regexes) = if regex {
+                (Vec::new(), patterns)
+            } else {
+                (patterns, Vec::new())
+            };
+            regexes.extend(regexp);
+            let locator = Locator::new(&LocateOptions {
+                patterns,
+                regexes,
+                ignore_case,
+                basename,
+                limit,
+                existing,
+            })?;
+
+            let engine = SearchEngine::new("search.db").await?;
+            let mut out = io::stdout().lock();
+            let found = engine
+                .locate(&locator, |path| -> Result<(), Box<dyn std::error::Error>> {
+                    if count {
+                        return Ok(());
+                    }
+                    if null {
+                        paths::write_path_nul(&path, &mut out)?;
+                    } else {
+                        out.write_all(paths::os_str_bytes(path.as_os_str()))?;
+                        writeln!(out)?;
+                    }
+                    Ok(())
+                })
+                .await?;
+            if count {
+                writeln!(out, "{}", found)?;
+            }
+            out.flush()?;
+
+            // like locate, nothing found is a failure
+            if found == 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Updatedb => {
+            let engine = SearchEngine::new("search.db").await?;
+            let mut events = engine.subscribe_progress();
+            engine.index_directories().await;
+
+            loop {
+                match events.recv().await {
+                    Ok(IndexEvent::Finished { status, .. }) => {
+                        if status != IndexRunStatus::Completed {
+                            std::process::exit(1);
+                        }
+                        break;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => std::process::exit(1),
+                }
+            }
+        }
+        Command::ImportLocate { path } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let import = engine.import_locate_db(path.as_deref()).await?;
+            eprintln!(
+                "Imported {} new files from {} paths in the {:?} database",
+                import.files_imported, import.paths_read, import.format
+            );
+        }
+        Command::Export {
+            output,
+            format,
+            roots,
+        } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let format = format.unwrap_or_else(|| archive::format_for_path(&output));
+            let files = engine.export(&output, format, &roots).await?;
+            eprintln!("Exported {} files to {}", files, output.display());
+        }
+        Command::Import { archive } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let import = engine.import(&archive).await?;
+            eprintln!(
+                "Imported {} files under {} exported {}",
+                import.files,
+                import.header.roots.join(", "),
+                format_time(import.header.exported_at)
+            );
+        }
+        Command::Diff {
+            before,
+            after,
+            json,
+        } => {
+            let changes = archive::diff(&before, &after)?;
+            let mut out = io::stdout().lock();
+            for change in &changes {
+                print_change(change, json, &mut out)?;
+            }
+        }
+        Command::Changes {
+            query,
+            since,
+            limit,
+            json,
+        } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let changes = engine.changes(since, &query, limit).await?;
+            let mut out = io::stdout().lock();
+            for change in &changes {
+                if json {
+                    serde_json::to_writer(&mut out, change)?;
+                    writeln!(out)?;
+                } else {
+                    print_logged_change(change, &mut out)?;
+                }
+            }
+        }
+        Command::Stats { json } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let stats = engine.stats().await?;
+
+            let mut out = io::stdout().lock();
+            if json {
+                serde_json::to_writer_pretty(&mut out, &stats)?;
+                writeln!(out)?;
+            } else {
+                print_stats(&stats, &mut out)?;
+            }
+        }
+        Command::Grep {
+            pattern,
+            query,
+            ignore_case,
+            after_context,
+            before_context,
+            context,
+            json,
+        } => {
+            let pattern = RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()?;
+            let options = GrepOptions {
+                pattern,
+                before_context: context.unwrap_or(before_context),
+                after_context: context.unwrap_or(after_context),
+            };
+
+            let engine = SearchEngine::new("search.db").await?;
+            let results = engine.grep(&query, options).await?;
+
+            let mut out = io::stdout().lock();
+            if json {
+                grep::print_results_json(&results, &mut out)?;
+            } else {
+                grep::print_results(&results, &mut out)?;
+            }
+        }
+        Command::IntegrityCheck { repair } => {
+            let engine = SearchEngine::new("search.db").await?;
+            let report = engine.integrity_check(repair).await?;
+
+            if report.database_errors.is_empty() {
+                println!("database: ok");
+            }
+            for error in &report.database_errors {
+                println!("database: {}", error);
+            }
+            match &report.fts_error {
+                Some(error) if report.repaired => {
+                    println!("search index: {} (rebuilt)", error)
+                }
+                Some(error) => println!("search index: {}", error),
+                None => println!("search index: ok"),
+            }
+
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+        }
+        #[cfg(unix)]
+        Command::Serve { socket } => {
+            use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+            let engine = Arc::new(SearchEngine::new("search.db").await?);
+            // left behind by a daemon that didn't get to clean up, anything else is kept
+            if std::fs::symlink_metadata(&socket).is_ok_and(|m| m.file_type().is_socket()) {
+                std::fs::remove_file(&socket)?;
+            }
+            let listener = tokio::net::UnixListener::bind(&socket)?;
+            // anyone can connect, what they get back depends on who they are
+            std::fs::set_permissions(&socket, std::fs::Permissions::from_mode(0o666))?;
+            eprintln!("Serving searches on {}", socket.display());
+            engine.serve(listener).await?;
+        }
     }
 
     Ok(())
 }
+
+// runs a query for search or run, prints the results and adds it to the history
+async fn run_search(
+    engine: &SearchEngine,
+    query: &str,
+    output: &SearchOutput,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let limit = (output.limit > 0).then_some(output.limit);
+    let results = if output.explain {
+        let explained = engine.explain(query, limit, output.sort).await?;
+        print_explain(&explained, &mut io::stderr().lock())?;
+        explained.results
+    } else {
+        let mut search_query = SearchQuery::new().with_limit(limit).with_sort(output.sort);
+        search_query.make_query(query.trim())?;
+        engine.search(&search_query).await?
+    };
+    engine.record_query(query.trim(), results.len()).await?;
+
+    // names are looked up once, and only for ls -l style output
+    let users = if output.long {
+        Users::load()
+    } else {
+        Users::default()
+    };
+    let mut out = io::stdout().lock();
+    for entry in &results {
+        if output.print0 {
+            paths::write_path_nul(&entry.path, &mut out)?;
+        } else if output.json {
+            serde_json::to_writer(&mut out, entry)?;
+            writeln!(out)?;
+        } else if output.long {
+            print_long(entry, &users, &mut out)?;
+        } else {
+            writeln!(out, "{}", entry.path.display())?;
+        }
+    }
+    Ok(())
+}
+
+// draws the indexer's progress on stderr until it finishes, returns how it finished
+// the bar is hidden when stderr isn't a terminal, only the summary is printed then
+async fn show_progress(mut events: broadcast::Receiver<IndexEvent>) -> Option<IndexRunStatus> {
+    let bar = ProgressBar::new_spinner();
+    bar.enable_steady_tick(Duration::from_millis(120));
+    let mut errors = 0;
+    let mut scanned = String::new();
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            // only the latest numbers matter, missed events can be skipped
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        };
+
+        match event {
+            IndexEvent::Started {
+                resumed,
+                expected_files,
+                ..
+            } => {
+                if resumed {
+                    bar.println("Resuming interrupted index");
+                }
+                // the previous run's size gives the bar a length, without it there's only a count
+                let template = match expected_files {
+                    Some(expected) => {
+                        bar.set_length(expected);
+                        "{spinner} [{elapsed_precise}] [{wide_bar}] {pos}/{len} files {msg}"
+                    }
+                    None => "{spinner} [{elapsed_precise}] {pos} files {msg}",
+                };
+                bar.set_style(ProgressStyle::with_template(template).unwrap());
+            }
+            IndexEvent::Queued {
+                directories_scanned,
+                ..
+            } => {
+                scanned = format!("{} directories", directories_scanned);
+                bar.set_message(scanned.clone());
+            }
+            IndexEvent::Committed {
+                files_committed,
+                eta,
+            } => {
+                bar.set_position(files_committed);
+                let mut message = scanned.clone();
+                if errors > 0 {
+                    message.push_str(&format!(", {} errors", errors));
+                }
+                if let Some(eta) = eta {
+                    message.push_str(&format!(", about {} left", HumanDuration(eta)));
+                }
+                bar.set_message(message);
+            }
+            // logged as they happen, see progress.rs
+            IndexEvent::Error { .. } => errors += 1,
+            IndexEvent::Finished {
+                status,
+                files_committed,
+                errors,
+                elapsed,
+            } => {
+                bar.finish_and_clear();
+                print_index_summary(status, files_committed, errors, elapsed);
+                return Some(status);
+            }
+        }
+    }
+}
+
+fn print_index_summary(status: IndexRunStatus, files: u64, errors: u64, elapsed: Duration) {
+    match status {
+        IndexRunStatus::Completed => eprintln!("Indexed {} files in {:.2?}", files, elapsed),
+        _ => eprintln!("Indexing failed after {} files", files),
+    }
+    if errors > 0 {
+        eprintln!("{} files or directories could not be indexed", errors);
+    }
+}
+
+// logs go to stderr, warnings and errors by default
+// RUST_LOG takes over from -v/-q when it's set, e.g. RUST_LOG=rustsearch::database=debug
+fn init_logging(
+    verbose: u8,
+    quiet: u8,
+    log_file: Option<&std::path::Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let level = |quiet: u8| match (quiet, verbose) {
+        (2.., _) => "off",
+        (1, _) => "error",
+        (_, 0) => "warn",
+        (_, 1) => "info",
+        (_, 2) => "debug",
+        _ => "trace",
+    };
+    // other crates stay at warn unless RUST_LOG says otherwise, -vvv from libsql is a lot
+    let filter = |level: &str| {
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(format!("warn,rustsearch={}", level)))
+    };
+
+    let stderr = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false)
+        .with_filter(filter(level(quiet)));
+    let file = match log_file {
+        Some(path) => Some(
+            fmt::layer()
+                .json()
+                .with_writer(Mutex::new(
+                    File::options().create(true).append(true).open(path)?,
+                ))
+                // -q is about the terminal, the file keeps logging
+                .with_filter(filter(level(0))),
+        ),
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .init();
+    Ok(())
+}
+
+fn print_stats(stats: &Stats, out: &mut impl Write) -> io::Result<()> {
+    let database = &stats.database;
+    writeln!(
+        out,
+        "files:        {} in {} directories",
+        database.files, database.directories
+    )?;
+    writeln!(
+        out,
+        "database:     {}",
+        format_size(database.database_bytes as u64)
+    )?;
+    writeln!(
+        out,
+        "search index: {}",
+        format_size(database.fts_bytes as u64)
+    )?;
+
+    match &database.last_run {
+        Some(run) => writeln!(
+            out,
+            "last index:   {} files in {}",
+            run.files_indexed,
+            HumanDuration(Duration::from_secs(
+                (run.finished_at.unwrap_or(run.started_at) - run.started_at).max(0) as u64
+            ))
+        )?,
+        None => writeln!(out, "last index:   never completed")?,
+    }
+    if stats.index.indexing {
+        writeln!(
+            out,
+            "indexing:     {} files so far",
+            stats.index.files_committed
+        )?;
+    }
+    match &stats.watching {
+        Some(path) => writeln!(out, "watcher:      watching {}", path.display())?,
+        None => writeln!(out, "watcher:      not running")?,
+    }
+
+    writeln!(out, "\nroots:")?;
+    for root in &database.roots {
+        writeln!(
+            out,
+            "  {:<24} {:>10} files {:>8} directories {:>12}",
+            root.root,
+            root.files,
+            root.directories,
+            format_size(root.bytes as u64)
+        )?;
+    }
+
+    for (title, extensions) in [
+        (
+            "top extensions by count:",
+            &database.top_extensions_by_count,
+        ),
+        ("top extensions by size:", &database.top_extensions_by_bytes),
+    ] {
+        writeln!(out, "\n{}", title)?;
+        for extension in extensions {
+            let name = match extension.extension.as_str() {
+                "" => "(none)",
+                ext => ext,
+            };
+            writeln!(
+                out,
+                "  {:<12} {:>10} files {:>12}",
+                name,
+                extension.files,
+                format_size(extension.bytes as u64)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_explain(explain: &SearchExplain, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "query:    {:?}", explain.original_query)?;
+    writeln!(out, "text:     {:?}", explain.query)?;
+    for filter in &explain.filters {
+        writeln!(out, "filter:   {:?}", filter)?;
+    }
+    match explain.limit {
+        Some(limit) => writeln!(out, "limit:    {}", limit)?,
+        None => writeln!(out, "limit:    none")?,
+    }
+    if let Some(sort) = explain.sort {
+        writeln!(out, "sort:     {:?} {:?}", sort.key, sort.order)?;
+    }
+    writeln!(out, "strategy: {:?}", explain.strategy)?;
+    if let Some(error) = &explain.fallback {
+        writeln!(out, "fallback: FTS5 failed ({}), used LIKE", error)?;
+    }
+    writeln!(out, "sql:      {}", explain.sql)?;
+    for (index, param) in explain.params.iter().enumerate() {
+        writeln!(out, "  ?{} = {:?}", index + 1, param)?;
+    }
+
+    writeln!(out, "plan:")?;
+    for line in &explain.plan {
+        writeln!(out, "  {}", line)?;
+    }
+
+    writeln!(out, "timings:")?;
+    let mut total = Duration::ZERO;
+    for (stage, elapsed) in &explain.timings {
+        writeln!(out, "  {:<8} {:?}", stage, elapsed)?;
+        total += *elapsed;
+    }
+    writeln!(out, "  {:<8} {:?}", "total", total)?;
+    writeln!(out, "results:  {}", explain.results.len())?;
+    Ok(())
+}
+
+// like ls -lh, e.g. "-rw-r--r--  theo     users      1.2 MiB  2025-01-31 12:30  /home/theo/video.mkv"
+// owners and groups without a name are shown as numbers, files from an import have neither
+fn print_long(entry: &FileEntry, users: &Users, out: &mut impl Write) -> io::Result<()> {
+    let owner = entry.uid.map_or("?".to_string(), |uid| {
+        users.user_name(uid).map_or(uid.to_string(), String::from)
+    });
+    let group = entry.gid.map_or("?".to_string(), |gid| {
+        users.group_name(gid).map_or(gid.to_string(), String::from)
+    });
+    write!(
+        out,
+        "{:<10}  {:<8} {:<8}  {:>10}  {:<16}  {}",
+        entry.mode.map_or("?".to_string(), format_mode),
+        owner,
+        group,
+        format_size(entry.size),
+        format_time(entry.modified_at),
+        entry.path.display()
+    )?;
+    // like ls -l
+    match &entry.link_target {
+        Some(target) => writeln!(out, " -> {}", target.display()),
+        None => writeln!(out),
+    }
+}
+
+// "  12  2025-01-31 12:30     3 results  ext:log size:>100mb", the number is what !N recalls
+fn print_history(history: &[QueryRecord], json: bool, out: &mut impl Write) -> io::Result<()> {
+    for record in history {
+        if json {
+            serde_json::to_writer(&mut *out, record)?;
+            writeln!(out)?;
+        } else {
+            writeln!(
+                out,
+                "{:>6}  {:<16}  {:>6} results  {}",
+                record.id,
+                format_time(record.ran_at),
+                record.result_count,
+                record.query
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// "+ path" for added, "- path" for removed and "~ path" for changed, like a diff
+fn print_change(change: &ArchiveChange, json: bool, out: &mut impl Write) -> io::Result<()> {
+    if json {
+        serde_json::to_writer(&mut *out, change)?;
+        return writeln!(out);
+    }
+    let sign = match change {
+        ArchiveChange::Added { .. } => '+',
+        ArchiveChange::Removed { .. } => '-',
+        ArchiveChange::Changed { .. } => '~',
+    };
+    writeln!(out, "{} {}", sign, change.path().display())
+}
+
+// when it was seen, what happened and the path, e.g. "2025-01-31 14:02  modified  /home/theo/a.rs"
+fn print_logged_change(change: &Change, out: &mut impl Write) -> io::Result<()> {
+    write!(
+        out,
+        "{}  {:<8}  ",
+        format_time(change.changed_at),
+        change.kind.as_str()
+    )?;
+    match &change.old_path {
+        Some(old_path) => writeln!(out, "{} -> {}", old_path.display(), change.path.display()),
+        None => writeln!(out, "{}", change.path.display()),
+    }
+}
+
+// the start of a time expression, so `--since today` means since midnight
+fn parse_since(value: &str) -> Result<i64, String> {
+    TimeRange::parse(value)
+        .and_then(|range| range.start)
+        .ok_or_else(|| format!("'{}' isn't a time, try 1h, 2d, today or 2025-01-31", value))
+}
+
+fn print_saved_searches(
+    searches: &[SavedSearch],
+    json: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for search in searches {
+        if json {
+            serde_json::to_writer(&mut *out, search)?;
+            writeln!(out)?;
+        } else {
+            writeln!(out, "{:<20}  {}", search.name, search.query)?;
+        }
+    }
+    Ok(())
+}
+
+// hands the file to the desktop's default app for it
+fn open_path(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = process::Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = process::Command::new("cmd");
+        // the empty argument is the window title, start takes a quoted path as one otherwise
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = process::Command::new("xdg-open");
+
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "couldn't open {}: {:?} exited with {}",
+            path.display(),
+            command.get_program(),
+            status
+        )));
+    }
+    Ok(())
+}
+
+// unix seconds as local time, e.g. 2025-01-31 12:30
+fn format_time(seconds: i64) -> String {
+    chrono::DateTime::from_timestamp(seconds, 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use libsql::{params, Connection, Result as SQLResult};
+use tracing::debug;
+
+use crate::{
+    config::Config,
+    paths::{display_path, path_from_bytes},
+};
+
+// The mount table, from /proc/self/mountinfo
+//
+// the walker uses it to stay out of filesystems it shouldn't index, see skipped_mounts. it's
+// also saved to the mounts table at the start of every index run, so fs: and mount: can find
+// each file's filesystem from the device the walker recorded for it
+
+pub const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    // st_dev of the files on it, stored the same way as files.device
+    pub device: i64,
+    pub mount_point: PathBuf,
+    pub fstype: String,
+    pub source: String,
+}
+
+// platforms without /proc have no mount table, so nothing is skipped by filesystem
+pub fn read_mounts() -> Vec<Mount> {
+    std::fs::read(MOUNTINFO_PATH)
+        .map(|info| parse_mountinfo(&info))
+        .unwrap_or_default()
+}
+
+// one mount per line, see proc_pid_mountinfo(5):
+// 36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+// there can be any number of optional fields (master:1) before the -
+pub fn parse_mountinfo(info: &[u8]) -> Vec<Mount> {
+    info.split(|byte| *byte == b'\n')
+        .filter_map(parse_mount)
+        .collect()
+}
+
+fn parse_mount(line: &[u8]) -> Option<Mount> {
+    let fields: Vec<&[u8]> = line.split(|byte| *byte == b' ').collect();
+    let separator = 6 + fields.iter().skip(6).position(|field| *field == b"-")?;
+    let (major, minor) = std::str::from_utf8(fields.get(2)?).ok()?.split_once(':')?;
+    Some(Mount {
+        device: makedev(major.parse().ok()?, minor.parse().ok()?),
+        mount_point: path_from_bytes(&unescape(fields.get(4)?)),
+        fstype: String::from_utf8_lossy(&unescape(fields.get(separator + 1)?)).into_owned(),
+        source: String::from_utf8_lossy(&unescape(fields.get(separator + 2)?)).into_owned(),
+    })
+}
+
+// spaces, tabs, newlines and backslashes are written as \ooo
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field
+            .get(i + 1..i + 4)
+            .filter(|_| field[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 4;
+            }
+            None => {
+                bytes.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
+
+// glibc's makedev, which is how the kernel's major:minor ends up in st_dev
+fn makedev(major: u64, minor: u64) -> i64 {
+    (((major & 0xffff_f000) << 32)
+        | ((major & 0x0000_0fff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0x0000_00ff)) as i64
+}
+
+pub fn filesystem_included(config: &Config, fstype: &str) -> bool {
+    (config.include_filesystems.is_empty()
+        || config.include_filesystems.iter().any(|t| t == fstype))
+        && !config.exclude_filesystems.iter().any(|t| t == fstype)
+}
+
+// the mount points under `root` the walker doesn't go into: filesystems that aren't included,
+// and with one_file_system, anything that isn't on `root_device`
+pub fn skipped_mounts(
+    config: &Config,
+    mounts: &[Mount],
+    root: &Path,
+    root_device: Option<i64>,
+) -> HashSet<PathBuf> {
+    mounts
+        .iter()
+        .filter(|mount| mount.mount_point != root && mount.mount_point.starts_with(root))
+        .filter(|mount| {
+            !filesystem_included(config, &mount.fstype)
+                || (config.one_file_system && root_device.is_some_and(|d| d != mount.device))
+        })
+        .map(|mount| {
+            debug!(
+                mount_point = %mount.mount_point.display(),
+                fstype = mount.fstype,
+                "Skipping mount"
+            );
+            mount.mount_point.clone()
+        })
+        .collect()
+}
+
+// devices that are no longer mounted are kept, their files can still be in the index.
+// bind mounts share a device, the first mount of it is the one that's kept
+pub async fn save_mounts(conn: &Connection, mounts: &[Mount]) -> SQLResult<()> {
+    let mut seen = HashSet::new();
+    conn.execute("BEGIN", ()).await?;
+    for mount in mounts.iter().filter(|mount| seen.insert(mount.device)) {
+        if let Err(e) = conn
+            .execute(
+                "INSERT OR REPLACE INTO mounts (device, mount_point, fstype, source)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    mount.device,
+                    display_path(mount.mount_point.as_os_str()),
+                    mount.fstype.clone(),
+                    mount.source.clone()
+                ],
+            )
+            .await
+        {
+            let _ = conn.execute("ROLLBACK", ()).await;
+            return Err(e);
+        }
+    }
+    conn.execute("COMMIT", ()).await?;
+    Ok(())
+}
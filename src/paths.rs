@@ -0,0 +1,145 @@
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use serde::{de::Error as _, ser::SerializeMap, Deserialize, Deserializer, Serializer};
+
+// Lossless handling of paths that aren't valid UTF-8
+//
+// on Linux a filename is any sequence of bytes, so a path can't always be stored as TEXT.
+// every path gets a display form that is always valid UTF-8, and the raw bytes are kept
+// next to it whenever the display form isn't exactly the path
+
+// the path as UTF-8, with any invalid bytes written as \xNN and backslashes doubled, so a file
+// really called caf\xE9 doesn't end up with the same display form as caf and a 0xE9 byte.
+// paths with neither come back unchanged, and the display form is unique to the path
+pub fn display_path(path: &OsStr) -> String {
+    if let Some(text) = path.to_str() {
+        return text.replace('\\', r"\\");
+    }
+
+    let mut display = String::new();
+    for chunk in os_str_bytes(path).utf8_chunks() {
+        display.push_str(&chunk.valid().replace('\\', r"\\"));
+        for byte in chunk.invalid() {
+            display.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    display
+}
+
+// reads what display_path wrote, for columns that only have the display form
+pub fn path_from_display(display: &str) -> PathBuf {
+    let mut bytes = Vec::with_capacity(display.len());
+    let mut rest = display.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [b'\\', tail @ ..] => {
+                bytes.push(b'\\');
+                rest = tail;
+            }
+            [b'x', high, low, tail @ ..] => {
+                match std::str::from_utf8(&[*high, *low])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(escaped) => {
+                        bytes.push(escaped);
+                        rest = tail;
+                    }
+                    None => bytes.push(byte),
+                }
+            }
+            // written before backslashes were doubled
+            _ => bytes.push(byte),
+        }
+    }
+    path_from_bytes(&bytes)
+}
+
+// the raw bytes of a path, only needed when it isn't valid UTF-8 or has a backslash in it
+pub fn raw_path_bytes(path: &OsStr) -> Option<Vec<u8>> {
+    match path.to_str() {
+        Some(text) if !text.contains('\\') => None,
+        _ => Some(os_str_bytes(path).to_vec()),
+    }
+}
+
+#[cfg(unix)]
+pub fn os_str_bytes(path: &OsStr) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_bytes()
+}
+
+// other platforms can't produce invalid paths from the walker, but fall back to the display form
+#[cfg(not(unix))]
+pub fn os_str_bytes(path: &OsStr) -> &[u8] {
+    path.to_str().unwrap_or_default().as_bytes()
+}
+
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).to_string())
+}
+
+// ripgrep style JSON for data that may not be UTF-8
+// {"text": "..."} when it is, {"bytes": "<base64>"} when it isn't
+pub fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    match path.to_str() {
+        Some(text) => map.serialize_entry("text", text)?,
+        None => map.serialize_entry(
+            "bytes",
+            &base64::engine::general_purpose::STANDARD.encode(os_str_bytes(path.as_os_str())),
+        )?,
+    }
+    map.end()
+}
+
+// reads what serialize_path wrote
+pub fn deserialize_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Encoded {
+        Text(String),
+        Bytes(String),
+    }
+
+    match Encoded::deserialize(deserializer)? {
+        Encoded::Text(text) => Ok(PathBuf::from(text)),
+        Encoded::Bytes(bytes) => base64::engine::general_purpose::STANDARD
+            .decode(bytes)
+            .map(|bytes| path_from_bytes(&bytes))
+            .map_err(D::Error::custom),
+    }
+}
+
+pub fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// the exact bytes of the path followed by a NUL, for xargs -0 and friends
+pub fn write_path_nul(path: &Path, out: &mut impl Write) -> std::io::Result<()> {
+    out.write_all(os_str_bytes(path.as_os_str()))?;
+    out.write_all(b"\0")
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::Metadata,
+    path::Path,
+};
+
+use libsql::{Connection, Result as SQLResult, Value};
+use serde::Serialize;
+
+use crate::{
+    paths::display_path,
+    search::{search_files, FileEntry, SearchQuery},
+    users::Users,
+};
+
+// Searching a shared index as another user
+//
+// the walker keeps the owner, group and mode of every file, and of every directory in the
+// directories table. a user only sees a file if they could have found it themselves: they need
+// to be able to search (x) every directory above it and list (r) the one it's in. directories
+// without a row, e.g. above the files from an import, count as closed. ACLs aren't looked at.
+// the mime type and xattrs were read from the file, so they're only shown to users who could
+// read (r) it, and mime: and xattr: only find files they can read
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Permissions {
+    pub uid: u32,
+    pub gid: u32,
+    // st_mode, file type bits included
+    pub mode: u32,
+}
+
+impl Permissions {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(_metadata: &Metadata) -> Option<Self> {
+        None
+    }
+
+    // whether `credentials` have all of `bits`, rwx as 4, 2 and 1. like the kernel, only the
+    // owner's bits apply to the owner and the group's to its members, root has them all
+    pub fn allow(&self, credentials: &Credentials, bits: u32) -> bool {
+        if credentials.uid == 0 {
+            return true;
+        }
+        let class = if credentials.uid == self.uid {
+            self.mode >> 6
+        } else if credentials.groups.contains(&self.gid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        class & bits == bits
+    }
+}
+
+const READ: u32 = 4;
+const SEARCH: u32 = 1;
+
+// perm: expressions, applied to the permission bits of st_mode
+// e.g. perm:+x perm:u+s perm:go-w perm:644 perm:world-writable. see ModeMatch::parse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeMatch {
+    // every one of the bits is set
+    All(u32),
+    // at least one of them is
+    Any(u32),
+    // none of them are
+    NoneOf(u32),
+    // the permission bits are exactly these
+    Exactly(u32),
+}
+
+impl ModeMatch {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.to_lowercase();
+        match value.as_str() {
+            "world-writable" => return Some(ModeMatch::All(0o002)),
+            "world-readable" => return Some(ModeMatch::All(0o004)),
+            "executable" => return Some(ModeMatch::Any(0o111)),
+            "setuid" => return Some(ModeMatch::All(0o4000)),
+            "setgid" => return Some(ModeMatch::All(0o2000)),
+            "sticky" => return Some(ModeMatch::All(0o1000)),
+            _ => {}
+        }
+        if (3..=4).contains(&value.len()) && value.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+            return u32::from_str_radix(&value, 8).ok().map(ModeMatch::Exactly);
+        }
+
+        // like chmod: who (u, g, o or a), then + or -, then r, w, x, s or t
+        let operator = value.find(['+', '-'])?;
+        let (who, permissions) = (&value[..operator], &value[operator + 1..]);
+        if permissions.is_empty() || !who.chars().all(|c| "ugoa".contains(c)) {
+            return None;
+        }
+        let classes: Vec<u32> = if who.is_empty() || who.contains('a') {
+            vec![6, 3, 0]
+        } else {
+            who.chars()
+                .map(|c| match c {
+                    'u' => 6,
+                    'g' => 3,
+                    _ => 0,
+                })
+                .collect()
+        };
+        let mut mask = 0;
+        for permission in permissions.chars() {
+            for &shift in &classes {
+                mask |= match (permission, shift) {
+                    ('r', _) => 4 << shift,
+                    ('w', _) => 2 << shift,
+                    ('x', _) => 1 << shift,
+                    ('s', 6) => 0o4000,
+                    ('s', 3) => 0o2000,
+                    ('t', 0) => 0o1000,
+                    ('s' | 't', _) => 0,
+                    _ => return None,
+                };
+            }
+        }
+        if mask == 0 {
+            return None;
+        }
+        Some(match (&value[operator..=operator], who) {
+            ("-", _) => ModeMatch::NoneOf(mask),
+            // +x on its own is executable by anyone, like find -perm /111
+            ("+", "") => ModeMatch::Any(mask),
+            _ => ModeMatch::All(mask),
+        })
+    }
+}
+
+// the mode as ls -l shows it, e.g. drwxr-sr-x
+pub fn format_mode(mode: u32) -> String {
+    let kind = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o010000 => 'p',
+        0o140000 => 's',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        _ => '-',
+    };
+    let mut formatted = String::from(kind);
+    for (shift, special, set) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        formatted.push(if bits & 4 != 0 { 'r' } else { '-' });
+        formatted.push(if bits & 2 != 0 { 'w' } else { '-' });
+        formatted.push(match (bits & 1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    formatted
+}
+
+// who a search is for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub uid: u32,
+    // the primary group first, then the supplementary ones
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn new(uid: u32, gid: u32, users: &Users) -> Self {
+        Self {
+            uid,
+            groups: users.groups_of(uid, gid),
+        }
+    }
+
+    // the process at the other end of a unix socket, from SO_PEERCRED
+    #[cfg(unix)]
+    pub fn of_peer(stream: &tokio::net::UnixStream) -> std::io::Result<Self> {
+        let peer = stream.peer_cred()?;
+        Ok(Self::new(peer.uid(), peer.gid(), &Users::load()))
+    }
+}
+
+// the permissions of the directories at `paths`, as far as they're indexed
+// keyed by their display form, like the directories table, see paths.rs
+async fn get_directories(
+    conn: &Connection,
+    paths: &HashSet<String>,
+) -> SQLResult<HashMap<String, Permissions>> {
+    let mut directories = HashMap::new();
+    let paths: Vec<&String> = paths.iter().collect();
+    for chunk in paths.chunks(500) {
+        let placeholders: Vec<String> = (1..=chunk.len()).map(|i| format!("?{}", i)).collect();
+        let params: Vec<Value> = chunk
+            .iter()
+            .map(|path| Value::Text(path.to_string()))
+            .collect();
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT path, uid, gid, mode FROM directories WHERE path IN ({})",
+                    placeholders.join(", ")
+                ),
+                params,
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            directories.insert(
+                row.get::<String>(0)?,
+                Permissions {
+                    uid: row.get::<i64>(1)? as u32,
+                    gid: row.get::<i64>(2)? as u32,
+                    mode: row.get::<i64>(3)? as u32,
+                },
+            );
+        }
+    }
+    Ok(directories)
+}
+
+fn can_find(
+    path: &Path,
+    credentials: &Credentials,
+    directories: &HashMap<String, Permissions>,
+) -> bool {
+    path.ancestors().skip(1).enumerate().all(|(i, directory)| {
+        let bits = if i == 0 { READ | SEARCH } else { SEARCH };
+        directories
+            .get(&display_path(directory.as_os_str()))
+            .is_some_and(|permissions| permissions.allow(credentials, bits))
+    })
+}
+
+// files without a known owner and mode, e.g. from a locate import, count as unreadable
+fn can_read(entry: &FileEntry, credentials: &Credentials) -> bool {
+    match (entry.uid, entry.gid, entry.mode) {
+        (Some(uid), Some(gid), Some(mode)) => {
+            Permissions { uid, gid, mode }.allow(credentials, READ)
+        }
+        _ => false,
+    }
+}
+
+// the entries `credentials` could have found themselves for `query`, in the same order
+pub async fn visible_files(
+    conn: &Connection,
+    query: &SearchQuery,
+    credentials: &Credentials,
+    entries: Vec<FileEntry>,
+) -> SQLResult<Vec<FileEntry>> {
+    if credentials.uid == 0 {
+        return Ok(entries);
+    }
+    let above: HashSet<String> = entries
+        .iter()
+        .flat_map(|entry| entry.path.ancestors().skip(1))
+        .map(|directory| display_path(directory.as_os_str()))
+        .collect();
+    let directories = get_directories(conn, &above).await?;
+    let filters_on_contents = query.filters_on_contents();
+    Ok(entries
+        .into_iter()
+        .filter(|entry| can_find(&entry.path, credentials, &directories))
+        .filter_map(|mut entry| {
+            if !can_read(&entry, credentials) {
+                // or mime: and xattr: would tell them what's in it
+                if filters_on_contents {
+                    return None;
+                }
+                entry.mime = None;
+                entry.xattrs.clear();
+            }
+            Some(entry)
+        })
+        .collect())
+}
+
+// a search as `credentials` would see it. hidden results still count towards the limit in SQL,
+// so it asks for more until there are enough visible ones or there's nothing left
+pub async fn search_visible(
+    conn: &Connection,
+    query: &SearchQuery,
+    credentials: &Credentials,
+) -> SQLResult<Vec<FileEntry>> {
+    let limit = query.get_limit();
+    let mut fetch = limit.map(|limit| limit.max(1) * 2);
+    loop {
+        let results = search_files(&query.clone().with_limit(fetch), conn).await?;
+        let exhausted = fetch.is_none_or(|fetch| results.len() < fetch);
+        let mut visible = visible_files(conn, query, credentials, results).await?;
+        if exhausted || limit.is_some_and(|limit| visible.len() >= limit) {
+            if let Some(limit) = limit {
+                visible.truncate(limit);
+            }
+            return Ok(visible);
+        }
+        fetch = fetch.map(|fetch| fetch * 4);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{ops::Deref, sync::Mutex};
+
+use libsql::{Connection, Database, Result as SQLResult};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::database::configure_connection;
+
+// A small pool of read-only connections used for searching
+//
+// with the database in WAL mode readers don't block the writer and the writer doesn't block readers,
+// so searches run against the last committed batch while the indexer keeps inserting.
+// every connection is set to query_only so nothing can write through the pool by accident
+pub struct ReaderPool {
+    connections: Mutex<Vec<Connection>>,
+    available: Semaphore,
+}
+
+impl ReaderPool {
+    pub async fn new(database: &Database, size: usize) -> SQLResult<Self> {
+        let mut connections = Vec::with_capacity(size);
+        for _ in 0..size {
+            let conn = database.connect()?;
+            configure_connection(&conn).await?;
+            conn.execute_batch("PRAGMA query_only = ON;").await?;
+            connections.push(conn);
+        }
+
+        Ok(Self {
+            connections: Mutex::new(connections),
+            available: Semaphore::new(size),
+        })
+    }
+
+    // a pool sized to the machine, searches are short so there's no point going much wider
+    pub fn default_size() -> usize {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .clamp(2, 8)
+    }
+
+    // waits for a free connection, it goes back into the pool when the guard is dropped
+    pub async fn get(&self) -> PooledConnection<'_> {
+        let permit = self
+            .available
+            .acquire()
+            .await
+            .expect("Reader pool semaphore closed");
+        let connection = self
+            .connections
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("Reader pool has a permit but no connection");
+
+        PooledConnection {
+            pool: self,
+            connection: Some(connection),
+            _permit: permit,
+        }
+    }
+}
+
+pub struct PooledConnection<'a> {
+    pool: &'a ReaderPool,
+    connection: Option<Connection>,
+    // released after the connection has been returned, see Drop
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.connections.lock().unwrap().push(connection);
+        }
+    }
+}
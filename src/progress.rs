@@ -0,0 +1,228 @@
+use std::{
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::{error, info};
+
+use crate::database::IndexRunStatus;
+
+// Progress of the indexer, for anything that wants to show it
+//
+// indexing runs on a blocking thread, so instead of printing it reports what it is doing here.
+// every change is sent as an IndexEvent to whoever subscribed, and the totals are kept
+// so a client can also poll stats() whenever it likes
+
+// events are dropped for receivers that fall this far behind, they'll just see the next one
+const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum IndexEvent {
+    Started {
+        run_id: i64,
+        resumed: bool,
+        // size of the last completed run, used for the ETA
+        expected_files: Option<u64>,
+    },
+    // a batch has been walked and is about to be written
+    Queued {
+        directories_scanned: u64,
+        files_queued: u64,
+    },
+    Committed {
+        files_committed: u64,
+        eta: Option<Duration>,
+    },
+    // a file or directory that couldn't be read or written, indexing carries on
+    Error {
+        #[serde(serialize_with = "crate::paths::serialize_optional_path")]
+        path: Option<PathBuf>,
+        error: String,
+    },
+    Finished {
+        status: IndexRunStatus,
+        files_committed: u64,
+        errors: u64,
+        elapsed: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexStats {
+    pub run_id: Option<i64>,
+    pub indexing: bool,
+    pub directories_scanned: u64,
+    pub files_queued: u64,
+    // includes the files an interrupted run committed before it was resumed
+    pub files_committed: u64,
+    pub errors: u64,
+    pub expected_files: Option<u64>,
+    pub elapsed: Duration,
+    pub eta: Option<Duration>,
+}
+
+struct ProgressState {
+    stats: IndexStats,
+    started: Option<Instant>,
+    // files_committed when this process picked the run up, the rate only counts what came after
+    resumed_from: u64,
+}
+
+impl ProgressState {
+    // fill in the time based fields for the current moment
+    fn snapshot(&self) -> IndexStats {
+        let mut stats = self.stats.clone();
+        if let Some(started) = self.started {
+            if stats.indexing {
+                stats.elapsed = started.elapsed();
+            }
+        }
+        stats.eta = self.eta(stats.elapsed);
+        stats
+    }
+
+    // assumes the index is about as big as last time, and files keep coming in at the same rate
+    fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        if !self.stats.indexing {
+            return None;
+        }
+        let expected = self.stats.expected_files?;
+        let done = self.stats.files_committed.saturating_sub(self.resumed_from);
+        if done == 0 || elapsed.is_zero() {
+            return None;
+        }
+
+        let remaining = expected.saturating_sub(self.stats.files_committed);
+        let rate = done as f64 / elapsed.as_secs_f64();
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+pub struct IndexProgress {
+    events: broadcast::Sender<IndexEvent>,
+    state: Mutex<ProgressState>,
+}
+
+impl Default for IndexProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndexProgress {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            events,
+            state: Mutex::new(ProgressState {
+                stats: IndexStats::default(),
+                started: None,
+                resumed_from: 0,
+            }),
+        }
+    }
+
+    // events sent after this call, subscribe before starting an index to see all of it
+    pub fn subscribe(&self) -> broadcast::Receiver<IndexEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        self.state.lock().unwrap().snapshot()
+    }
+
+    // nobody listening is fine, the stats are still kept
+    fn send(&self, event: IndexEvent) {
+        let _ = self.events.send(event);
+    }
+
+    // `already_committed` is what an interrupted run had written before it was resumed
+    pub fn started(
+        &self,
+        run_id: i64,
+        already_committed: Option<u64>,
+        expected_files: Option<u64>,
+    ) {
+        let resumed = already_committed.is_some();
+        let already_committed = already_committed.unwrap_or(0);
+        {
+            let mut state = self.state.lock().unwrap();
+            state.stats = IndexStats {
+                run_id: Some(run_id),
+                indexing: true,
+                files_committed: already_committed,
+                expected_files,
+                ..IndexStats::default()
+            };
+            state.started = Some(Instant::now());
+            state.resumed_from = already_committed;
+        }
+        info!(run_id, resumed, expected_files, "index started");
+        self.send(IndexEvent::Started {
+            run_id,
+            resumed,
+            expected_files,
+        });
+    }
+
+    pub fn queued(&self, directories_scanned: u64, files_queued: u64) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.stats.directories_scanned = directories_scanned;
+            state.stats.files_queued = files_queued;
+        }
+        self.send(IndexEvent::Queued {
+            directories_scanned,
+            files_queued,
+        });
+    }
+
+    pub fn committed(&self, written: usize) {
+        let stats = {
+            let mut state = self.state.lock().unwrap();
+            state.stats.files_committed += written as u64;
+            state.snapshot()
+        };
+        self.send(IndexEvent::Committed {
+            files_committed: stats.files_committed,
+            eta: stats.eta,
+        });
+    }
+
+    pub fn error(&self, path: Option<PathBuf>, error: String) {
+        // unreadable files are common when walking / and counted in the summary,
+        // one without a path means the whole index has stopped
+        match &path {
+            Some(path) => info!(path = %path.display(), "{}", error),
+            None => error!("{}", error),
+        }
+        self.state.lock().unwrap().stats.errors += 1;
+        self.send(IndexEvent::Error { path, error });
+    }
+
+    pub fn finished(&self, status: IndexRunStatus) {
+        let stats = {
+            let mut state = self.state.lock().unwrap();
+            state.stats.elapsed = state.started.map(|s| s.elapsed()).unwrap_or_default();
+            state.stats.indexing = false;
+            state.snapshot()
+        };
+        info!(
+            status = ?status,
+            files = stats.files_committed,
+            errors = stats.errors,
+            elapsed = ?stats.elapsed,
+            "index finished"
+        );
+        self.send(IndexEvent::Finished {
+            status,
+            files_committed: stats.files_committed,
+            errors: stats.errors,
+            elapsed: stats.elapsed,
+        });
+    }
+}
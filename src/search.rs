@@ -1,115 +1,736 @@
-use std::sync::MutexGuard;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use crate::database::get_database_count;
-use libsql::{params, Connection, Database, Result as SQLResult};
+use crate::{
+    database::get_database_count,
+    dates::TimeRange,
+    paths::{path_from_bytes, path_from_display},
+    permissions::ModeMatch,
+    sizes::SizeRange,
+    users::Users,
+};
+use libsql::{Connection, Result as SQLResult, Value};
+use serde::Serialize;
+use tracing::{debug, instrument, warn, Level};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 
 pub struct FileEntry {
-    pub path: String,
+    // the exact path on disk, even if it isn't valid UTF-8
+    #[serde(serialize_with = "crate::paths::serialize_path")]
+    pub path: PathBuf,
     pub filename: String,
     pub extension: String,
     pub size: u64,
     pub modified_at: i64,
+    pub created_at: Option<i64>,
+    pub accessed_at: Option<i64>,
+    // where a symlink points
+    #[serde(serialize_with = "crate::paths::serialize_optional_path")]
+    pub link_target: Option<PathBuf>,
+    // the owner and group are numbers, names are looked up when they're shown, see users.rs
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub mode: Option<u32>,
+    // only there when detect_mime and index_xattrs were on, see content.rs
+    pub mime: Option<String>,
+    pub xattrs: BTreeMap<String, String>,
+}
+
+// the columns every search selects, in the order FileEntry::from_row expects them
+// modified_at is declared TEXT in the schema so it has to be cast back to an integer
+const FILE_COLUMNS: &str = "files.path, files.filename, files.extension, files.size, \
+    CAST(files.modified_at AS INTEGER), files.path_bytes, files.created_at, files.accessed_at, \
+    files.link_target, files.uid, files.gid, files.mode, files.mime, files.xattrs";
+
+const DEFAULT_LIMIT: usize = 50;
+
+impl FileEntry {
+    fn from_row(row: &libsql::Row) -> SQLResult<Self> {
+        // path is only a display form when the raw bytes are there, see paths.rs
+        let path = match row.get::<Option<Vec<u8>>>(5)? {
+            Some(bytes) => path_from_bytes(&bytes),
+            None => PathBuf::from(row.get::<String>(0)?),
+        };
+
+        Ok(Self {
+            path,
+            // the name itself, not the display form with its backslashes doubled
+            filename: path_from_display(&row.get::<String>(1)?)
+                .to_string_lossy()
+                .into_owned(),
+            extension: row.get::<Option<String>>(2)?.unwrap_or_default(),
+            size: row.get::<i64>(3)? as u64,
+            modified_at: row.get(4)?,
+            created_at: row.get(6)?,
+            accessed_at: row.get(7)?,
+            link_target: row
+                .get::<Option<String>>(8)?
+                .map(|target| path_from_display(&target)),
+            uid: row.get::<Option<i64>>(9)?.map(|uid| uid as u32),
+            gid: row.get::<Option<i64>>(10)?.map(|gid| gid as u32),
+            mode: row.get::<Option<i64>>(11)?.map(|mode| mode as u32),
+            mime: row.get(12)?,
+            xattrs: row
+                .get::<Option<String>>(13)?
+                .and_then(|xattrs| serde_json::from_str(&xattrs).ok())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+// key:value filters that are applied to the files table rather than the FTS5 index
+// e.g. ext:rs path:src name:main modified:last-week size:>1mb target:/mnt/data fs:ext4
+// owner:theo perm:world-writable mime:image/* xattr:user.xdg.tags=work
+// see dates.rs and sizes.rs for the time and size expressions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Extension(String),
+    Path(String),
+    Filename(String),
+    Modified(TimeRange),
+    Created(TimeRange),
+    Accessed(TimeRange),
+    Size(SizeRange),
+    // symlinks pointing to a path, or anywhere under it
+    Target(String),
+    // files on a type of filesystem, or the one mounted at a path, see mounts.rs
+    Filesystem(String),
+    Mount(String),
+    // names are looked up when the query is parsed
+    Owner(u32),
+    Group(u32),
+    Mode(ModeMatch),
+    // a glob like image/*, see content.rs
+    Mime(String),
+    // an extended attribute that's set, or that has a value
+    Xattr(String, Option<String>),
+}
+
+impl Filter {
+    // None when the token isn't a filter, it's searched for as text then. a filter with a value
+    // that can't be used, like owner:nosuchuser or size:big, is an error rather than text
+    pub fn parse(token: &str) -> Option<Result<Self, String>> {
+        let (key, value) = token.split_once(':')?;
+        if value.is_empty() {
+            return None;
+        }
+
+        let key = key.to_lowercase();
+        let filter = match key.as_str() {
+            "ext" | "extension" => Some(Filter::Extension(
+                value.trim_start_matches('.').to_lowercase(),
+            )),
+            "path" => Some(Filter::Path(value.to_string())),
+            "name" | "filename" => Some(Filter::Filename(value.to_string())),
+            "modified" | "mtime" => TimeRange::parse(value).map(Filter::Modified),
+            "created" | "btime" => TimeRange::parse(value).map(Filter::Created),
+            "accessed" | "atime" => TimeRange::parse(value).map(Filter::Accessed),
+            "size" => SizeRange::parse(value).map(Filter::Size),
+            "target" => Some(Filter::Target(value.to_string())),
+            "owner" | "user" | "uid" => Users::load().uid_of(value).map(Filter::Owner),
+            "group" | "gid" => Users::load().gid_of(value).map(Filter::Group),
+            "perm" | "mode" => ModeMatch::parse(value).map(Filter::Mode),
+            "setuid" | "setgid" => {
+                let bit = if key.eq_ignore_ascii_case("setuid") {
+                    0o4000
+                } else {
+                    0o2000
+                };
+                match value.to_lowercase().as_str() {
+                    "yes" | "true" => Some(Filter::Mode(ModeMatch::All(bit))),
+                    "no" | "false" => Some(Filter::Mode(ModeMatch::NoneOf(bit))),
+                    _ => None,
+                }
+            }
+            "fs" | "fstype" => Some(Filter::Filesystem(value.to_lowercase())),
+            // mime:image is the same as mime:image/*
+            "mime" | "type" => Some(Filter::Mime(if value.contains('/') {
+                value.to_lowercase()
+            } else {
+                format!("{}/*", value.to_lowercase())
+            })),
+            // only user.* attributes are indexed, so the namespace can be left off
+            "xattr" => {
+                let (name, value) = match value.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (value, None),
+                };
+                let name = if name.starts_with("user.") {
+                    name.to_string()
+                } else {
+                    format!("user.{}", name)
+                };
+                Some(Filter::Xattr(name, value))
+            }
+            "mount" => Some(Filter::Mount(match value.trim_end_matches('/') {
+                "" => "/".to_string(),
+                mount_point => mount_point.to_string(),
+            })),
+            _ => return None,
+        };
+        Some(filter.ok_or_else(|| match key.as_str() {
+            "owner" | "user" | "uid" => format!("unknown user '{}' in {}", value, token),
+            "group" | "gid" => format!("unknown group '{}' in {}", value, token),
+            _ => format!("invalid value '{}' in {}", value, token),
+        }))
+    }
+
+    // push the SQL condition for this filter, binding its value as the next positional parameter
+    fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            Filter::Extension(ext) => {
+                params.push(Value::Text(ext.clone()));
+                format!("LOWER(files.extension) = ?{}", params.len())
+            }
+            Filter::Path(path) => {
+                params.push(Value::Text(format!("%{}%", path)));
+                format!("files.path LIKE ?{}", params.len())
+            }
+            Filter::Filename(name) => {
+                params.push(Value::Text(format!("%{}%", name)));
+                format!("files.filename LIKE ?{}", params.len())
+            }
+            // the same expression as the files_modified_at index
+            Filter::Modified(range) => {
+                time_range_sql("CAST(files.modified_at AS INTEGER)", range, params)
+            }
+            Filter::Created(range) => time_range_sql("files.created_at", range, params),
+            Filter::Accessed(range) => time_range_sql("files.accessed_at", range, params),
+            // a range scan on the files_size index
+            Filter::Size(range) => {
+                let mut conditions = Vec::new();
+                if let Some(min) = range.min {
+                    params.push(Value::Integer(min.min(i64::MAX as u64) as i64));
+                    conditions.push(format!("files.size >= ?{}", params.len()));
+                }
+                if let Some(max) = range.max {
+                    params.push(Value::Integer(max.min(i64::MAX as u64) as i64));
+                    conditions.push(format!("files.size <= ?{}", params.len()));
+                }
+                conditions.join(" AND ")
+            }
+            // targets are stored as absolute paths, so an absolute one is a tree. the range
+            // rather than a LIKE lets it use files_link_target, '0' is the character after '/'
+            Filter::Target(target) if target.starts_with('/') => {
+                let target = target.trim_end_matches('/');
+                params.push(Value::Text(target.to_string()));
+                params.push(Value::Text(format!("{}/", target)));
+                params.push(Value::Text(format!("{}0", target)));
+                let n = params.len();
+                format!(
+                    "(files.link_target = ?{} OR (files.link_target > ?{} AND files.link_target < ?{}))",
+                    n - 2,
+                    n - 1,
+                    n
+                )
+            }
+            Filter::Target(target) => {
+                params.push(Value::Text(format!("%{}%", target)));
+                format!("files.link_target LIKE ?{}", params.len())
+            }
+            // fs:fuse takes in every fuse.* type
+            Filter::Filesystem(fstype) => {
+                params.push(Value::Text(fstype.clone()));
+                format!(
+                    "files.device IN (SELECT device FROM mounts \
+                     WHERE fstype = ?{0} OR fstype LIKE ?{0} || '.%')",
+                    params.len()
+                )
+            }
+            Filter::Mount(mount_point) => {
+                params.push(Value::Text(mount_point.clone()));
+                format!(
+                    "files.device IN (SELECT device FROM mounts WHERE mount_point = ?{})",
+                    params.len()
+                )
+            }
+            Filter::Owner(uid) => {
+                params.push(Value::Integer((*uid).into()));
+                format!("files.uid = ?{}", params.len())
+            }
+            Filter::Group(gid) => {
+                params.push(Value::Integer((*gid).into()));
+                format!("files.gid = ?{}", params.len())
+            }
+            // a symlink's own mode is always 777 and means nothing, so links never match
+            Filter::Mode(mode_match) => {
+                let condition = match mode_match {
+                    ModeMatch::All(mask) => {
+                        params.push(Value::Integer((*mask).into()));
+                        format!("(files.mode & ?{0}) = ?{0}", params.len())
+                    }
+                    ModeMatch::Any(mask) => {
+                        params.push(Value::Integer((*mask).into()));
+                        format!("(files.mode & ?{}) != 0", params.len())
+                    }
+                    ModeMatch::NoneOf(mask) => {
+                        params.push(Value::Integer((*mask).into()));
+                        format!("(files.mode & ?{}) = 0", params.len())
+                    }
+                    ModeMatch::Exactly(bits) => {
+                        params.push(Value::Integer((*bits).into()));
+                        format!("(files.mode & 4095) = ?{}", params.len())
+                    }
+                };
+                format!("{} AND (files.mode & 61440) != 40960", condition)
+            }
+            Filter::Mime(pattern) => {
+                params.push(Value::Text(pattern.clone()));
+                format!("files.mime GLOB ?{}", params.len())
+            }
+            Filter::Xattr(name, None) => {
+                params.push(Value::Text(name.clone()));
+                format!(
+                    "EXISTS (SELECT 1 FROM json_each(files.xattrs) WHERE key = ?{})",
+                    params.len()
+                )
+            }
+            // xdg.tags and the like are comma separated lists, any one of them matches
+            Filter::Xattr(name, Some(value)) => {
+                params.push(Value::Text(name.clone()));
+                params.push(Value::Text(value.clone()));
+                format!(
+                    "EXISTS (SELECT 1 FROM json_each(files.xattrs) WHERE key = ?{} \
+                     AND instr(',' || value || ',', ',' || ?{} || ',') > 0)",
+                    params.len() - 1,
+                    params.len()
+                )
+            }
+        }
+    }
+}
+
+// files without the time (NULL) never match
+fn time_range_sql(column: &str, range: &TimeRange, params: &mut Vec<Value>) -> String {
+    let mut conditions = vec![format!("{} IS NOT NULL", column)];
+    if let Some(start) = range.start {
+        params.push(Value::Integer(start));
+        conditions.push(format!("{} >= ?{}", column, params.len()));
+    }
+    if let Some(end) = range.end {
+        params.push(Value::Integer(end));
+        conditions.push(format!("{} < ?{}", column, params.len()));
+    }
+    conditions.join(" AND ")
 }
 
+// how often and how recently a file was opened, like zoxide: every open counts 4 within the hour,
+// 2 within the day, 0.5 within the week and 0.25 after that. 0 for files never opened
+const FRECENCY: &str = "COALESCE(selections.count * CASE \
+    WHEN unixepoch() - selections.last_selected_at < 3600 THEN 4.0 \
+    WHEN unixepoch() - selections.last_selected_at < 86400 THEN 2.0 \
+    WHEN unixepoch() - selections.last_selected_at < 604800 THEN 0.5 \
+    ELSE 0.25 END, 0)";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    // best FTS5 match first, boosted by frecency. without search text it's frecency alone
+    Relevance,
+    Name,
+    Path,
+    Size,
+    Modified,
+    Extension,
+    // how many directories deep the file is
+    Depth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+impl Sort {
+    pub fn new(key: SortKey, order: SortOrder) -> Self {
+        Self { key, order }
+    }
+
+    // "size", "size-desc", "name-asc" ...
+    // without a direction, sizes and dates put the biggest and newest first, everything else goes A-Z
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.to_lowercase();
+        let (key, order) = match value.rsplit_once('-') {
+            Some((key, "asc")) => (key, Some(SortOrder::Ascending)),
+            Some((key, "desc")) => (key, Some(SortOrder::Descending)),
+            _ => (value.as_str(), None),
+        };
+
+        let key = match key {
+            "relevance" | "rank" => SortKey::Relevance,
+            "name" | "filename" => SortKey::Name,
+            "path" => SortKey::Path,
+            "size" => SortKey::Size,
+            "modified" | "mtime" | "date" => SortKey::Modified,
+            "ext" | "extension" => SortKey::Extension,
+            "depth" => SortKey::Depth,
+            _ => return None,
+        };
+        let order = order.unwrap_or(match key {
+            SortKey::Relevance | SortKey::Size | SortKey::Modified => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        });
+        Some(Self { key, order })
+    }
+
+    // the ORDER BY clause, these expressions match the indexes in database.rs
+    // relevance needs the selections table joined in, see SearchQuery::join_sql
+    fn to_sql(self, strategy: SearchStrategy) -> String {
+        let column = match self.key {
+            // rank is negative and lower for better matches, so scaling it up by the frecency
+            // moves opened files towards the top without letting them beat much better matches
+            SortKey::Relevance if strategy == SearchStrategy::Fts5 => {
+                format!("files_fts.rank * (1.0 + {})", FRECENCY)
+            }
+            SortKey::Relevance => FRECENCY.to_string(),
+            SortKey::Name => "files.filename COLLATE NOCASE".to_string(),
+            SortKey::Path => "files.path".to_string(),
+            SortKey::Size => "files.size".to_string(),
+            SortKey::Modified => "CAST(files.modified_at AS INTEGER)".to_string(),
+            SortKey::Extension => "LOWER(files.extension)".to_string(),
+            SortKey::Depth => {
+                "length(files.path) - length(replace(files.path, '/', ''))".to_string()
+            }
+        };
+        // rank is lower for better matches, so most relevant first is ascending
+        let descending = match self.key {
+            SortKey::Relevance if strategy == SearchStrategy::Fts5 => {
+                self.order == SortOrder::Ascending
+            }
+            _ => self.order == SortOrder::Descending,
+        };
+        format!(
+            " ORDER BY {}{}",
+            column,
+            if descending { " DESC" } else { "" }
+        )
+    }
+}
+
+impl std::str::FromStr for Sort {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Sort::parse(value).ok_or_else(|| {
+            format!(
+                "unknown sort '{}', expected relevance, name, path, size, modified, extension \
+                 or depth, optionally followed by -asc or -desc",
+                value
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SearchQuery {
     original_query: String,
     query: String,
-    operations: Vec<String>,
+    filters: Vec<Filter>,
+    limit: Option<usize>,
+    sort: Option<Sort>,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 impl SearchQuery {
     pub fn new() -> Self {
         Self {
             original_query: String::new(),
             query: String::new(),
-            operations: Vec::new(),
+            filters: Vec::new(),
+            limit: Some(DEFAULT_LIMIT),
+            sort: None,
         }
     }
 
+    // None removes the limit entirely, used when every match is needed (e.g. grep candidates)
+    pub fn with_limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    // a sort: in the query text takes priority over this
+    pub fn with_sort(mut self, sort: Option<Sort>) -> Self {
+        self.sort = sort;
+        self
+    }
+
     pub fn replace_characters(&self, query: &str) -> String {
         query
-            .to_string()
-            .replace('"', "")
-            // .replace('\\', "")
-            .replace('?', "")
-            .replace('(', "")
-            .replace(')', "")
-            .replace('[', "")
-            .replace(']', "")
-            .replace('{', "")
-            .replace('}', "")
-            .replace(';', "")
-            .replace('!', "")
-            .replace('@', "")
-            .replace('#', "")
-            .replace('$', "")
-            .replace('&', "")
-            .replace('|', "")
-            .replace('<', "")
-            .replace('>', "")
-            .replace('=', "")
+            .replace(
+                [
+                    '"', '?', '(', ')', '[', ']', '{', '}', ';', '!', '@', '#', '$', '&', '|', '<',
+                    '>', '=',
+                ],
+                "",
+            )
             .replace('/', " + ") // Replace slashes with plus for FTS5
     }
 
-    pub fn make_query(&mut self, query: &str) -> String {
-        // self.original_query = query.to_string();
-        // self.query = query.to_string();
-        // self.operations.clear();
+    // split the raw query into filters, sorting and the text that is passed on to FTS5
+    pub fn make_query(&mut self, query: &str) -> Result<String, String> {
+        self.original_query = query.to_string();
+        self.filters.clear();
 
-        // Replace characters and return the modified query
-        // self.replace_characters(query)
-        query.to_string()
+        let mut text = Vec::new();
+        for token in query.split_whitespace() {
+            if let Some(sort) = token.strip_prefix("sort:").and_then(Sort::parse) {
+                self.sort = Some(sort);
+                continue;
+            }
+            match Filter::parse(token) {
+                Some(filter) => self.filters.push(filter?),
+                None => text.push(token),
+            }
+        }
+
+        self.query = text.join(" ");
+        Ok(self.query.clone())
     }
+
     pub fn get_original_query(&self) -> &str {
         &self.original_query
     }
+
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn get_filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    pub fn get_limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn get_sort(&self) -> Option<Sort> {
+        self.sort
+    }
+
+    // whether a filter matches on what was read from inside the files, mime: and xattr:
+    pub(crate) fn filters_on_contents(&self) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| matches!(filter, Filter::Mime(_) | Filter::Xattr(..)))
+    }
+
+    // WHERE conditions for the filters, joined with AND
+    // returns None if there are no filters
+    pub(crate) fn filter_sql(&self, params: &mut Vec<Value>) -> Option<String> {
+        if self.filters.is_empty() {
+            return None;
+        }
+        let conditions: Vec<String> = self.filters.iter().map(|f| f.to_sql(params)).collect();
+        Some(conditions.join(" AND "))
+    }
+
+    // text searches are ranked by relevance unless another sort was asked for,
+    // LIKE and filter only searches stay in table order so a LIMIT can stop early
+    fn effective_sort(&self, strategy: SearchStrategy) -> Option<Sort> {
+        self.sort.or((strategy == SearchStrategy::Fts5)
+            .then(|| Sort::new(SortKey::Relevance, SortOrder::Descending)))
+    }
+
+    fn join_sql(&self, strategy: SearchStrategy) -> &'static str {
+        match self.effective_sort(strategy) {
+            Some(sort) if sort.key == SortKey::Relevance => {
+                " LEFT JOIN selections ON selections.path = files.path"
+            }
+            _ => "",
+        }
+    }
+
+    fn order_sql(&self, strategy: SearchStrategy) -> String {
+        self.effective_sort(strategy)
+            .map(|sort| sort.to_sql(strategy))
+            .unwrap_or_default()
+    }
+
+    fn limit_sql(&self) -> String {
+        match self.limit {
+            Some(limit) => format!(" LIMIT {}", limit),
+            None => String::new(),
+        }
+    }
 }
 
-pub async fn search_files(
-    query: &str,
-    conn: MutexGuard<'_, Connection>,
-) -> SQLResult<Vec<FileEntry>> {
-    if query.starts_with("LIKE") {
-        // If the query starts with "LIKE", we can skip FTS5 search
-        let search_pattern = query.replace("LIKE", "").trim().to_string();
-        println!(
-            "Skipping FTS5 search, using LIKE search for pattern: {}",
-            search_pattern
-        );
-        return search_normal(&search_pattern, &conn).await;
-    }
-
-    let search_pattern = query.to_string();
-
-    let result = match search_fts5(&search_pattern, &conn).await {
-        Ok(res) => res,
-
-        // if fts5 search fails, fall back to normal LIKE search
-        // this is a fallback to ensure that the search always works
-        Err(e) => {
-            eprintln!("FTS5 search failed: {}", e);
-            eprintln!("Falling back to LIKE search...");
-            match search_normal(&query, &conn).await {
-                Ok(res) => res,
-                Err(e2) => {
-                    eprintln!("LIKE search also failed: {}", e2);
-                    return Err(e2);
+#[instrument(name = "search", skip_all, fields(query = query.get_original_query()))]
+pub async fn search_files(query: &SearchQuery, conn: &Connection) -> SQLResult<Vec<FileEntry>> {
+    let (strategy, search_pattern) = SearchStrategy::choose(query);
+    let result = match strategy {
+        SearchStrategy::Like => {
+            debug!(
+                "Skipping FTS5 search, using LIKE search for pattern: {}",
+                search_pattern
+            );
+            return search_normal(&search_pattern, query, conn).await;
+        }
+        // only filters were given, nothing to match against the FTS5 index
+        SearchStrategy::Filters => return search_filters(query, conn).await,
+        SearchStrategy::Fts5 => match search_fts5(&search_pattern, query, conn).await {
+            Ok(res) => res,
+
+            // if fts5 search fails, fall back to normal LIKE search
+            // this is a fallback to ensure that the search always works
+            Err(e) => {
+                warn!("FTS5 search failed, falling back to LIKE search: {}", e);
+                match search_normal(&search_pattern, query, conn).await {
+                    Ok(res) => res,
+                    Err(e2) => {
+                        warn!("LIKE search also failed: {}", e2);
+                        return Err(e2);
+                    }
                 }
             }
-        }
+        },
     };
 
-    let db_count = get_database_count(&conn).await;
-    if let Err(e) = db_count {
-        eprintln!("Failed to get database count: {}", e);
-    } else {
-        println!("Total files in database: {}", db_count.unwrap());
+    // counting is a full scan, so only bother when someone will see it
+    if tracing::enabled!(Level::DEBUG) {
+        match get_database_count(conn).await {
+            Ok(count) => debug!("Total files in database: {}", count),
+            Err(e) => debug!("Failed to get database count: {}", e),
+        }
     }
+    debug!(results = result.len(), "search finished");
 
     Ok(result)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchStrategy {
+    Fts5,
+    Like,
+    Filters,
+}
+
+impl SearchStrategy {
+    // which search a query runs as, and the pattern it searches for
+    fn choose(query: &SearchQuery) -> (Self, String) {
+        if let Some(pattern) = query.get_query().strip_prefix("LIKE") {
+            // If the query starts with "LIKE", we can skip FTS5 search
+            return (SearchStrategy::Like, pattern.trim().to_string());
+        }
+        if query.get_query().is_empty() {
+            return (SearchStrategy::Filters, String::new());
+        }
+        (SearchStrategy::Fts5, query.get_query().to_string())
+    }
+
+    fn to_sql(self, search_pattern: &str, query: &SearchQuery) -> (String, Vec<Value>) {
+        match self {
+            SearchStrategy::Fts5 => fts5_sql(search_pattern, query),
+            SearchStrategy::Like => normal_sql(search_pattern, query),
+            SearchStrategy::Filters => filters_sql(query),
+        }
+    }
+}
+
+// what `rustsearch search --explain` shows
+#[derive(Debug, Clone)]
+pub struct SearchExplain {
+    pub original_query: String,
+    // the text matched against the index, after filters are taken out
+    pub query: String,
+    pub filters: Vec<Filter>,
+    pub limit: Option<usize>,
+    pub sort: Option<Sort>,
+    pub strategy: SearchStrategy,
+    // why FTS5 was given up on, if it was
+    pub fallback: Option<String>,
+    pub sql: String,
+    pub params: Vec<Value>,
+    // EXPLAIN QUERY PLAN output, indented by depth
+    pub plan: Vec<String>,
+    pub timings: Vec<(&'static str, Duration)>,
+    pub results: Vec<FileEntry>,
+}
+
+// search_files, but keeping track of every step
+pub async fn explain_search(
+    raw_query: &str,
+    limit: Option<usize>,
+    sort: Option<Sort>,
+    conn: &Connection,
+) -> Result<SearchExplain, Box<dyn std::error::Error>> {
+    let mut timings = Vec::new();
+
+    let now = Instant::now();
+    let mut query = SearchQuery::new().with_limit(limit).with_sort(sort);
+    query.make_query(raw_query.trim())?;
+    let (mut strategy, search_pattern) = SearchStrategy::choose(&query);
+    timings.push(("parse", now.elapsed()));
+
+    let mut fallback = None;
+    loop {
+        let (sql, params) = strategy.to_sql(&search_pattern, &query);
+
+        let now = Instant::now();
+        let plan = query_plan(conn, &sql, params.clone()).await?;
+        timings.push(("plan", now.elapsed()));
+
+        match collect_entries_timed(conn, &sql, params.clone(), &mut timings).await {
+            Ok(results) => {
+                return Ok(SearchExplain {
+                    original_query: query.get_original_query().to_string(),
+                    query: query.get_query().to_string(),
+                    filters: query.get_filters().to_vec(),
+                    limit: query.get_limit(),
+                    sort: query.get_sort(),
+                    strategy,
+                    fallback,
+                    sql,
+                    params,
+                    plan,
+                    timings,
+                    results,
+                });
+            }
+            // same fallback as search_files
+            Err(e) if strategy == SearchStrategy::Fts5 => {
+                fallback = Some(e.to_string());
+                strategy = SearchStrategy::Like;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+async fn query_plan(conn: &Connection, sql: &str, params: Vec<Value>) -> SQLResult<Vec<String>> {
+    let mut rows = conn
+        .query(&format!("EXPLAIN QUERY PLAN {}", sql), params)
+        .await?;
+
+    // each row points at its parent, the depth comes from walking back up
+    let mut depths: HashMap<i64, usize> = HashMap::new();
+    let mut plan = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let id: i64 = row.get(0)?;
+        let parent: i64 = row.get(1)?;
+        let detail: String = row.get(3)?;
+
+        let depth = depths.get(&parent).map_or(0, |depth| depth + 1);
+        depths.insert(id, depth);
+        plan.push(format!("{}{}", "  ".repeat(depth), detail));
+    }
+    Ok(plan)
+}
+
 // home/theo godot = home + theo godot
 // matches to include home with theo close by, and godot somewhere in the path
 
@@ -129,62 +750,119 @@ pub async fn search_files(
 //*  Internal search functions, takes directly from the database
 //*
 //*------------------------------------------------------------------------**/
-pub async fn search_fts5(search_pattern: &str, conn: &Connection) -> SQLResult<Vec<FileEntry>> {
-    print!("Searching FTS5 for pattern: {}\n", search_pattern);
-    let mut stmt = conn
-        .prepare("SELECT path, filename FROM files_fts WHERE files_fts MATCH ?1 LIMIT 50")
-        .await?;
+async fn collect_entries(
+    conn: &Connection,
+    sql: &str,
+    params: Vec<Value>,
+) -> SQLResult<Vec<FileEntry>> {
+    let mut stmt = conn.prepare(sql).await?;
+    let mut rows = stmt.query(params).await?;
 
-    let mut rows = match stmt.query([search_pattern]).await {
-        Ok(rows) => rows,
-        Err(_) => return Ok(vec![]),
-    };
-    let mut count = 0;
     let mut entries: Vec<FileEntry> = Vec::new();
-
     while let Some(row) = rows.next().await? {
-        let path: String = row.get(0)?;
-        let filename: String = row.get(1)?;
-        count += 1;
-
-        entries.push(FileEntry {
-            path,
-            filename,
-            extension: "test".to_string(), // Placeholder for extension
-            size: 1,                       // Placeholder for size
-            modified_at: 1,                // Placeholder for modified
-        });
+        entries.push(FileEntry::from_row(&row)?);
     }
 
     Ok(entries)
 }
 
-// Fallback LIKE search - always works
-pub async fn search_normal(query: &str, conn: &Connection) -> SQLResult<Vec<FileEntry>> {
-    let search_pattern = format!("%{}%", query);
+// collect_entries split into its stages, execute runs up to the first row and fetch is the rest
+async fn collect_entries_timed(
+    conn: &Connection,
+    sql: &str,
+    params: Vec<Value>,
+    timings: &mut Vec<(&'static str, Duration)>,
+) -> SQLResult<Vec<FileEntry>> {
+    let now = Instant::now();
+    let mut stmt = conn.prepare(sql).await?;
+    timings.push(("prepare", now.elapsed()));
 
-    let mut stmt = conn
-        .prepare("SELECT path, filename FROM files WHERE filename LIKE ?1 OR path LIKE ?1 LIMIT 50")
-        .await?;
+    let now = Instant::now();
+    let mut rows = stmt.query(params).await?;
+    let mut next = rows.next().await?;
+    timings.push(("execute", now.elapsed()));
 
-    let mut rows = stmt.query([search_pattern]).await?;
-    let mut count = 0;
+    let now = Instant::now();
     let mut entries: Vec<FileEntry> = Vec::new();
+    while let Some(row) = next {
+        entries.push(FileEntry::from_row(&row)?);
+        next = rows.next().await?;
+    }
+    timings.push(("fetch", now.elapsed()));
 
-    while let Some(row) = rows.next().await? {
-        let path: String = row.get(0)?;
-        let filename: String = row.get(1)?;
+    Ok(entries)
+}
 
-        entries.push(FileEntry {
-            path,
-            filename,
-            extension: "test".to_string(), // Placeholder for extension
-            size: 1,
-            modified_at: 1,
-        });
+fn fts5_sql(search_pattern: &str, query: &SearchQuery) -> (String, Vec<Value>) {
+    let mut params = vec![Value::Text(search_pattern.to_string())];
+    let mut sql = format!(
+        "SELECT {} FROM files_fts JOIN files ON files.id = files_fts.rowid{} WHERE files_fts MATCH ?1",
+        FILE_COLUMNS,
+        query.join_sql(SearchStrategy::Fts5)
+    );
+    if let Some(filters) = query.filter_sql(&mut params) {
+        sql.push_str(" AND ");
+        sql.push_str(&filters);
+    }
+    sql.push_str(&query.order_sql(SearchStrategy::Fts5));
+    sql.push_str(&query.limit_sql());
+    (sql, params)
+}
 
-        count += 1;
+fn normal_sql(search_pattern: &str, query: &SearchQuery) -> (String, Vec<Value>) {
+    let mut params = vec![Value::Text(format!("%{}%", search_pattern))];
+    let mut sql = format!(
+        "SELECT {} FROM files{} WHERE (files.filename LIKE ?1 OR files.path LIKE ?1)",
+        FILE_COLUMNS,
+        query.join_sql(SearchStrategy::Like)
+    );
+    if let Some(filters) = query.filter_sql(&mut params) {
+        sql.push_str(" AND ");
+        sql.push_str(&filters);
     }
+    sql.push_str(&query.order_sql(SearchStrategy::Like));
+    sql.push_str(&query.limit_sql());
+    (sql, params)
+}
 
-    Ok(entries)
+fn filters_sql(query: &SearchQuery) -> (String, Vec<Value>) {
+    let mut params = Vec::new();
+    let mut sql = format!(
+        "SELECT {} FROM files{}",
+        FILE_COLUMNS,
+        query.join_sql(SearchStrategy::Filters)
+    );
+    if let Some(filters) = query.filter_sql(&mut params) {
+        sql.push_str(" WHERE ");
+        sql.push_str(&filters);
+    }
+    sql.push_str(&query.order_sql(SearchStrategy::Filters));
+    sql.push_str(&query.limit_sql());
+    (sql, params)
+}
+
+pub async fn search_fts5(
+    search_pattern: &str,
+    query: &SearchQuery,
+    conn: &Connection,
+) -> SQLResult<Vec<FileEntry>> {
+    debug!("Searching FTS5 for pattern: {}", search_pattern);
+    let (sql, params) = fts5_sql(search_pattern, query);
+    collect_entries(conn, &sql, params).await
+}
+
+// Fallback LIKE search - always works
+pub async fn search_normal(
+    search_pattern: &str,
+    query: &SearchQuery,
+    conn: &Connection,
+) -> SQLResult<Vec<FileEntry>> {
+    let (sql, params) = normal_sql(search_pattern, query);
+    collect_entries(conn, &sql, params).await
+}
+
+// Filter-only search, e.g. "ext:rs path:src"
+pub async fn search_filters(query: &SearchQuery, conn: &Connection) -> SQLResult<Vec<FileEntry>> {
+    let (sql, params) = filters_sql(query);
+    collect_entries(conn, &sql, params).await
 }
@@ -0,0 +1,134 @@
+// Sizes for the size: filter, and for showing sizes to people
+//
+//   size:>100mb   size:<=4kib   size:1k..10k   size:0   size:empty
+//   tiny, small, medium, large, huge, gigantic   the same buckets as Everything
+//
+// units follow fd: k/kb/m/mb/... are powers of 1000 and ki/kib/mi/mib/... powers of 1024,
+// decimals are allowed (1.5gb). a bare number is bytes
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+// bytes, both ends inclusive, None is unbounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeRange {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl SizeRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.to_lowercase();
+
+        if let Some((min, max)) = value.split_once("..") {
+            if min.is_empty() && max.is_empty() {
+                return None;
+            }
+            let min = match min {
+                "" => None,
+                min => Some(parse_bytes(min)?),
+            };
+            let max = match max {
+                "" => None,
+                max => Some(parse_bytes(max)?),
+            };
+            return Some(Self { min, max });
+        }
+
+        // the two character operators have to be checked first
+        if let Some(rest) = value.strip_prefix(">=") {
+            return Some(Self::at_least(parse_bytes(rest)?));
+        }
+        if let Some(rest) = value.strip_prefix("<=") {
+            return Some(Self::at_most(parse_bytes(rest)?));
+        }
+        if let Some(rest) = value.strip_prefix('>') {
+            return Some(Self::at_least(parse_bytes(rest)?.checked_add(1)?));
+        }
+        if let Some(rest) = value.strip_prefix('<') {
+            return Some(Self::at_most(parse_bytes(rest)?.checked_sub(1)?));
+        }
+
+        let (min, max) = match value.as_str() {
+            "empty" => (0, Some(0)),
+            "tiny" => (0, Some(10 * KIB)),
+            "small" => (10 * KIB + 1, Some(100 * KIB)),
+            "medium" => (100 * KIB + 1, Some(MIB)),
+            "large" => (MIB + 1, Some(16 * MIB)),
+            "huge" => (16 * MIB + 1, Some(128 * MIB)),
+            "gigantic" => (128 * MIB + 1, None),
+            _ => {
+                let bytes = parse_bytes(&value)?;
+                (bytes, Some(bytes))
+            }
+        };
+        Some(Self {
+            min: Some(min),
+            max,
+        })
+    }
+
+    fn at_least(min: u64) -> Self {
+        Self {
+            min: Some(min),
+            max: None,
+        }
+    }
+
+    fn at_most(max: u64) -> Self {
+        Self {
+            min: None,
+            max: Some(max),
+        }
+    }
+}
+
+// 512, 4k, 1.5mb, 2gib
+fn parse_bytes(value: &str) -> Option<u64> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    if amount.is_empty() {
+        return None;
+    }
+
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000u64.pow(2),
+        "g" | "gb" => 1000u64.pow(3),
+        "t" | "tb" => 1000u64.pow(4),
+        "ki" | "kib" => KIB,
+        "mi" | "mib" => MIB,
+        "gi" | "gib" => 1024u64.pow(3),
+        "ti" | "tib" => 1024u64.pow(4),
+        _ => return None,
+    };
+
+    // whole numbers stay exact, decimals go through f64
+    match amount.parse::<u64>() {
+        Ok(amount) => amount.checked_mul(multiplier),
+        Err(_) => {
+            let amount: f64 = amount.parse().ok()?;
+            let bytes = (amount * multiplier as f64).round();
+            (bytes.is_finite() && bytes >= 0.0 && bytes < u64::MAX as f64).then_some(bytes as u64)
+        }
+    }
+}
+
+// 512 B, 1.2 MiB, 3.0 GiB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < KIB {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / KIB as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -105,3 +105,4506 @@
 //         let results = engine.search(query).await;
 //     }
 // }
+
+// Fixtures shared by the test modules below
+#[cfg(test)]
+mod test_support {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use jwalk::{ClientState, DirEntry, WalkDirGeneric};
+    use libsql::Connection;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        content::ContentOptions,
+        database::{create_schema, insert_files_to_db, start_index_run, BulkWriter, FileRecord},
+        progress::IndexProgress,
+        search::{search_files, SearchQuery},
+    };
+
+    // a database at `path` with the current schema
+    pub async fn open(path: &Path) -> Connection {
+        let database = libsql::Builder::new_local(path).build().await.unwrap();
+        let conn = database.connect().unwrap();
+        create_schema(conn.clone(), None).await.unwrap();
+        conn
+    }
+
+    // for tests that don't need anything else in the directory
+    pub async fn temp_database() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        (dir, conn)
+    }
+
+    // a file with only its path and a modified time, tests fill in what they look at
+    // with ..record(path)
+    pub fn record(path: impl Into<PathBuf>) -> FileRecord {
+        FileRecord {
+            modified_at: 1700000000,
+            ..FileRecord::from_path(path.into())
+        }
+    }
+
+    pub async fn write(conn: &Connection, records: &[FileRecord]) {
+        let mut writer = BulkWriter::new(conn).await.unwrap();
+        let errors = writer.write(records).await;
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    // one index run over `walk`, the way index_files does it
+    pub async fn index_with<C: ClientState>(
+        conn: &Connection,
+        walk: impl IntoIterator<Item = jwalk::Result<DirEntry<C>>>,
+        content: ContentOptions,
+    ) {
+        let run_id = start_index_run(conn).await.unwrap();
+        insert_files_to_db(
+            walk,
+            Arc::new(Mutex::new(conn.clone())),
+            run_id,
+            Arc::new(IndexProgress::new()),
+            content,
+        )
+        .await
+        .unwrap();
+    }
+
+    pub async fn index_tree(conn: &Connection, tree: &Path) {
+        let walk = WalkDirGeneric::<(usize, bool)>::new(tree);
+        index_with(conn, walk, ContentOptions::default()).await;
+    }
+
+    // the paths `query` finds, in the order they came back
+    pub async fn search(conn: &Connection, query: &str) -> Vec<String> {
+        let mut search_query = SearchQuery::new().with_limit(None);
+        search_query.make_query(query).unwrap();
+        search_files(&search_query, conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path.display().to_string())
+            .collect()
+    }
+
+    pub async fn search_sorted(conn: &Connection, query: &str) -> Vec<String> {
+        let mut paths = search(conn, query).await;
+        paths.sort();
+        paths
+    }
+
+    // the first column of the first row, for COUNT(*) and the like
+    pub async fn count(conn: &Connection, sql: &str) -> i64 {
+        let mut rows = conn.query(sql, ()).await.unwrap();
+        rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod migration_tests {
+    use libsql::Connection;
+
+    use crate::{
+        database::{create_schema, get_schema_version, migrate, save_search, SCHEMA_VERSION},
+        tests::test_support::{count, record, temp_database, write},
+    };
+
+    // the schema at each previous version, with a couple of indexed files
+    // every migration added to database.rs needs a fixture for the version it upgrades from
+    const FIXTURES: &[(i64, &str)] = &[
+        // before versioning, files_fts was only updated by a manual rebuild
+        (
+            0,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // files_fts kept in sync by triggers
+        (
+            1,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // index runs and checkpoints, non-UTF-8 paths were written as blobs
+        (
+            2,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100),
+                (X'2F686F6D652F7468656F2F636166E92E7273', X'636166E92E7273', 'rs', 1, 1700000200);",
+        ),
+        // raw bytes for non-UTF-8 paths
+        (
+            3,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // indexes for sorting
+        (
+            4,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // created and accessed times
+        (
+            5,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // search history and saved searches
+        (
+            6,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // selections for frecency
+        (
+            7,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // the changes log
+        (
+            8,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // device and inode
+        (
+            9,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // symlink targets
+        (
+            10,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                link_target TEXT,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // the mount table
+        (
+            11,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                link_target TEXT,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;
+            CREATE TABLE mounts (
+                device      INTEGER PRIMARY KEY,
+                mount_point TEXT NOT NULL,
+                fstype      TEXT NOT NULL,
+                source      TEXT
+            );
+            CREATE INDEX mounts_fstype ON mounts(fstype);
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // owners, groups and modes
+        (
+            12,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                link_target TEXT,
+                uid         INTEGER,
+                gid         INTEGER,
+                mode        INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;
+            CREATE TABLE mounts (
+                device      INTEGER PRIMARY KEY,
+                mount_point TEXT NOT NULL,
+                fstype      TEXT NOT NULL,
+                source      TEXT
+            );
+            CREATE INDEX mounts_fstype ON mounts(fstype);
+            CREATE TABLE directories (
+                path TEXT PRIMARY KEY,
+                uid  INTEGER NOT NULL,
+                gid  INTEGER NOT NULL,
+                mode INTEGER NOT NULL
+            );
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // owner and group indexes
+        (
+            13,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                link_target TEXT,
+                uid         INTEGER,
+                gid         INTEGER,
+                mode        INTEGER,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;
+            CREATE TABLE mounts (
+                device      INTEGER PRIMARY KEY,
+                mount_point TEXT NOT NULL,
+                fstype      TEXT NOT NULL,
+                source      TEXT
+            );
+            CREATE INDEX mounts_fstype ON mounts(fstype);
+            CREATE TABLE directories (
+                path TEXT PRIMARY KEY,
+                uid  INTEGER NOT NULL,
+                gid  INTEGER NOT NULL,
+                mode INTEGER NOT NULL
+            );
+            CREATE INDEX files_uid ON files(uid);
+            CREATE INDEX files_gid ON files(gid);
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+        // mime types and xattrs
+        (
+            14,
+            "CREATE TABLE files (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                filename    TEXT NOT NULL,
+                extension   TEXT,
+                size        INTEGER NOT NULL,
+                modified_at TEXT NOT NULL,
+                path_bytes  BLOB,
+                created_at  INTEGER,
+                accessed_at INTEGER,
+                indexed_at  INTEGER,
+                device      INTEGER,
+                inode       INTEGER,
+                link_target TEXT,
+                uid         INTEGER,
+                gid         INTEGER,
+                mode        INTEGER,
+                mime        TEXT,
+                xattrs      TEXT,
+                UNIQUE(path)
+            );
+            CREATE VIRTUAL TABLE files_fts USING fts5(
+                filename, path, extension, content='files', content_rowid='id'
+            );
+            CREATE TRIGGER files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TRIGGER files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+            END;
+            CREATE TRIGGER files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts(files_fts, rowid, filename, path, extension)
+                VALUES ('delete', old.id, old.filename, old.path, old.extension);
+                INSERT INTO files_fts(rowid, filename, path, extension)
+                VALUES (new.id, new.filename, new.path, new.extension);
+            END;
+            CREATE TABLE index_runs (
+                id            INTEGER PRIMARY KEY,
+                started_at    INTEGER NOT NULL,
+                finished_at   INTEGER,
+                status        TEXT NOT NULL,
+                files_indexed INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE index_checkpoints (
+                run_id    INTEGER NOT NULL REFERENCES index_runs(id),
+                directory TEXT NOT NULL,
+                PRIMARY KEY (run_id, directory)
+            );
+            CREATE INDEX files_filename_nocase ON files(filename COLLATE NOCASE);
+            CREATE INDEX files_size ON files(size);
+            CREATE INDEX files_modified_at ON files(CAST(modified_at AS INTEGER));
+            CREATE INDEX files_extension_lower ON files(LOWER(extension));
+            CREATE INDEX files_depth ON files(length(path) - length(replace(path, '/', '')));
+            CREATE INDEX files_created_at ON files(created_at);
+            CREATE INDEX files_accessed_at ON files(accessed_at);
+            CREATE TABLE queries (
+                id           INTEGER PRIMARY KEY,
+                query        TEXT NOT NULL,
+                ran_at       INTEGER NOT NULL,
+                result_count INTEGER NOT NULL
+            );
+            CREATE TABLE saved_searches (
+                name       TEXT PRIMARY KEY,
+                query      TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE selections (
+                path             TEXT PRIMARY KEY,
+                count            INTEGER NOT NULL,
+                last_selected_at INTEGER NOT NULL
+            );
+            CREATE TABLE changes (
+                id             INTEGER PRIMARY KEY,
+                kind           TEXT NOT NULL,
+                changed_at     INTEGER NOT NULL,
+                path           TEXT NOT NULL,
+                path_bytes     BLOB,
+                filename       TEXT NOT NULL,
+                extension      TEXT,
+                size           INTEGER NOT NULL,
+                modified_at    INTEGER NOT NULL,
+                created_at     INTEGER,
+                accessed_at    INTEGER,
+                old_path       TEXT,
+                old_path_bytes BLOB
+            );
+            CREATE INDEX changes_changed_at ON changes(changed_at);
+            CREATE TRIGGER files_changes_insert AFTER INSERT ON files
+            WHEN new.indexed_at IS NOT NULL
+                AND EXISTS (SELECT 1 FROM index_runs WHERE status = 'completed')
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('created', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_update AFTER UPDATE ON files
+            WHEN new.path = old.path AND old.indexed_at IS NOT NULL AND new.indexed_at IS NOT NULL
+                AND (new.size != old.size OR new.modified_at != old.modified_at)
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('modified', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at);
+            END;
+            CREATE TRIGGER files_changes_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at, old_path, old_path_bytes)
+                VALUES ('renamed', unixepoch(), new.path, new.path_bytes, new.filename,
+                    new.extension, new.size, new.modified_at, new.created_at, new.accessed_at,
+                    old.path, old.path_bytes);
+            END;
+            CREATE TRIGGER files_changes_delete AFTER DELETE ON files
+            WHEN old.indexed_at IS NOT NULL
+            BEGIN
+                INSERT INTO changes (kind, changed_at, path, path_bytes, filename, extension,
+                    size, modified_at, created_at, accessed_at)
+                VALUES ('deleted', unixepoch(), old.path, old.path_bytes, old.filename,
+                    old.extension, old.size, old.modified_at, old.created_at, old.accessed_at);
+            END;
+            CREATE INDEX files_device_inode ON files(device, inode);
+            CREATE TRIGGER files_selections_rename AFTER UPDATE OF path ON files
+            WHEN new.path != old.path
+            BEGIN
+                UPDATE OR REPLACE selections SET path = new.path WHERE path = old.path;
+            END;
+            CREATE INDEX files_link_target ON files(link_target) WHERE link_target IS NOT NULL;
+            CREATE TABLE mounts (
+                device      INTEGER PRIMARY KEY,
+                mount_point TEXT NOT NULL,
+                fstype      TEXT NOT NULL,
+                source      TEXT
+            );
+            CREATE INDEX mounts_fstype ON mounts(fstype);
+            CREATE TABLE directories (
+                path TEXT PRIMARY KEY,
+                uid  INTEGER NOT NULL,
+                gid  INTEGER NOT NULL,
+                mode INTEGER NOT NULL
+            );
+            CREATE INDEX files_uid ON files(uid);
+            CREATE INDEX files_gid ON files(gid);
+            CREATE INDEX files_mime ON files(mime);
+            INSERT INTO files (path, filename, extension, size, modified_at) VALUES
+                ('/home/theo/Projects/main.rs', 'main.rs', 'rs', 120, 1700000000),
+                ('/home/theo/notes.txt', 'notes.txt', 'txt', 42, 1700000100);",
+        ),
+    ];
+
+    async fn open_fixture(dir: &tempfile::TempDir, sql: &str) -> Connection {
+        let path = dir.path().join("search.db");
+        let database = libsql::Builder::new_local(path).build().await.unwrap();
+        let conn = database.connect().unwrap();
+        conn.execute_batch(sql).await.unwrap();
+        conn
+    }
+
+    #[test]
+    fn every_previous_version_has_a_fixture() {
+        for version in 0..SCHEMA_VERSION {
+            assert!(
+                FIXTURES.iter().any(|(v, _)| *v == version),
+                "missing migration fixture for schema version {}",
+                version
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_fixtures_from_every_previous_version() {
+        for (version, sql) in FIXTURES {
+            let dir = tempfile::tempdir().unwrap();
+            let conn = open_fixture(&dir, sql).await;
+            conn.execute(&format!("PRAGMA user_version = {}", version), ())
+                .await
+                .unwrap();
+
+            migrate(&conn).await.unwrap();
+
+            assert_eq!(get_schema_version(&conn).await.unwrap(), SCHEMA_VERSION);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM files").await, 2);
+            // existing rows are searchable and new rows reach the index through the triggers
+            assert_eq!(
+                count(
+                    &conn,
+                    "SELECT COUNT(*) FROM files_fts WHERE files_fts MATCH 'main'"
+                )
+                .await,
+                1,
+                "fixture version {}",
+                version
+            );
+            conn.execute(
+                "INSERT INTO files (path, filename, extension, size, modified_at)
+                 VALUES ('/home/theo/todo.md', 'todo.md', 'md', 1, 1700000200)",
+                (),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                count(
+                    &conn,
+                    "SELECT COUNT(*) FROM files_fts WHERE files_fts MATCH 'todo'"
+                )
+                .await,
+                1
+            );
+            conn.execute(
+                "INSERT INTO files_fts(files_fts, rank) VALUES('integrity-check', 1)",
+                (),
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn doubles_backslashes_in_display_paths() {
+        let (_, sql) = FIXTURES.iter().find(|(version, _)| *version == 14).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open_fixture(&dir, sql).await;
+        conn.execute_batch(
+            r"INSERT INTO files (path, filename, extension, size, modified_at, link_target)
+              VALUES ('/srv/a\b.txt', 'a\b.txt', 'txt', 1, 1700000000, '/mnt/c\d');
+              INSERT INTO index_runs (started_at, status) VALUES (1700000000, 'completed');
+              INSERT INTO selections (path, count, last_selected_at) VALUES
+                  ('/srv/a\b.txt', 3, 1700000000),
+                  ('/srv/gone\old.txt', 1, 1700000000);
+              INSERT INTO changes (kind, changed_at, path, filename, size, modified_at, old_path)
+              VALUES
+                  ('deleted', 1700000000, '/srv/gone\old.txt', 'gone\old.txt', 1, 1700000000,
+                      NULL),
+                  ('renamed', 1700000000, '/srv/a\b.txt', 'a\b.txt', 1, 1700000000,
+                      '/srv/draft\b.txt');
+              PRAGMA user_version = 14;",
+        )
+        .await
+        .unwrap();
+
+        migrate(&conn).await.unwrap();
+
+        let mut rows = conn
+            .query(
+                r"SELECT path, path_bytes, filename, link_target FROM files WHERE filename LIKE 'a%'",
+                (),
+            )
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get::<String>(0).unwrap(), r"/srv/a\\b.txt");
+        assert_eq!(row.get::<Vec<u8>>(1).unwrap(), br"/srv/a\b.txt");
+        assert_eq!(row.get::<String>(2).unwrap(), r"a\\b.txt");
+        assert_eq!(row.get::<String>(3).unwrap(), r"/mnt/c\\d");
+
+        // frecency and history still line up with the files, escaped once
+        let strings = |sql: &'static str| {
+            let conn = conn.clone();
+            async move {
+                let mut rows = conn.query(sql, ()).await.unwrap();
+                let mut values = Vec::new();
+                while let Some(row) = rows.next().await.unwrap() {
+                    values.push(row.get::<Option<String>>(0).unwrap().unwrap_or_default());
+                }
+                values
+            }
+        };
+        assert_eq!(
+            strings("SELECT path FROM selections ORDER BY path").await,
+            [r"/srv/a\\b.txt", r"/srv/gone\\old.txt"]
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files JOIN selections USING (path) WHERE count = 3"
+            )
+            .await,
+            1
+        );
+        assert_eq!(
+            strings("SELECT path FROM changes ORDER BY id").await,
+            [r"/srv/gone\\old.txt", r"/srv/a\\b.txt"]
+        );
+        assert_eq!(
+            strings("SELECT old_path FROM changes ORDER BY id").await,
+            ["", r"/srv/draft\\b.txt"]
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM changes WHERE path_bytes IS NULL"
+            )
+            .await,
+            0
+        );
+        // the paths didn't change on disk, so nothing new is logged as renamed
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM changes").await, 2);
+    }
+
+    #[tokio::test]
+    async fn migrates_empty_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open_fixture(&dir, "").await;
+
+        migrate(&conn).await.unwrap();
+        // running again on an up to date database is a no-op
+        migrate(&conn).await.unwrap();
+
+        assert_eq!(get_schema_version(&conn).await.unwrap(), SCHEMA_VERSION);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM files").await, 0);
+    }
+
+    #[tokio::test]
+    async fn refuses_newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open_fixture(&dir, "").await;
+        conn.execute(&format!("PRAGMA user_version = {}", SCHEMA_VERSION + 1), ())
+            .await
+            .unwrap();
+
+        assert!(migrate(&conn).await.is_err());
+        assert_eq!(get_schema_version(&conn).await.unwrap(), SCHEMA_VERSION + 1);
+    }
+
+    #[tokio::test]
+    async fn reset_starts_over_from_an_empty_schema() {
+        let (_dir, conn) = temp_database().await;
+        write(&conn, &[record("/home/theo/notes.txt")]).await;
+        save_search(&conn, "notes", "notes").await.unwrap();
+
+        create_schema(conn.clone(), Some(true)).await.unwrap();
+
+        assert_eq!(get_schema_version(&conn).await.unwrap(), SCHEMA_VERSION);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM files").await, 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM saved_searches").await, 0);
+        write(&conn, &[record("/home/theo/todo.md")]).await;
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files_fts WHERE files_fts MATCH 'todo'"
+            )
+            .await,
+            1
+        );
+    }
+}
+
+#[cfg(test)]
+mod grep_tests {
+    use regex::bytes::Regex;
+    use serde_json::json;
+
+    use crate::grep::{grep_file, grep_files, print_results, print_results_json, GrepOptions};
+
+    fn options(pattern: &str, before_context: usize, after_context: usize) -> GrepOptions {
+        GrepOptions {
+            pattern: Regex::new(pattern).unwrap(),
+            before_context,
+            after_context,
+        }
+    }
+
+    #[test]
+    fn overlapping_context_is_merged_into_one_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        let contents: String = (1..=14)
+            .map(|n| match n {
+                3 | 6 | 12 => format!("line {} TODO\n", n),
+                _ => format!("line {}\n", n),
+            })
+            .collect();
+        std::fs::write(&path, contents).unwrap();
+
+        let result = grep_file(&path, &options("TODO", 2, 2)).unwrap();
+        assert_eq!(result.match_count(), 3);
+        let mut out = Vec::new();
+        print_results(&[result], &mut out).unwrap();
+
+        // 3 and 6 share lines 4 and 5, 12 is far enough away to start a new group
+        let p = path.display();
+        let expected = format!(
+            "{p}-1-line 1
+{p}-2-line 2
+{p}:3:8:line 3 TODO
+{p}-4-line 4
+{p}-5-line 5
+{p}:6:8:line 6 TODO
+{p}-7-line 7
+{p}-8-line 8
+--
+{p}-10-line 10
+{p}-11-line 11
+{p}:12:9:line 12 TODO
+{p}-13-line 13
+{p}-14-line 14
+"
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn binary_and_unreadable_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("main.o");
+        std::fs::write(&binary, b"TODO\0\x7fELF").unwrap();
+        // not UTF-8 but not binary either, it is searched and printed lossily
+        let latin1 = dir.path().join("notes.txt");
+        std::fs::write(&latin1, b"caf\xe9 TODO\n").unwrap();
+        let missing = dir.path().join("missing.txt");
+
+        let results = grep_files(&[binary, latin1.clone(), missing], &options("TODO", 0, 0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, latin1);
+        let line = results[0].lines[0].as_ref().unwrap();
+        assert_eq!(line.column, Some(6));
+        assert_eq!(line.text, "caf\u{fffd} TODO");
+    }
+
+    #[test]
+    fn json_output_is_one_object_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {\n    // TODO\n}\n\nfn TODO() {}\n").unwrap();
+
+        let result = grep_file(&path, &options("TODO", 1, 0)).unwrap();
+        let mut out = Vec::new();
+        print_results_json(&[result], &mut out).unwrap();
+
+        // context lines have no column and group breaks aren't printed
+        let text = path.display().to_string();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                json!({"type": "context", "path": {"text": text}, "line": 1, "text": "fn main() {"}),
+                json!({"type": "match", "path": {"text": text}, "line": 2, "column": 8, "text": "    // TODO"}),
+                json!({"type": "context", "path": {"text": text}, "line": 4, "text": ""}),
+                json!({"type": "match", "path": {"text": text}, "line": 5, "column": 4, "text": "fn TODO() {}"}),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod integrity_tests {
+    use crate::{
+        database::check_integrity,
+        tests::test_support::{record, search, temp_database, write},
+    };
+
+    #[tokio::test]
+    async fn finds_and_rebuilds_a_stale_search_index() {
+        let (_dir, conn) = temp_database().await;
+        write(&conn, &[record("/home/theo/notes.txt")]).await;
+        let report = check_integrity(&conn, false).await.unwrap();
+        assert!(report.is_ok() && report.fts_error.is_none());
+
+        // a row files_fts never hears about
+        conn.execute("DROP TRIGGER files_fts_insert", ())
+            .await
+            .unwrap();
+        write(&conn, &[record("/home/theo/todo.md")]).await;
+        assert!(search(&conn, "todo").await.is_empty());
+
+        let report = check_integrity(&conn, false).await.unwrap();
+        assert!(report.database_errors.is_empty());
+        assert!(report.fts_error.is_some());
+        assert!(!report.repaired && !report.is_ok());
+
+        let report = check_integrity(&conn, true).await.unwrap();
+        assert!(report.repaired && report.is_ok());
+        assert!(check_integrity(&conn, false)
+            .await
+            .unwrap()
+            .fts_error
+            .is_none());
+        assert_eq!(search(&conn, "todo").await, ["/home/theo/todo.md"]);
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use std::time::Duration;
+
+    use crate::{database::create_schema, pool::ReaderPool};
+
+    #[tokio::test]
+    async fn readers_see_committed_rows_while_writer_is_busy() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = libsql::Builder::new_local(dir.path().join("search.db"))
+            .build()
+            .await
+            .unwrap();
+        let writer = database.connect().unwrap();
+        create_schema(writer.clone(), None).await.unwrap();
+        let readers = ReaderPool::new(&database, 2).await.unwrap();
+
+        writer
+            .execute(
+                "INSERT INTO files (path, filename, extension, size, modified_at)
+                 VALUES ('/a/committed.rs', 'committed.rs', 'rs', 1, 1)",
+                (),
+            )
+            .await
+            .unwrap();
+        // an open write transaction, like the indexer in the middle of a batch
+        writer
+            .execute_batch(
+                "BEGIN IMMEDIATE;
+                 INSERT INTO files (path, filename, extension, size, modified_at)
+                 VALUES ('/a/pending.rs', 'pending.rs', 'rs', 1, 1);",
+            )
+            .await
+            .unwrap();
+
+        let conn = readers.get().await;
+        let search = async {
+            let mut rows = conn
+                .query("SELECT path FROM files_fts WHERE files_fts MATCH 'rs'", ())
+                .await
+                .unwrap();
+            let mut paths = Vec::new();
+            while let Some(row) = rows.next().await.unwrap() {
+                paths.push(row.get::<String>(0).unwrap());
+            }
+            paths
+        };
+        // sqlite calls block the thread, so time it rather than relying on a tokio timeout
+        let started = std::time::Instant::now();
+        let paths = search.await;
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "search waited on the writer"
+        );
+        assert_eq!(paths, vec!["/a/committed.rs".to_string()]);
+
+        // readers can't write, even by accident
+        assert!(conn.execute("DELETE FROM files", ()).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod bulk_writer_tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        database::{BulkWriter, FileRecord},
+        tests::test_support::{count, record, temp_database},
+    };
+
+    #[tokio::test]
+    async fn reports_failed_rows_and_keeps_the_rest() {
+        let (_dir, conn) = temp_database().await;
+        // stands in for any row sqlite refuses
+        conn.execute_batch(
+            "CREATE TRIGGER reject_bad BEFORE INSERT ON files WHEN new.filename = 'bad.rs'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        )
+        .await
+        .unwrap();
+
+        // 250 rows covers full chunks, the failing chunk and a short final chunk
+        let mut records: Vec<FileRecord> = (0..250)
+            .map(|i| record(PathBuf::from(format!("/src/file_{}.rs", i))))
+            .collect();
+        records[120] = record(PathBuf::from("/src/bad.rs"));
+
+        let mut writer = BulkWriter::new(&conn).await.unwrap();
+        let errors = writer.write(&records).await;
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, PathBuf::from("/src/bad.rs"));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM files").await, 249);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn round_trips_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        use crate::{
+            paths::write_path_nul,
+            search::{search_files, SearchQuery},
+        };
+
+        let (_dir, conn) = temp_database().await;
+
+        let raw = b"/src/caf\xe9.rs";
+        let mut writer = BulkWriter::new(&conn).await.unwrap();
+        let errors = writer
+            .write(&[record(PathBuf::from(OsStr::from_bytes(raw)))])
+            .await;
+        assert!(errors.is_empty());
+
+        // path holds a readable form for searching, path_bytes the exact bytes
+        let mut rows = conn
+            .query("SELECT path, path_bytes FROM files", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get::<String>(0).unwrap(), "/src/caf\\xE9.rs");
+        assert_eq!(row.get::<Vec<u8>>(1).unwrap(), raw.to_vec());
+
+        let mut query = SearchQuery::new();
+        query.make_query("caf").unwrap();
+        let results = search_files(&query, &conn).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path.as_os_str().as_bytes(), raw);
+
+        let mut nul = Vec::new();
+        write_path_nul(&results[0].path, &mut nul).unwrap();
+        assert_eq!(nul, b"/src/caf\xe9.rs\0");
+
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert_eq!(json["path"]["bytes"], "L3NyYy9jYWbpLnJz");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn backslashes_are_not_mistaken_for_invalid_bytes() {
+        use std::{
+            collections::HashSet,
+            ffi::OsStr,
+            os::unix::{ffi::OsStrExt, fs::symlink},
+        };
+
+        use crate::{
+            paths::{display_path, path_from_display},
+            search::{search_files, SearchQuery},
+            tests::test_support::{index_tree, open},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        // one file really called a\xE9, the other a followed by the byte 0xE9
+        let literal = tree.join(r"a\xE9");
+        let raw = tree.join(OsStr::from_bytes(b"a\xe9"));
+        std::fs::write(&literal, "").unwrap();
+        std::fs::write(&raw, "").unwrap();
+        symlink(&literal, tree.join("link")).unwrap();
+        assert_ne!(
+            display_path(literal.as_os_str()),
+            display_path(raw.as_os_str())
+        );
+        assert_eq!(path_from_display(&display_path(raw.as_os_str())), raw);
+
+        let conn = open(&dir.path().join("search.db")).await;
+        index_tree(&conn, &tree).await;
+
+        let mut query = SearchQuery::new().with_limit(None);
+        query.make_query("").unwrap();
+        let results = search_files(&query, &conn).await.unwrap();
+        let paths: HashSet<PathBuf> = results.iter().map(|entry| entry.path.clone()).collect();
+        assert_eq!(
+            paths,
+            HashSet::from([literal.clone(), raw, tree.join("link")])
+        );
+        let link = results
+            .iter()
+            .find(|entry| entry.path == tree.join("link"))
+            .unwrap();
+        assert_eq!(link.link_target.as_deref(), Some(literal.as_path()));
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use std::sync::Arc;
+
+    use jwalk::WalkDirGeneric;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        content::ContentOptions,
+        database::{insert_files_to_db, start_index_run, IndexRunStatus},
+        progress::{IndexEvent, IndexProgress},
+        tests::test_support::open,
+    };
+
+    #[tokio::test]
+    async fn reports_committed_files_and_finishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(tree.join("src")).unwrap();
+        for name in ["a.rs", "b.rs", "src/c.rs"] {
+            std::fs::write(tree.join(name), "fn main() {}").unwrap();
+        }
+
+        let conn = open(&dir.path().join("search.db")).await;
+        let run_id = start_index_run(&conn).await.unwrap();
+
+        let progress = Arc::new(IndexProgress::new());
+        let mut events = progress.subscribe();
+        // the last run was twice as big, so the ETA has something to go on
+        progress.started(run_id, None, Some(6));
+
+        let walk = WalkDirGeneric::<(usize, bool)>::new(&tree);
+        insert_files_to_db(
+            walk,
+            Arc::new(Mutex::new(conn)),
+            run_id,
+            progress.clone(),
+            ContentOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert!(matches!(
+            received.first(),
+            Some(IndexEvent::Started { resumed: false, .. })
+        ));
+        assert!(received.iter().any(|e| matches!(
+            e,
+            IndexEvent::Queued {
+                directories_scanned: 2,
+                files_queued: 3
+            }
+        )));
+        assert!(received.iter().any(|e| matches!(
+            e,
+            IndexEvent::Committed {
+                files_committed: 3,
+                eta: Some(_)
+            }
+        )));
+        assert!(matches!(
+            received.last(),
+            Some(IndexEvent::Finished {
+                status: IndexRunStatus::Completed,
+                files_committed: 3,
+                errors: 0,
+                ..
+            })
+        ));
+
+        let stats = progress.stats();
+        assert!(!stats.indexing);
+        assert_eq!(stats.files_committed, 3);
+        assert_eq!(stats.eta, None);
+    }
+}
+
+#[cfg(test)]
+mod resume_tests {
+    use std::{
+        collections::HashSet,
+        ffi::OsStr,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use libsql::Connection;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        config::Config,
+        content::ContentOptions,
+        database::{
+            get_completed_directories, get_interrupted_run, insert_files_to_db, start_index_run,
+        },
+        paths::display_path,
+        progress::IndexProgress,
+        tests::test_support::{count, index_tree, open},
+    };
+
+    // a run over `tree` that skips `completed`, like index_directories does. with `kill`, the
+    // walk stops dead once it runs out of entries, before its last batch is committed
+    fn walk(conn: &Connection, tree: &Path, run_id: i64, completed: HashSet<PathBuf>, kill: bool) {
+        let config = Config {
+            roots: vec![tree.to_path_buf()],
+            ..Config::default()
+        };
+        let walks = crate::get_directories(&config, &[], completed).unwrap();
+        let conn = Arc::new(Mutex::new(conn.clone()));
+        let result = std::thread::spawn(move || {
+            let killed = std::iter::from_fn(move || {
+                if kill {
+                    std::panic::resume_unwind(Box::new("killed"));
+                }
+                None
+            });
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime
+                .block_on(insert_files_to_db(
+                    walks.into_iter().flatten().chain(killed),
+                    conn,
+                    run_id,
+                    Arc::new(IndexProgress::new()),
+                    ContentOptions::default(),
+                ))
+                .unwrap();
+        })
+        .join();
+        assert_eq!(result.is_err(), kill);
+    }
+
+    async fn files_under(conn: &Connection, directory: &Path) -> i64 {
+        count(
+            conn,
+            &format!(
+                "SELECT COUNT(*) FROM files WHERE path LIKE '{}/%'",
+                display_path(directory.as_os_str())
+            ),
+        )
+        .await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resumes_an_interrupted_run_where_it_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let (a, b) = (tree.join("a"), tree.join(OsStr::from_bytes(b"caf\xe9")));
+        // more than a batch each, so the first directory is checkpointed before the kill. one
+        // isn't valid UTF-8, its checkpoint still has to match it exactly
+        for directory in [&a, &b] {
+            std::fs::create_dir_all(directory).unwrap();
+            for i in 0..600 {
+                std::fs::write(directory.join(format!("{}.txt", i)), "").unwrap();
+            }
+        }
+        let conn = open(&dir.path().join("search.db")).await;
+        index_tree(&conn, &tree).await;
+        // as if that index ran a minute ago
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+
+        let run_id = start_index_run(&conn).await.unwrap();
+        walk(&conn, &tree, run_id, HashSet::new(), true);
+        let run = get_interrupted_run(&conn).await.unwrap().unwrap();
+        assert_eq!(run.id, run_id);
+        assert_eq!(run.files_indexed, 1000);
+        let completed = get_completed_directories(&conn, run_id).await.unwrap();
+        // jwalk doesn't sort, either one could have gone first
+        assert_eq!(completed.len(), 1);
+        let (done, rest) = if completed.contains(&a) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        assert!(completed.contains(&done));
+
+        std::fs::write(done.join("late.txt"), "").unwrap();
+        std::fs::write(rest.join("late.txt"), "").unwrap();
+        walk(&conn, &tree, run_id, completed, false);
+
+        assert!(get_interrupted_run(&conn).await.unwrap().is_none());
+        // the checkpointed directory isn't walked again, so its new file isn't seen, and the
+        // files it had aren't taken for deleted either
+        assert_eq!(files_under(&conn, &done).await, 600);
+        assert_eq!(files_under(&conn, &rest).await, 601);
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use crate::{
+        database::{get_database_stats, FileRecord},
+        search::{explain_search, SearchStrategy},
+        tests::test_support::{self, temp_database, write},
+    };
+
+    fn record(path: &str, size: i64) -> FileRecord {
+        FileRecord {
+            size,
+            ..test_support::record(path)
+        }
+    }
+
+    async fn indexed_database() -> (tempfile::TempDir, libsql::Connection) {
+        let (dir, conn) = temp_database().await;
+        write(
+            &conn,
+            &[
+                record("/home/theo/src/main.rs", 100),
+                record("/home/theo/src/lib.rs", 200),
+                record("/home/theo/video.mkv", 5000),
+                record("/srv/notes.txt", 10),
+            ],
+        )
+        .await;
+        (dir, conn)
+    }
+
+    #[tokio::test]
+    async fn counts_files_per_root_and_extension() {
+        let (_dir, conn) = indexed_database().await;
+
+        let stats = get_database_stats(&conn).await.unwrap();
+        assert_eq!(stats.files, 4);
+        assert_eq!(stats.directories, 3);
+        let roots: Vec<(&str, i64, i64)> = stats
+            .roots
+            .iter()
+            .map(|root| (root.root.as_str(), root.files, root.bytes))
+            .collect();
+        assert_eq!(roots, vec![("/home", 3, 5300), ("/srv", 1, 10)]);
+        assert_eq!(stats.top_extensions_by_count[0].extension, "rs");
+        assert_eq!(stats.top_extensions_by_bytes[0].extension, "mkv");
+        assert!(stats.fts_bytes > 0 && stats.database_bytes > stats.fts_bytes);
+    }
+
+    #[tokio::test]
+    async fn explains_the_sql_and_plan_a_query_ran_as() {
+        let (_dir, conn) = indexed_database().await;
+
+        let explain = explain_search("main ext:rs", Some(10), None, &conn)
+            .await
+            .unwrap();
+        assert_eq!(explain.strategy, SearchStrategy::Fts5);
+        assert_eq!(explain.query, "main");
+        assert!(explain.sql.contains("MATCH ?1") && explain.sql.ends_with("LIMIT 10"));
+        assert!(explain.plan.iter().any(|line| line.contains("files_fts")));
+        assert_eq!(explain.results.len(), 1);
+
+        // an invalid FTS5 query falls back to LIKE, the same as search_files
+        let explain = explain_search("main\"", None, None, &conn).await.unwrap();
+        assert_eq!(explain.strategy, SearchStrategy::Like);
+        assert!(explain.fallback.is_some());
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use crate::{
+        database::FileRecord,
+        search::{explain_search, Sort, SortKey, SortOrder},
+        tests::test_support::{self, search, temp_database, write},
+    };
+
+    fn record(path: &str, size: i64, modified_at: i64) -> FileRecord {
+        FileRecord {
+            size,
+            modified_at,
+            ..test_support::record(path)
+        }
+    }
+
+    #[test]
+    fn parses_keys_and_directions() {
+        assert_eq!(
+            Sort::parse("size-desc"),
+            Some(Sort::new(SortKey::Size, SortOrder::Descending))
+        );
+        assert_eq!(
+            Sort::parse("Name"),
+            Some(Sort::new(SortKey::Name, SortOrder::Ascending))
+        );
+        // newest first unless asked otherwise
+        assert_eq!(
+            Sort::parse("modified"),
+            Some(Sort::new(SortKey::Modified, SortOrder::Descending))
+        );
+        assert_eq!(Sort::parse("colour-asc"), None);
+    }
+
+    #[tokio::test]
+    async fn orders_results_by_the_sort_key() {
+        let (_dir, conn) = temp_database().await;
+        // modified_at is stored as TEXT, 999 would sort after 1700000000 if it wasn't cast
+        write(
+            &conn,
+            &[
+                record("/a/b/c/deep.rs", 30, 999),
+                record("/a/Beta.rs", 2000, 1700000000),
+                record("/a/alpha.txt", 100, 1600000000),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            search(&conn, "sort:size-desc").await,
+            ["/a/Beta.rs", "/a/alpha.txt", "/a/b/c/deep.rs"]
+        );
+        assert_eq!(
+            search(&conn, "sort:name").await,
+            ["/a/alpha.txt", "/a/Beta.rs", "/a/b/c/deep.rs"]
+        );
+        assert_eq!(
+            search(&conn, "sort:modified-asc").await,
+            ["/a/b/c/deep.rs", "/a/alpha.txt", "/a/Beta.rs"]
+        );
+        assert_eq!(
+            search(&conn, "ext:rs sort:depth-desc").await,
+            ["/a/b/c/deep.rs", "/a/Beta.rs"]
+        );
+
+        // a sort without any text walks the index rather than sorting the whole table
+        let explain = explain_search("sort:size-desc", Some(50), None, &conn)
+            .await
+            .unwrap();
+        assert!(
+            explain.plan.iter().any(|line| line.contains("files_size")),
+            "{:?}",
+            explain.plan
+        );
+    }
+}
+
+#[cfg(test)]
+mod date_filter_tests {
+    use chrono::{Local, NaiveDate, TimeZone};
+
+    use crate::{
+        database::FileRecord,
+        dates::TimeRange,
+        tests::test_support::{record, search_sorted, temp_database, write},
+    };
+
+    fn local(year: i32, month: u32, day: u32, hour: u32) -> i64 {
+        let time = NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap();
+        Local.from_local_datetime(&time).unwrap().timestamp()
+    }
+
+    fn range(start: Option<i64>, end: Option<i64>) -> Option<TimeRange> {
+        Some(TimeRange { start, end })
+    }
+
+    #[test]
+    fn parses_time_expressions() {
+        // a Wednesday
+        let now = Local.timestamp_opt(local(2025, 3, 12, 15), 0).unwrap();
+        let parse = |value| TimeRange::parse_at(value, now);
+
+        assert_eq!(
+            parse("today"),
+            range(Some(local(2025, 3, 12, 0)), Some(local(2025, 3, 13, 0)))
+        );
+        assert_eq!(
+            parse("last-week"),
+            range(Some(local(2025, 3, 3, 0)), Some(local(2025, 3, 10, 0)))
+        );
+        assert_eq!(
+            parse("last-month"),
+            range(Some(local(2025, 2, 1, 0)), Some(local(2025, 3, 1, 0)))
+        );
+        assert_eq!(
+            parse(">2025-01-01"),
+            range(Some(local(2025, 1, 2, 0)), None)
+        );
+        assert_eq!(parse("<=2025-01"), range(None, Some(local(2025, 2, 1, 0))));
+        assert_eq!(parse("2h"), range(Some(now.timestamp() - 7200), None));
+        assert_eq!(
+            parse("2h..1d"),
+            range(Some(now.timestamp() - 86400), Some(now.timestamp() - 7200))
+        );
+        assert_eq!(
+            parse("2025-01-01..2025-01-31"),
+            range(Some(local(2025, 1, 1, 0)), Some(local(2025, 2, 1, 0)))
+        );
+        assert_eq!(parse("sometime"), None);
+        assert_eq!(parse("99"), None);
+    }
+
+    #[tokio::test]
+    async fn filters_on_modified_created_and_accessed() {
+        let (_dir, conn) = temp_database().await;
+
+        let record = |path: &str, modified_at, created_at| FileRecord {
+            modified_at,
+            created_at,
+            accessed_at: Some(modified_at),
+            ..record(path)
+        };
+        write(
+            &conn,
+            &[
+                record(
+                    "/old.rs",
+                    local(2024, 6, 1, 12),
+                    Some(local(2024, 6, 1, 12)),
+                ),
+                record(
+                    "/new.rs",
+                    local(2025, 2, 1, 12),
+                    Some(local(2024, 12, 1, 12)),
+                ),
+                // no birth time on this filesystem
+                record("/unknown.rs", local(2025, 2, 2, 12), None),
+            ],
+        )
+        .await;
+
+        let paths = |query| search_sorted(&conn, query);
+        assert_eq!(paths("modified:2025").await, ["/new.rs", "/unknown.rs"]);
+        assert_eq!(paths("modified:<2025-01-01").await, ["/old.rs"]);
+        assert_eq!(paths("created:2024").await, ["/new.rs", "/old.rs"]);
+        assert_eq!(paths("accessed:2025-02-02").await, ["/unknown.rs"]);
+    }
+}
+
+#[cfg(test)]
+mod size_filter_tests {
+    use crate::{
+        database::FileRecord,
+        sizes::{format_size, SizeRange},
+        tests::test_support::{record, search, temp_database, write},
+    };
+
+    fn range(min: Option<u64>, max: Option<u64>) -> Option<SizeRange> {
+        Some(SizeRange { min, max })
+    }
+
+    #[test]
+    fn parses_size_expressions() {
+        assert_eq!(SizeRange::parse(">100mb"), range(Some(100_000_001), None));
+        assert_eq!(SizeRange::parse("<=4KiB"), range(None, Some(4096)));
+        assert_eq!(SizeRange::parse("1k..10k"), range(Some(1000), Some(10_000)));
+        assert_eq!(SizeRange::parse("1.5mib.."), range(Some(1_572_864), None));
+        assert_eq!(SizeRange::parse("empty"), range(Some(0), Some(0)));
+        assert_eq!(SizeRange::parse("512"), range(Some(512), Some(512)));
+        assert_eq!(SizeRange::parse("<0"), None);
+        assert_eq!(SizeRange::parse("10 parsecs"), None);
+    }
+
+    #[test]
+    fn formats_sizes_for_people() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1_258_291), "1.2 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[tokio::test]
+    async fn filters_on_size() {
+        let (_dir, conn) = temp_database().await;
+
+        let record = |path: &str, size| FileRecord {
+            size,
+            ..record(path)
+        };
+        write(
+            &conn,
+            &[
+                record("/empty", 0),
+                record("/small", 5_000),
+                record("/big", 200_000_000),
+            ],
+        )
+        .await;
+
+        for (query, expected) in [
+            ("size:empty", vec!["/empty"]),
+            ("size:>100mb", vec!["/big"]),
+            ("size:1k..10k", vec!["/small"]),
+            ("size:<10k sort:size-desc", vec!["/small", "/empty"]),
+        ] {
+            assert_eq!(search(&conn, query).await, expected, "{}", query);
+        }
+    }
+}
+
+#[cfg(test)]
+mod saved_search_tests {
+    use crate::{
+        database::{
+            delete_saved_search, get_query, get_query_history, get_saved_search,
+            get_saved_searches, record_query, save_search,
+        },
+        tests::test_support::temp_database,
+    };
+
+    #[tokio::test]
+    async fn records_history_newest_first() {
+        let (_dir, conn) = temp_database().await;
+        record_query(&conn, "ext:rs", 12).await.unwrap();
+        record_query(&conn, "size:>100mb", 0).await.unwrap();
+
+        let history = get_query_history(&conn, 10).await.unwrap();
+        let queries: Vec<_> = history
+            .iter()
+            .map(|r| (r.query.as_str(), r.result_count))
+            .collect();
+        assert_eq!(queries, vec![("size:>100mb", 0), ("ext:rs", 12)]);
+        assert!(history[0].ran_at > 0);
+
+        assert_eq!(get_query_history(&conn, 1).await.unwrap().len(), 1);
+        assert_eq!(
+            get_query(&conn, history[1].id).await.unwrap().as_deref(),
+            Some("ext:rs")
+        );
+        assert_eq!(get_query(&conn, 999).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn saves_replaces_and_deletes_named_searches() {
+        let (_dir, conn) = temp_database().await;
+        save_search(&conn, "big-logs", "ext:log size:>10mb")
+            .await
+            .unwrap();
+        save_search(&conn, "big-logs", "ext:log size:>100mb")
+            .await
+            .unwrap();
+        save_search(&conn, "docs", "ext:md").await.unwrap();
+
+        assert_eq!(
+            get_saved_search(&conn, "big-logs")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("ext:log size:>100mb")
+        );
+        let names: Vec<_> = get_saved_searches(&conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["big-logs", "docs"]);
+
+        assert!(delete_saved_search(&conn, "docs").await.unwrap());
+        assert!(!delete_saved_search(&conn, "docs").await.unwrap());
+        assert_eq!(get_saved_search(&conn, "docs").await.unwrap(), None);
+    }
+}
+
+#[cfg(test)]
+mod frecency_tests {
+    use std::path::Path;
+
+    use crate::{
+        database::record_selection,
+        tests::test_support::{record, search, temp_database, write},
+    };
+
+    #[tokio::test]
+    async fn opened_files_rank_higher() {
+        let (_dir, conn) = temp_database().await;
+        write(
+            &conn,
+            &[
+                record("/notes/notes.md"),
+                record("/notes/old/notes-2019.md"),
+                record("/notes/todo.md"),
+            ],
+        )
+        .await;
+
+        let before = search(&conn, "notes").await;
+        assert_eq!(before[0], "/notes/notes.md");
+
+        record_selection(&conn, Path::new("/notes/old/notes-2019.md"))
+            .await
+            .unwrap();
+        record_selection(&conn, Path::new("/notes/old/notes-2019.md"))
+            .await
+            .unwrap();
+        let after = search(&conn, "notes").await;
+        assert_eq!(after[0], "/notes/old/notes-2019.md");
+
+        // an explicit sort still wins
+        let by_depth = search(&conn, "notes sort:depth").await;
+        assert_eq!(by_depth[0], "/notes/notes.md");
+
+        // without search text relevance is frecency alone
+        let filters = search(&conn, "ext:md sort:relevance").await;
+        assert_eq!(filters[0], "/notes/old/notes-2019.md");
+    }
+}
+
+#[cfg(test)]
+mod locate_tests {
+    use crate::{
+        locate::{glob_to_regex, LocateOptions, Locator},
+        search::{search_files, SearchQuery},
+        tests::test_support::{record, temp_database, write},
+    };
+
+    #[test]
+    fn translates_globs_like_fnmatch() {
+        assert_eq!(glob_to_regex("main.rs"), r"main\.rs");
+        assert_eq!(glob_to_regex("*.rs"), r"^.*\.rs$");
+        assert_eq!(glob_to_regex("/home/?/[!a-c]x"), r"^/home/./[^a-c]x$");
+        assert_eq!(glob_to_regex(r"a\*b"), r"^a\*b$");
+        assert_eq!(glob_to_regex("[]]"), r"^[\]]$");
+        assert_eq!(glob_to_regex("[abc"), r"^\[abc$");
+    }
+
+    #[tokio::test]
+    async fn locates_like_mlocate() {
+        let (_dir, conn) = temp_database().await;
+        write(
+            &conn,
+            &[
+                record("/src/Main.rs"),
+                record("/src/lib.rs"),
+                record("/docs/main.md"),
+                record("/rs/notes.txt"),
+            ],
+        )
+        .await;
+
+        let locate = |options: LocateOptions| {
+            let conn = conn.clone();
+            async move {
+                let mut found = Vec::new();
+                let count = Locator::new(&options)
+                    .unwrap()
+                    .run(&conn, |path| -> Result<(), libsql::Error> {
+                        found.push(path.display().to_string());
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+                assert_eq!(count, found.len());
+                found
+            }
+        };
+        let patterns = |patterns: &[&str]| LocateOptions {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..LocateOptions::default()
+        };
+
+        assert_eq!(locate(patterns(&["main"])).await, vec!["/docs/main.md"]);
+        assert_eq!(
+            locate(LocateOptions {
+                ignore_case: true,
+                ..patterns(&["main"])
+            })
+            .await,
+            vec!["/src/Main.rs", "/docs/main.md"]
+        );
+        // globs match the whole path, any pattern is enough
+        assert_eq!(
+            locate(patterns(&["*.rs", "*.txt"])).await,
+            vec!["/src/Main.rs", "/src/lib.rs", "/rs/notes.txt"]
+        );
+        assert_eq!(
+            locate(LocateOptions {
+                basename: true,
+                ..patterns(&["rs"])
+            })
+            .await,
+            vec!["/src/Main.rs", "/src/lib.rs"]
+        );
+        assert_eq!(
+            locate(LocateOptions {
+                regexes: vec![r"^/src/[a-z]+\.rs$".to_string()],
+                ..LocateOptions::default()
+            })
+            .await,
+            vec!["/src/lib.rs"]
+        );
+        assert_eq!(
+            locate(LocateOptions {
+                limit: Some(1),
+                ..patterns(&["/"])
+            })
+            .await
+            .len(),
+            1
+        );
+        // nothing here is on disk
+        assert!(locate(LocateOptions {
+            existing: true,
+            ..patterns(&["/"])
+        })
+        .await
+        .is_empty());
+    }
+
+    #[tokio::test]
+    async fn matches_backslashes_in_the_path_itself() {
+        let (_dir, conn) = temp_database().await;
+        write(&conn, &[record(r"/srv/a\b.txt"), record("/srv/ab.txt")]).await;
+
+        let locate = |pattern: &str, basename: bool, ignore_case: bool| {
+            let conn = conn.clone();
+            let options = LocateOptions {
+                patterns: vec![pattern.to_string()],
+                basename,
+                ignore_case,
+                ..LocateOptions::default()
+            };
+            async move {
+                let mut found = Vec::new();
+                Locator::new(&options)
+                    .unwrap()
+                    .run(&conn, |path| -> Result<(), libsql::Error> {
+                        found.push(path.display().to_string());
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+                found
+            }
+        };
+
+        for (basename, ignore_case) in [(false, false), (true, false), (true, true)] {
+            assert_eq!(
+                locate(r"*a\\b.txt", basename, ignore_case).await,
+                [r"/srv/a\b.txt"],
+                "basename: {}, ignore_case: {}",
+                basename,
+                ignore_case
+            );
+        }
+        // an escaped b is just a b
+        assert_eq!(locate(r"a\b.txt", true, false).await, ["/srv/ab.txt"]);
+
+        let mut query = SearchQuery::new().with_limit(None);
+        query.make_query("ext:txt").unwrap();
+        let mut names: Vec<String> = search_files(&query, &conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect();
+        names.sort();
+        assert_eq!(names, [r"a\b.txt", "ab.txt"]);
+    }
+}
+
+#[cfg(test)]
+mod locate_import_tests {
+    use std::{
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use libsql::Connection;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        database::{import_locate_db, FileRecord, LocateDbFormat, LocateDbReader, LocateEntry},
+        tests::test_support::{index_tree, open, record, write},
+    };
+
+    // an mlocate.db with the given directories, entries are (type, name), 0 file and 1 directory
+    fn mlocate_db(root: &str, directories: &[(&str, &[(u8, &str)])]) -> Vec<u8> {
+        // prune_bind_mounts = "0", \x30 so it isn't read as an octal escape
+        let config = b"prune_bind_mounts\0\x30\0\0";
+        let mut db = b"\0mlocate".to_vec();
+        db.extend_from_slice(&(config.len() as u32).to_be_bytes());
+        db.extend_from_slice(&[0, 1, 0, 0]);
+        db.extend_from_slice(root.as_bytes());
+        db.push(0);
+        db.extend_from_slice(config);
+        for (directory, entries) in directories {
+            db.extend_from_slice(&1700000000u64.to_be_bytes());
+            db.extend_from_slice(&[0; 8]);
+            db.extend_from_slice(directory.as_bytes());
+            db.push(0);
+            for (kind, name) in *entries {
+                db.push(*kind);
+                db.extend_from_slice(name.as_bytes());
+                db.push(0);
+            }
+            db.push(2);
+        }
+        db
+    }
+
+    // a version 1 plocate.db holding `paths` two to a block, compressed with `dictionary`
+    // the hash table isn't needed to list the paths, so it's left empty
+    fn plocate_db(paths: &[&str], dictionary: &[u8]) -> Vec<u8> {
+        let blocks: Vec<Vec<u8>> = paths
+            .chunks(2)
+            .map(|chunk| {
+                let mut block = Vec::new();
+                for path in chunk {
+                    block.extend_from_slice(path.as_bytes());
+                    block.push(0);
+                }
+                let mut encoder =
+                    zstd::stream::Encoder::with_dictionary(Vec::new(), 3, dictionary).unwrap();
+                encoder.write_all(&block).unwrap();
+                encoder.finish().unwrap()
+            })
+            .collect();
+
+        let header_len = 56;
+        let dictionary_offset = header_len;
+        let mut offsets = vec![(dictionary_offset + dictionary.len()) as u64];
+        for block in &blocks {
+            offsets.push(offsets.last().unwrap() + block.len() as u64);
+        }
+        let index_offset = *offsets.last().unwrap();
+
+        let mut db = b"\0plocate".to_vec();
+        db.extend_from_slice(&1u32.to_ne_bytes());
+        db.extend_from_slice(&[0; 8]);
+        db.extend_from_slice(&(blocks.len() as u32).to_ne_bytes());
+        db.extend_from_slice(&0u64.to_ne_bytes());
+        db.extend_from_slice(&index_offset.to_ne_bytes());
+        db.extend_from_slice(&1u32.to_ne_bytes());
+        db.extend_from_slice(&(dictionary.len() as u32).to_ne_bytes());
+        db.extend_from_slice(&(dictionary_offset as u64).to_ne_bytes());
+        assert_eq!(db.len(), header_len);
+        db.extend_from_slice(dictionary);
+        for block in &blocks {
+            db.extend_from_slice(block);
+        }
+        for offset in offsets {
+            db.extend_from_slice(&offset.to_ne_bytes());
+        }
+        db
+    }
+
+    async fn indexed(conn: &Connection) -> Vec<(String, i64)> {
+        let mut rows = conn
+            .query("SELECT path, size FROM files ORDER BY path", ())
+            .await
+            .unwrap();
+        let mut files = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            files.push((row.get(0).unwrap(), row.get(1).unwrap()));
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn imports_mlocate_without_overwriting_indexed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        // already indexed by the walker, the import must keep its size
+        write(
+            &conn,
+            &[FileRecord {
+                size: 42,
+                ..record("/home/theo/notes.txt")
+            }],
+        )
+        .await;
+
+        let db = mlocate_db(
+            "/home/theo",
+            &[
+                (
+                    "/home/theo",
+                    &[(1, "Projects"), (0, "notes.txt"), (1, "node_modules")],
+                ),
+                ("/home/theo/Projects", &[(0, "main.rs"), (1, "empty")]),
+                ("/home/theo/Projects/empty", &[]),
+                ("/home/theo/node_modules", &[(0, "left-pad.js")]),
+            ],
+        );
+        let db_path = dir.path().join("mlocate.db");
+        std::fs::write(&db_path, &db).unwrap();
+
+        let conn = Mutex::new(conn);
+        let import = import_locate_db(&conn, &db_path, |path: &Path| {
+            path.to_string_lossy().contains("node_modules")
+        })
+        .await
+        .unwrap();
+        assert_eq!(import.format, LocateDbFormat::Mlocate);
+        assert_eq!(import.paths_read, 6);
+        assert_eq!(import.files_imported, 1);
+
+        assert_eq!(
+            indexed(&*conn.lock().await).await,
+            vec![
+                ("/home/theo/Projects/main.rs".to_string(), 0),
+                ("/home/theo/notes.txt".to_string(), 42),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_plocate_blocks_compressed_with_a_dictionary() {
+        let paths = ["/srv", "/srv/a.txt", "/srv/b", "/srv/b/c.rs", "/srv/empty"];
+        let db = plocate_db(&paths, b"/srv/b/c.rs/srv/a.txt");
+
+        let entries: Vec<LocateEntry> = LocateDbReader::new(&db)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let read: Vec<_> = entries.iter().map(|entry| entry.path.clone()).collect();
+        assert_eq!(read, paths.iter().map(PathBuf::from).collect::<Vec<_>>());
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("plocate.db");
+        std::fs::write(&db_path, &db).unwrap();
+        let conn = Mutex::new(open(&dir.path().join("search.db")).await);
+        let import = import_locate_db(&conn, &db_path, |_: &Path| false)
+            .await
+            .unwrap();
+        assert_eq!(import.format, LocateDbFormat::Plocate);
+
+        // directories are recognised by having something in them, an empty one looks like a file
+        let files: Vec<_> = indexed(&*conn.lock().await)
+            .await
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(files, vec!["/srv/a.txt", "/srv/b/c.rs", "/srv/empty"]);
+
+        assert!(LocateDbReader::new(b"SQLite format 3\0").is_err());
+    }
+
+    #[tokio::test]
+    async fn reindexing_a_root_drops_imported_files_that_are_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        std::fs::write(tree.join("kept.txt"), "").unwrap();
+        let path = |name: &str| tree.join(name).display().to_string();
+        let (kept, gone) = (path("kept.txt"), path("gone.txt"));
+        let db = plocate_db(&[&kept, &gone, "/elsewhere/notes.txt"], b"");
+        let db_path = dir.path().join("plocate.db");
+        std::fs::write(&db_path, &db).unwrap();
+        let conn = Mutex::new(open(&dir.path().join("search.db")).await);
+        import_locate_db(&conn, &db_path, |_: &Path| false)
+            .await
+            .unwrap();
+
+        // the walk reads one of them and never finds the other, the third isn't under the root
+        let conn = conn.into_inner();
+        index_tree(&conn, &tree).await;
+        let files: Vec<_> = indexed(&conn)
+            .await
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(files, vec!["/elsewhere/notes.txt".to_string(), kept]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_out_of_range_databases() {
+        let invalid = |result: std::io::Result<_>| {
+            result.map(|_: LocateDbReader| ()).unwrap_err().kind()
+                == std::io::ErrorKind::InvalidData
+        };
+        let db = plocate_db(&["/srv/a.txt"], b"/srv");
+        assert!(invalid(LocateDbReader::new(&db[..20])));
+        let mlocate = mlocate_db("/", &[]);
+        assert!(invalid(LocateDbReader::new(&mlocate[..12])));
+
+        // a dictionary and an offset table at the very end of the address space
+        let mut far_dictionary = db.clone();
+        far_dictionary[48..56].copy_from_slice(&u64::MAX.to_ne_bytes());
+        assert!(invalid(LocateDbReader::new(&far_dictionary)));
+        let mut far_index = db.clone();
+        far_index[32..40].copy_from_slice(&(u64::MAX - 4).to_ne_bytes());
+        let mut reader = LocateDbReader::new(&far_index).unwrap();
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        assert!(reader.next().is_none());
+    }
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use std::{
+        ffi::OsStr,
+        os::unix::{ffi::OsStrExt, fs::MetadataExt},
+        path::{Path, PathBuf},
+    };
+
+    use jwalk::WalkDirGeneric;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        archive::{
+            self, ArchiveChange, ArchiveFormat, ArchiveHeader, ArchiveReader, ArchiveWriter,
+            ArchivedFile,
+        },
+        changes::{get_changes, ChangeKind},
+        config::Config,
+        content::ContentOptions,
+        database::FileRecord,
+        search::SearchQuery,
+        tests::test_support::{count, index_tree, index_with, open, record, write},
+    };
+
+    fn file(path: &Path, size: i64, created_at: Option<i64>) -> ArchivedFile {
+        ArchivedFile {
+            path: path.to_path_buf(),
+            size,
+            modified_at: 1700000000,
+            created_at,
+            accessed_at: None,
+        }
+    }
+
+    fn write_archive(path: &Path, format: ArchiveFormat, files: &[ArchivedFile]) {
+        let header = ArchiveHeader {
+            format_version: 1,
+            schema_version: 7,
+            roots: vec!["/".to_string()],
+            config_hash: String::new(),
+            exported_at: 1700000000,
+        };
+        let mut writer = ArchiveWriter::create(path, format, &header).unwrap();
+        for file in files {
+            writer.write(file.clone()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trips_and_diffs_both_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let latin1 = PathBuf::from(OsStr::from_bytes(b"/srv/caf\xe9.txt"));
+        let before = vec![
+            file(Path::new("/srv/a.txt"), 1, Some(1600000000)),
+            file(&latin1, 2, None),
+            file(Path::new("/srv/gone.txt"), 3, None),
+        ];
+        let after = vec![
+            file(Path::new("/srv/a.txt"), 10, Some(1600000000)),
+            file(&latin1, 2, None),
+            file(Path::new("/srv/new.txt"), 4, None),
+        ];
+
+        let before_path = dir.path().join("before.ndjson.zst");
+        let after_path = dir.path().join("after.snapshot");
+        write_archive(&before_path, ArchiveFormat::Ndjson, &before);
+        write_archive(&after_path, ArchiveFormat::Snapshot, &after);
+
+        for (path, format, files) in [
+            (&before_path, ArchiveFormat::Ndjson, &before),
+            (&after_path, ArchiveFormat::Snapshot, &after),
+        ] {
+            let reader = ArchiveReader::open(path).unwrap();
+            assert_eq!(reader.format(), format);
+            assert_eq!(reader.header().schema_version, 7);
+            let read: Vec<ArchivedFile> = reader.collect::<Result<_, _>>().unwrap();
+            assert_eq!(&read, files);
+        }
+
+        let changes = archive::diff(&before_path, &after_path).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                ArchiveChange::Changed {
+                    before: before[0].clone(),
+                    after: after[0].clone(),
+                },
+                ArchiveChange::Removed {
+                    file: before[2].clone(),
+                },
+                ArchiveChange::Added {
+                    file: after[2].clone(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_snapshot_lengths_past_the_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.rsindex");
+        write_archive(&path, ArchiveFormat::Snapshot, &[]);
+        let snapshot = std::fs::read(&path).unwrap();
+        let header_end = 16 + u32::from_le_bytes(snapshot[12..16].try_into().unwrap()) as usize;
+
+        let mut huge_header = snapshot.clone();
+        huge_header[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &huge_header).unwrap();
+        let error = ArchiveReader::open(&path).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut huge_path = snapshot[..header_end].to_vec();
+        huge_path.extend(zstd::encode_all(&u32::MAX.to_le_bytes()[..], 3).unwrap());
+        std::fs::write(&path, &huge_path).unwrap();
+        let mut reader = ArchiveReader::open(&path).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn exports_roots_and_merges_them_into_another_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = open(&dir.path().join("source.db")).await;
+        let records: Vec<FileRecord> = ["/share/docs/a.md", "/share/docs2/b.md", "/home/c.md"]
+            .into_iter()
+            .map(|path| FileRecord {
+                size: 5,
+                ..record(path)
+            })
+            .collect();
+        write(&source, &records).await;
+
+        let archive_path = dir.path().join("share.snapshot");
+        let exported = archive::export(
+            &source,
+            &archive_path,
+            ArchiveFormat::Snapshot,
+            &[PathBuf::from("/share/docs/")],
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+        // docs2 only shares a prefix with the root, it isn't under it
+        assert_eq!(exported, 1);
+
+        let target = open(&dir.path().join("target.db")).await;
+        let existing = FileRecord {
+            size: 1,
+            ..record("/share/docs/a.md")
+        };
+        write(&target, &[existing]).await;
+
+        let target = Mutex::new(target);
+        let import = archive::import(&target, &archive_path).await.unwrap();
+        assert_eq!(import.files, 1);
+        assert_eq!(import.header.roots, vec!["/share/docs"]);
+
+        let conn = target.lock().await;
+        let mut rows = conn
+            .query("SELECT path, size FROM files ORDER BY path", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get::<String>(0).unwrap(), "/share/docs/a.md");
+        assert_eq!(row.get::<i64>(1).unwrap(), 5);
+        assert!(rows.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn importing_over_an_index_keeps_it_reindexable() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        std::fs::write(tree.join("a.md"), "# a").unwrap();
+        std::fs::write(tree.join("b.md"), "# b").unwrap();
+        let xattrs = xattr::set(tree.join("a.md"), "user.xdg.tags", b"work").is_ok();
+        let conn = open(&dir.path().join("search.db")).await;
+        index_with(
+            &conn,
+            WalkDirGeneric::<(usize, bool)>::new(&tree),
+            ContentOptions {
+                detect_mime: true,
+                skip_binary: false,
+                index_xattrs: true,
+            },
+        )
+        .await;
+
+        let archive_path = dir.path().join("tree.snapshot");
+        write_archive(
+            &archive_path,
+            ArchiveFormat::Snapshot,
+            &[
+                file(&tree.join("a.md"), 99, None),
+                file(&tree.join("b.md"), 99, None),
+            ],
+        );
+        let conn = Mutex::new(conn);
+        archive::import(&conn, &archive_path).await.unwrap();
+        let conn = conn.into_inner();
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files WHERE size = 99 AND indexed_at IS NOT NULL"
+            )
+            .await,
+            2
+        );
+        // moves are still recognised by device and inode
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files WHERE device IS NOT NULL AND inode IS NOT NULL"
+            )
+            .await,
+            2
+        );
+        // search_files_as still knows who can see them
+        let metadata = std::fs::metadata(tree.join("a.md")).unwrap();
+        assert_eq!(
+            count(
+                &conn,
+                &format!(
+                    "SELECT COUNT(*) FROM files WHERE filename = 'a.md'
+                     AND uid = {} AND gid = {} AND mode = {}",
+                    metadata.uid(),
+                    metadata.gid(),
+                    metadata.mode()
+                )
+            )
+            .await,
+            1
+        );
+        // the archive has no contents either
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files WHERE mime = 'text/plain'"
+            )
+            .await,
+            2
+        );
+        if xattrs {
+            assert_eq!(
+                count(
+                    &conn,
+                    "SELECT COUNT(*) FROM files WHERE xattrs ->> '$.\"user.xdg.tags\"' = 'work'"
+                )
+                .await,
+                1
+            );
+        }
+
+        // as if that index ran a minute ago
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+        std::fs::remove_file(tree.join("b.md")).unwrap();
+        index_tree(&conn, &tree).await;
+
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM files WHERE filename = 'b.md'").await,
+            0
+        );
+        let deleted: Vec<_> = get_changes(&conn, 0, &SearchQuery::new().with_limit(None))
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|change| change.kind == ChangeKind::Deleted)
+            .map(|change| change.path)
+            .collect();
+        assert_eq!(deleted, [tree.join("b.md")]);
+    }
+}
+
+#[cfg(test)]
+mod changes_tests {
+    use std::path::{Path, PathBuf};
+
+    use jwalk::WalkDirGeneric;
+    use libsql::Connection;
+    use notify::{
+        event::{CreateKind, ModifyKind, RemoveKind, RenameMode},
+        Event, EventKind,
+    };
+
+    use crate::{
+        apply_watch_event,
+        changes::{get_changes, ChangeKind},
+        config::Config,
+        content::ContentOptions,
+        database::{finish_index_run, start_index_run, IndexRunStatus},
+        mounts::{save_mounts, Mount},
+        search::SearchQuery,
+        tests::test_support::{count, index_tree, index_with, open},
+    };
+
+    // (kind, file name, old file name) for every change matching `query`
+    async fn changes(conn: &Connection, query: &str) -> Vec<(ChangeKind, String, Option<String>)> {
+        let name = |path: &Path| path.file_name().unwrap().to_string_lossy().to_string();
+        let mut search_query = SearchQuery::new().with_limit(None);
+        search_query.make_query(query).unwrap();
+        let mut changes: Vec<_> = get_changes(conn, 0, &search_query)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| {
+                (
+                    change.kind,
+                    name(&change.path),
+                    change.old_path.as_deref().map(name),
+                )
+            })
+            .collect();
+        changes.sort_by(|a, b| a.1.cmp(&b.1));
+        changes
+    }
+
+    #[tokio::test]
+    async fn reindexing_logs_created_modified_and_deleted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(tree.join("src")).unwrap();
+        for name in ["a.rs", "src/b.rs", "gone.txt"] {
+            std::fs::write(tree.join(name), "fn main() {}").unwrap();
+        }
+        let conn = open(&dir.path().join("search.db")).await;
+
+        // everything would be created, so the first index isn't logged
+        index_tree(&conn, &tree).await;
+        assert!(changes(&conn, "").await.is_empty());
+
+        // as if that index ran a minute ago, anything it read and the next one doesn't is gone
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+        std::fs::write(tree.join("a.rs"), "fn main() { println!(); }").unwrap();
+        std::fs::write(tree.join("src/c.rs"), "").unwrap();
+        std::fs::remove_file(tree.join("gone.txt")).unwrap();
+        index_tree(&conn, &tree).await;
+
+        assert_eq!(
+            changes(&conn, "").await,
+            vec![
+                (ChangeKind::Modified, "a.rs".to_string(), None),
+                (ChangeKind::Created, "c.rs".to_string(), None),
+                (ChangeKind::Deleted, "gone.txt".to_string(), None),
+            ]
+        );
+        // deleted files keep what the index knew about them, so filters still apply
+        assert_eq!(changes(&conn, "ext:txt").await.len(), 1);
+        assert_eq!(changes(&conn, "src size:0").await.len(), 1);
+
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM files WHERE filename = 'gone.txt'"
+            )
+            .await,
+            0
+        );
+    }
+
+    // a second index over `tree` after `change` has been made to it
+    async fn reindex_after(conn: &Connection, tree: &Path, change: impl FnOnce()) {
+        index_tree(conn, tree).await;
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+        change();
+        index_tree(conn, tree).await;
+    }
+
+    #[tokio::test]
+    async fn owner_group_and_mode_filters_look_at_the_file_as_it_is_now() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        std::fs::write(tree.join("gone.sh"), "").unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        reindex_after(&conn, &tree, || {
+            std::fs::write(tree.join("plain.txt"), "").unwrap();
+            std::fs::write(tree.join("tool.sh"), "").unwrap();
+            std::fs::set_permissions(tree.join("tool.sh"), PermissionsExt::from_mode(0o4755))
+                .unwrap();
+            std::fs::remove_file(tree.join("gone.sh")).unwrap();
+        })
+        .await;
+        let metadata = std::fs::metadata(&tree).unwrap();
+
+        let names = |changes: Vec<(ChangeKind, String, Option<String>)>| {
+            changes
+                .into_iter()
+                .map(|change| change.1)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(changes(&conn, &format!("owner:{}", metadata.uid())).await),
+            ["plain.txt", "tool.sh"]
+        );
+        assert_eq!(
+            names(changes(&conn, &format!("group:{}", metadata.gid())).await),
+            ["plain.txt", "tool.sh"]
+        );
+        assert_eq!(names(changes(&conn, "perm:u+x").await), ["tool.sh"]);
+        assert_eq!(names(changes(&conn, "setuid:yes").await), ["tool.sh"]);
+        assert_eq!(names(changes(&conn, "setuid:no").await), ["plain.txt"]);
+        // the deleted file isn't there to say who owned it
+        assert_eq!(changes(&conn, "").await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn target_filters_look_at_the_link_as_it_is_now() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(tree.join("data")).unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        reindex_after(&conn, &tree, || {
+            std::os::unix::fs::symlink(tree.join("data"), tree.join("shortcut")).unwrap();
+            std::fs::write(tree.join("notes.txt"), "").unwrap();
+        })
+        .await;
+
+        let target = format!("target:{}", tree.join("data").display());
+        let logged = changes(&conn, &target).await;
+        assert_eq!(
+            logged,
+            vec![(ChangeKind::Created, "shortcut".to_string(), None)]
+        );
+        assert_eq!(changes(&conn, "target:data").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn filesystem_and_mount_filters_look_at_the_file_as_it_is_now() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        reindex_after(&conn, &tree, || {
+            std::fs::write(tree.join("notes.txt"), "").unwrap();
+        })
+        .await;
+        let mount = Mount {
+            device: std::fs::metadata(&tree).unwrap().dev() as i64,
+            mount_point: tree.clone(),
+            fstype: "fuse.sshfs".to_string(),
+            source: "theo@box:/".to_string(),
+        };
+        save_mounts(&conn, &[mount]).await.unwrap();
+
+        assert_eq!(changes(&conn, "fs:fuse").await.len(), 1);
+        assert_eq!(
+            changes(&conn, &format!("mount:{}", tree.display()))
+                .await
+                .len(),
+            1
+        );
+        assert!(changes(&conn, "fs:ext4").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mime_and_xattr_filters_look_at_the_file_as_it_is_now() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        let content = ContentOptions {
+            detect_mime: true,
+            skip_binary: false,
+            index_xattrs: true,
+        };
+        index_with(&conn, WalkDirGeneric::<(usize, bool)>::new(&tree), content).await;
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+        std::fs::write(tree.join("picture"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        std::fs::write(tree.join("notes"), "caf\u{e9} au lait\n").unwrap();
+        let xattrs = xattr::set(tree.join("notes"), "user.xdg.tags", b"work").is_ok();
+        index_with(&conn, WalkDirGeneric::<(usize, bool)>::new(&tree), content).await;
+
+        assert_eq!(
+            changes(&conn, "mime:image").await,
+            vec![(ChangeKind::Created, "picture".to_string(), None)]
+        );
+        assert_eq!(changes(&conn, "mime:text/plain").await.len(), 1);
+        if xattrs {
+            assert_eq!(
+                changes(&conn, "xattr:xdg.tags=work").await,
+                vec![(ChangeKind::Created, "notes".to_string(), None)]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn watcher_events_are_applied_and_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(tree.join("docs")).unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        let run_id = start_index_run(&conn).await.unwrap();
+        finish_index_run(&conn, run_id, IndexRunStatus::Completed)
+            .await
+            .unwrap();
+
+        let config = Config::default();
+        let apply = |kind: EventKind, paths: &[PathBuf]| {
+            let event = paths
+                .iter()
+                .fold(Event::new(kind), |event, path| event.add_path(path.clone()));
+            let conn = conn.clone();
+            let config = config.clone();
+            async move { apply_watch_event(&conn, &config, &event).await.unwrap() }
+        };
+
+        let draft = tree.join("docs/draft.md");
+        std::fs::write(&draft, "# notes").unwrap();
+        apply(
+            EventKind::Create(CreateKind::File),
+            std::slice::from_ref(&draft),
+        )
+        .await;
+        // skipped like the walker would
+        std::fs::create_dir_all(tree.join("node_modules")).unwrap();
+        let skipped = tree.join("node_modules/left-pad.js");
+        std::fs::write(&skipped, "").unwrap();
+        apply(EventKind::Create(CreateKind::File), &[skipped]).await;
+
+        let notes = tree.join("docs/notes.md");
+        std::fs::rename(&draft, &notes).unwrap();
+        apply(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &[draft, notes],
+        )
+        .await;
+
+        std::fs::remove_dir_all(tree.join("docs")).unwrap();
+        apply(EventKind::Remove(RemoveKind::Folder), &[tree.join("docs")]).await;
+
+        let mut logged = changes(&conn, "").await;
+        logged.sort_by_key(|change| change.0 as u8);
+        assert_eq!(
+            logged,
+            vec![
+                (ChangeKind::Created, "draft.md".to_string(), None),
+                (ChangeKind::Deleted, "notes.md".to_string(), None),
+                (
+                    ChangeKind::Renamed,
+                    "notes.md".to_string(),
+                    Some("draft.md".to_string())
+                ),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod rename_tests {
+    use std::{
+        ffi::OsStr,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+    };
+
+    use libsql::Connection;
+
+    use crate::{
+        changes::{get_changes, ChangeKind},
+        database::{record_selection, rename_path},
+        paths::path_from_bytes,
+        search::SearchQuery,
+        tests::test_support::{index_tree, open, record, write},
+    };
+
+    // (id, path, filename, extension) of every row, by path
+    async fn rows(conn: &Connection) -> Vec<(i64, PathBuf, String, String)> {
+        let mut rows = conn
+            .query(
+                "SELECT id, path, path_bytes, filename, extension FROM files ORDER BY path",
+                (),
+            )
+            .await
+            .unwrap();
+        let mut files = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            let path = match row.get::<Option<Vec<u8>>>(2).unwrap() {
+                Some(bytes) => path_from_bytes(&bytes),
+                None => PathBuf::from(row.get::<String>(1).unwrap()),
+            };
+            files.push((
+                row.get(0).unwrap(),
+                path,
+                row.get(3).unwrap(),
+                row.get(4).unwrap(),
+            ));
+        }
+        files
+    }
+
+    async fn selected_paths(conn: &Connection) -> Vec<String> {
+        let mut rows = conn
+            .query("SELECT path FROM selections ORDER BY path", ())
+            .await
+            .unwrap();
+        let mut paths = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            paths.push(row.get(0).unwrap());
+        }
+        paths
+    }
+
+    #[tokio::test]
+    async fn moves_a_directory_in_one_statement() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        let latin1 = PathBuf::from(OsStr::from_bytes(b"/srv/proj/sub/caf\xe9.rs"));
+        write(
+            &conn,
+            &[
+                record("/srv/proj/a.rs"),
+                record(latin1),
+                // shares a prefix with /srv/proj but isn't under it
+                record("/srv/project/b.rs"),
+            ],
+        )
+        .await;
+        record_selection(&conn, Path::new("/srv/proj/a.rs"))
+            .await
+            .unwrap();
+        let before = rows(&conn).await;
+
+        let moved = rename_path(&conn, Path::new("/srv/proj"), Path::new("/srv/new"))
+            .await
+            .unwrap();
+        assert_eq!(moved, 2);
+        let after = rows(&conn).await;
+        assert_eq!(
+            after.iter().map(|row| row.1.clone()).collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/srv/new/a.rs"),
+                PathBuf::from(OsStr::from_bytes(b"/srv/new/sub/caf\xe9.rs")),
+                PathBuf::from("/srv/project/b.rs"),
+            ]
+        );
+        // the same rows, so anything keyed on them carries over
+        assert_eq!(
+            after.iter().map(|row| row.0).collect::<Vec<_>>(),
+            vec![before[0].0, before[1].0, before[2].0]
+        );
+        assert_eq!(selected_paths(&conn).await, vec!["/srv/new/a.rs"]);
+
+        // a file takes its new name and extension
+        rename_path(
+            &conn,
+            Path::new("/srv/new/a.rs"),
+            Path::new("/srv/project/b.rs"),
+        )
+        .await
+        .unwrap();
+        let after = rows(&conn).await;
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[1].0, before[0].0);
+        assert_eq!((after[1].2.as_str(), after[1].3.as_str()), ("b.rs", "rs"));
+        assert_eq!(selected_paths(&conn).await, vec!["/srv/project/b.rs"]);
+    }
+
+    #[tokio::test]
+    async fn reindexing_keeps_moved_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(tree.join("docs")).unwrap();
+        std::fs::write(tree.join("docs/a.md"), "# a").unwrap();
+        std::fs::write(tree.join("b.md"), "# b").unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+
+        index_tree(&conn, &tree).await;
+        let a = tree.join("docs/a.md");
+        record_selection(&conn, &a).await.unwrap();
+        let before = rows(&conn).await;
+
+        // as if that index ran a minute ago
+        conn.execute("UPDATE files SET indexed_at = indexed_at - 60", ())
+            .await
+            .unwrap();
+        std::fs::rename(tree.join("docs"), tree.join("notes")).unwrap();
+        index_tree(&conn, &tree).await;
+
+        let after = rows(&conn).await;
+        let moved = tree.join("notes/a.md");
+        assert_eq!(
+            after
+                .iter()
+                .map(|row| (row.0, row.1.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (before[0].0, tree.join("b.md")),
+                (before[1].0, moved.clone())
+            ]
+        );
+        assert_eq!(
+            selected_paths(&conn).await,
+            vec![moved.to_string_lossy().to_string()]
+        );
+
+        let changes = get_changes(&conn, 0, &SearchQuery::new().with_limit(None))
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Renamed);
+        assert_eq!(changes[0].old_path.as_deref(), Some(a.as_path()));
+    }
+}
+
+#[cfg(test)]
+mod symlink_tests {
+    use std::{
+        collections::HashSet,
+        os::unix::fs::symlink,
+        path::{Path, PathBuf},
+    };
+
+    use libsql::Connection;
+
+    use crate::{
+        config::{Config, FollowSymlinks},
+        content::ContentOptions,
+        tests::test_support::{index_with, open, search_sorted},
+    };
+
+    // walks `tree` the way index_files does, with the given policy
+    async fn index(conn: &Connection, tree: &Path, follow_symlinks: FollowSymlinks) {
+        let config = Config {
+            roots: vec![tree.to_path_buf()],
+            follow_symlinks,
+            ..Config::default()
+        };
+        let walks = crate::get_directories(&config, &[], HashSet::new()).unwrap();
+        index_with(conn, walks.into_iter().flatten(), ContentOptions::default()).await;
+    }
+
+    // paths relative to `tree`, sorted
+    async fn search(conn: &Connection, tree: &Path, query: &str) -> Vec<String> {
+        let prefix = format!("{}/", tree.display());
+        search_sorted(conn, query)
+            .await
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(&prefix)
+                    .map(str::to_string)
+                    .unwrap_or(path)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn symlinks_are_followed_by_policy_and_never_in_circles() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(tree.join("a/b")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(tree.join("a/b/main.rs"), "").unwrap();
+        std::fs::write(outside.join("lib.rs"), "").unwrap();
+        // back up to the tree, then a way round into it that isn't an ancestor
+        symlink("../..", tree.join("a/b/up")).unwrap();
+        symlink(tree.join("a/b"), tree.join("b")).unwrap();
+        symlink(&outside, tree.join("out")).unwrap();
+
+        let cases = [
+            (
+                FollowSymlinks::Never,
+                vec!["a/b/main.rs", "a/b/up", "b", "out"],
+            ),
+            (
+                FollowSymlinks::WithinRoots,
+                vec!["a/b/main.rs", "a/b/up", "b", "b/main.rs", "b/up", "out"],
+            ),
+            (
+                FollowSymlinks::Always,
+                vec![
+                    "a/b/main.rs",
+                    "a/b/up",
+                    "b",
+                    "b/main.rs",
+                    "b/up",
+                    "out",
+                    "out/lib.rs",
+                ],
+            ),
+        ];
+        for (policy, expected) in cases {
+            let conn = open(&dir.path().join(format!("{:?}.db", policy))).await;
+            index(&conn, &tree, policy).await;
+            assert_eq!(search(&conn, &tree, "").await, expected, "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn target_finds_links_into_a_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let data = dir.path().join("data");
+        std::fs::create_dir_all(tree.join("links")).unwrap();
+        std::fs::create_dir_all(data.join("photos")).unwrap();
+        std::fs::create_dir_all(dir.path().join("database")).unwrap();
+        symlink(&data, tree.join("links/data")).unwrap();
+        // relative targets are stored resolved, dangling ones too
+        symlink("../../data/photos/2024", tree.join("links/photos")).unwrap();
+        symlink(dir.path().join("database"), tree.join("links/db")).unwrap();
+        let conn = open(&dir.path().join("search.db")).await;
+        index(&conn, &tree, FollowSymlinks::Never).await;
+
+        let target = |path: PathBuf| format!("target:{}", path.display());
+        assert_eq!(
+            search(&conn, &tree, &target(data.clone())).await,
+            ["links/data", "links/photos"]
+        );
+        assert_eq!(
+            search(&conn, &tree, &target(data.join("photos/"))).await,
+            ["links/photos"]
+        );
+        assert_eq!(search(&conn, &tree, "target:database").await, ["links/db"]);
+    }
+}
+
+#[cfg(test)]
+mod mount_tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{
+        config::Config,
+        database::FileRecord,
+        mounts::{parse_mountinfo, save_mounts, skipped_mounts},
+        tests::test_support::{record, search_sorted, temp_database, write},
+    };
+
+    const MOUNTINFO: &[u8] = b"\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+24 22 0:32 / /tmp rw,nosuid shared:5 - tmpfs tmpfs rw,size=8G
+25 22 259:3 / /home rw,relatime shared:30 - ext4 /dev/nvme0n1p3 rw
+26 25 0:50 / /home/theo/my\\040server rw,nosuid shared:40 master:2 - fuse.sshfs theo@box:/ rw
+27 22 8:17 / /mnt/usb rw,relatime - vfat /dev/sdb1 rw
+";
+
+    #[test]
+    fn walker_skips_mounts_by_filesystem() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(mounts.len(), 6);
+        // 259:2 and 8:17 as the kernel encodes them in st_dev
+        assert_eq!(mounts[0].device, (259 << 8) | 2);
+        assert_eq!(mounts[5].device, (8 << 8) | 17);
+        assert_eq!(mounts[4].mount_point, PathBuf::from("/home/theo/my server"));
+        assert_eq!(mounts[4].fstype, "fuse.sshfs");
+        assert_eq!(mounts[4].source, "theo@box:/");
+
+        let skipped = |config: &Config, root: &str| {
+            let root_device = mounts
+                .iter()
+                .find(|mount| mount.mount_point == Path::new(root))
+                .map(|mount| mount.device);
+            let mut skipped: Vec<PathBuf> =
+                skipped_mounts(config, &mounts, Path::new(root), root_device)
+                    .into_iter()
+                    .collect();
+            skipped.sort();
+            skipped
+        };
+
+        let config = Config::default();
+        assert_eq!(
+            skipped(&config, "/"),
+            ["/home/theo/my server", "/proc", "/tmp"].map(PathBuf::from)
+        );
+        assert_eq!(
+            skipped(&config, "/home"),
+            ["/home/theo/my server"].map(PathBuf::from)
+        );
+
+        let config = Config {
+            one_file_system: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            skipped(&config, "/"),
+            ["/home", "/home/theo/my server", "/mnt/usb", "/proc", "/tmp"].map(PathBuf::from)
+        );
+
+        let config = Config {
+            include_filesystems: vec!["ext4".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            skipped(&config, "/"),
+            ["/home/theo/my server", "/mnt/usb", "/proc", "/tmp"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn imports_keep_to_what_a_walk_would_find() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let walks_into = |roots: &[&str], path: &str| {
+            let config = Config {
+                roots: roots.iter().map(PathBuf::from).collect(),
+                ..Config::default()
+            };
+            crate::walks_into(&crate::walked_roots(&config, &mounts), Path::new(path))
+        };
+
+        assert!(walks_into(&["/"], "/etc/hosts"));
+        assert!(walks_into(&["/"], "/home/theo/notes.txt"));
+        assert!(!walks_into(&["/"], "/proc/1/status"));
+        assert!(!walks_into(&["/"], "/tmp/build/a.o"));
+        assert!(!walks_into(&["/"], "/home/theo/my server/backup.tar"));
+        assert!(walks_into(&["/home", "/mnt/usb"], "/mnt/usb/photo.jpg"));
+        assert!(!walks_into(&["/home", "/mnt/usb"], "/etc/hosts"));
+    }
+
+    #[tokio::test]
+    async fn filters_on_filesystem_and_mount() {
+        let (_dir, conn) = temp_database().await;
+        let mounts = parse_mountinfo(MOUNTINFO);
+        save_mounts(&conn, &mounts).await.unwrap();
+
+        let record = |path: &str, device: Option<i64>| FileRecord {
+            device,
+            ..record(path)
+        };
+        write(
+            &conn,
+            &[
+                record("/etc/hosts", Some(mounts[0].device)),
+                record("/home/theo/notes.txt", Some(mounts[3].device)),
+                record("/home/theo/my server/backup.tar", Some(mounts[4].device)),
+                record("/mnt/usb/photo.jpg", Some(mounts[5].device)),
+                // from an import, so its filesystem isn't known
+                record("/home/theo/imported.txt", None),
+            ],
+        )
+        .await;
+
+        for (query, expected) in [
+            ("fs:ext4", vec!["/etc/hosts", "/home/theo/notes.txt"]),
+            ("fs:fuse", vec!["/home/theo/my server/backup.tar"]),
+            ("fs:VFAT", vec!["/mnt/usb/photo.jpg"]),
+            ("mount:/home/", vec!["/home/theo/notes.txt"]),
+            ("mount:/", vec!["/etc/hosts"]),
+            ("notes mount:/home", vec!["/home/theo/notes.txt"]),
+        ] {
+            assert_eq!(search_sorted(&conn, query).await, expected, "{}", query);
+        }
+    }
+}
+
+#[cfg(test)]
+mod permission_tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        database::{write_directories, FileRecord},
+        permissions::{format_mode, search_visible, Credentials, ModeMatch, Permissions},
+        search::SearchQuery,
+        tests::test_support::{record, search_sorted, temp_database, write},
+        users::Users,
+    };
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash
+theo:x:1000:1000:Theo:/home/theo:/bin/bash
+bob:x:1001:1001::/home/bob:/bin/sh
+";
+    const GROUP: &str = "root:x:0:
+users:x:100:theo,bob
+docker:x:998:theo
+theo:x:1000:
+";
+
+    fn directory(path: &str, uid: u32, gid: u32, mode: u32) -> (PathBuf, Permissions) {
+        let permissions = Permissions {
+            uid,
+            gid,
+            mode: 0o040000 | mode,
+        };
+        (PathBuf::from(path), permissions)
+    }
+
+    #[test]
+    fn permission_classes() {
+        let users = Users::parse(PASSWD, GROUP);
+        assert_eq!(users.user_name(1000), Some("theo"));
+        assert_eq!(users.groups_of(1000, 1000), [1000, 100, 998]);
+        assert_eq!(users.groups_of(1001, 1001), [1001, 100]);
+        assert_eq!(users.groups_of(4242, 4242), [4242]);
+
+        let theo = Credentials::new(1000, 1000, &users);
+        let bob = Credentials::new(1001, 1001, &users);
+        let root = Credentials::new(0, 0, &users);
+        // the owner has no read permission, even though everyone else does
+        let (_, odd) = directory("/odd", 1000, 100, 0o374);
+        assert!(!odd.allow(&theo, 4));
+        assert!(odd.allow(&theo, 3));
+        assert!(odd.allow(&bob, 7));
+        assert!(odd.allow(&root, 7));
+        let (_, private) = directory("/private", 1000, 1000, 0o700);
+        assert!(private.allow(&theo, 5));
+        assert!(!private.allow(&bob, 1));
+    }
+
+    #[tokio::test]
+    async fn search_as_a_user_hides_what_they_cannot_reach() {
+        let (_dir, conn) = temp_database().await;
+
+        write_directories(
+            &conn,
+            &[
+                directory("/", 0, 0, 0o755),
+                directory("/home", 0, 0, 0o755),
+                directory("/home/theo", 1000, 1000, 0o700),
+                directory("/home/shared", 0, 100, 0o750),
+                // anyone in users can drop files in, but not list them
+                directory("/home/shared/inbox", 0, 100, 0o730),
+            ],
+        )
+        .await
+        .unwrap();
+        write(
+            &conn,
+            &[
+                record("/home/theo/notes.txt"),
+                record("/home/shared/readme.txt"),
+                record("/home/shared/inbox/report.txt"),
+                // nothing is known about /srv, so only root sees it
+                record("/srv/www/index.txt"),
+            ],
+        )
+        .await;
+
+        let users = Users::parse(PASSWD, GROUP);
+        let search = |uid: u32, limit: Option<usize>| {
+            let conn = conn.clone();
+            let credentials = Credentials::new(uid, uid, &users);
+            async move {
+                let mut query = SearchQuery::new().with_limit(limit);
+                query.make_query("ext:txt").unwrap();
+                let mut paths: Vec<String> = search_visible(&conn, &query, &credentials)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| entry.path.display().to_string())
+                    .collect();
+                paths.sort();
+                paths
+            }
+        };
+
+        assert_eq!(search(0, None).await.len(), 4);
+        assert_eq!(
+            search(1000, None).await,
+            ["/home/shared/readme.txt", "/home/theo/notes.txt"]
+        );
+        assert_eq!(search(1001, None).await, ["/home/shared/readme.txt"]);
+        assert!(search(4242, None).await.is_empty());
+        // hidden files don't use up the limit
+        assert_eq!(search(1001, Some(1)).await, ["/home/shared/readme.txt"]);
+    }
+
+    // an image of theo's with a tag, readable by `mode`
+    fn image(path: &str, mode: u32, tag: (&str, &str)) -> FileRecord {
+        FileRecord {
+            permissions: Some(Permissions {
+                uid: 1000,
+                gid: 1000,
+                mode: 0o100000 | mode,
+            }),
+            mime: Some("image/png".to_string()),
+            xattrs: [(tag.0.to_string(), tag.1.to_string())].into(),
+            ..record(path)
+        }
+    }
+
+    #[tokio::test]
+    async fn contents_of_unreadable_files_stay_hidden() {
+        let (_dir, conn) = temp_database().await;
+        write_directories(
+            &conn,
+            &[directory("/", 0, 0, 0o755), directory("/srv", 0, 0, 0o755)],
+        )
+        .await
+        .unwrap();
+        write(
+            &conn,
+            &[
+                image("/srv/public.png", 0o644, ("user.xdg.tags", "work")),
+                image("/srv/secret.png", 0o600, ("user.secret", "hunter2")),
+            ],
+        )
+        .await;
+
+        let users = Users::parse(PASSWD, GROUP);
+        let search = |uid: u32, text: &str| {
+            let conn = conn.clone();
+            let credentials = Credentials::new(uid, uid, &users);
+            let mut query = SearchQuery::new().with_limit(None);
+            query.make_query(text).unwrap();
+            async move {
+                let mut entries = search_visible(&conn, &query, &credentials).await.unwrap();
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.path.display().to_string(), entry.mime, entry.xattrs))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // bob can find the secret, but not see what's in it
+        let found = search(1001, "ext:png").await;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].1.as_deref(), Some("image/png"));
+        assert_eq!(found[0].2.len(), 1);
+        assert_eq!(found[1].0, "/srv/secret.png");
+        assert_eq!(found[1].1, None);
+        assert!(found[1].2.is_empty());
+        assert_eq!(search(1001, "mime:image").await.len(), 1);
+        assert!(search(1001, "xattr:secret=hunter2").await.is_empty());
+        assert!(search(1001, "xattr:secret").await.is_empty());
+
+        let found = search(1000, "xattr:secret=hunter2").await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.as_deref(), Some("image/png"));
+    }
+
+    #[tokio::test]
+    async fn credentials_come_from_the_socket_peer() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = std::fs::metadata(dir.path()).unwrap();
+        let (ours, _theirs) = tokio::net::UnixStream::pair().unwrap();
+        let credentials = Credentials::of_peer(&ours).unwrap();
+        assert_eq!(credentials.uid, metadata.uid());
+        assert_eq!(credentials.groups[0], metadata.gid());
+    }
+
+    #[tokio::test]
+    async fn serves_searches_as_whoever_connects() {
+        use std::{os::unix::fs::MetadataExt, sync::Arc};
+
+        use tokio::{
+            io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+            net::{UnixListener, UnixStream},
+        };
+
+        use crate::{tests::test_support::open, SearchEngine};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("search.db");
+        let conn = open(&db_path).await;
+        write_directories(
+            &conn,
+            &[
+                directory("/", 0, 0, 0o755),
+                directory("/srv", 0, 0, 0o755),
+                directory("/home", 0, 0, 0o755),
+                directory("/home/theo", 1000, 1000, 0o700),
+            ],
+        )
+        .await
+        .unwrap();
+        // we're the peer, so the daemon searches as us
+        let uid = std::fs::metadata(dir.path()).unwrap().uid();
+        let someone_else = if uid == 1000 { 1001 } else { 1000 };
+        let secret = FileRecord {
+            permissions: Some(Permissions {
+                uid: someone_else,
+                gid: someone_else,
+                mode: 0o100600,
+            }),
+            mime: Some("image/png".to_string()),
+            xattrs: [("user.secret".to_string(), "hunter2".to_string())].into(),
+            ..record("/srv/secret.png")
+        };
+        write(
+            &conn,
+            &[
+                record("/srv/notes.txt"),
+                record("/home/theo/private.txt"),
+                secret,
+            ],
+        )
+        .await;
+        drop(conn);
+
+        let engine = Arc::new(SearchEngine::new(db_path.to_str().unwrap()).await.unwrap());
+        let socket = dir.path().join("rustsearch.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(engine.serve(listener));
+
+        let stream = UnixStream::connect(&socket).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        // queries on one connection, each gets its own line back
+        writer
+            .write_all(b"ext:txt\nname:notes\nsecret\nxattr:secret=hunter2\n")
+            .await
+            .unwrap();
+        let mut responses = Vec::new();
+        for _ in 0..4 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+            let mut results = response["results"].as_array().unwrap().clone();
+            results.sort_by_key(|entry| entry["path"]["text"].to_string());
+            responses.push(results);
+        }
+        let paths = |results: &[serde_json::Value]| {
+            results
+                .iter()
+                .map(|entry| entry["path"]["text"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        if uid == 0 || uid == 1000 {
+            assert_eq!(
+                paths(&responses[0]),
+                ["/home/theo/private.txt", "/srv/notes.txt"]
+            );
+        } else {
+            assert_eq!(paths(&responses[0]), ["/srv/notes.txt"]);
+        }
+        assert_eq!(paths(&responses[1]), ["/srv/notes.txt"]);
+        // root reads anything, anyone else can find the 0600 file but not what's in it
+        assert_eq!(paths(&responses[2]), ["/srv/secret.png"]);
+        if uid == 0 {
+            assert_eq!(responses[2][0]["mime"], "image/png");
+            assert_eq!(responses[2][0]["xattrs"]["user.secret"], "hunter2");
+            assert_eq!(paths(&responses[3]), ["/srv/secret.png"]);
+        } else {
+            assert!(responses[2][0]["mime"].is_null());
+            assert_eq!(responses[2][0]["xattrs"], serde_json::json!({}));
+            assert!(responses[3].is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn serve_cuts_off_queries_that_are_too_long() {
+        use std::sync::Arc;
+
+        use tokio::{
+            io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+            net::{UnixListener, UnixStream},
+        };
+
+        use crate::{tests::test_support::open, SearchEngine};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("search.db");
+        drop(open(&db_path).await);
+        let engine = Arc::new(SearchEngine::new(db_path.to_str().unwrap()).await.unwrap());
+        let socket = dir.path().join("rustsearch.sock");
+        tokio::spawn(engine.serve(UnixListener::bind(&socket).unwrap()));
+
+        let stream = UnixStream::connect(&socket).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"notes\n").await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(line, r#"{"results":[]}"#);
+
+        // no newline, the daemon stops reading once it's past the limit. anything after that
+        // would be left unread and reset the connection, so this is a byte over it
+        writer.write_all(&[b'a'; 64 * 1024 + 1]).await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["error"], "queries can be at most 65536 bytes");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[test]
+    fn mode_expressions() {
+        assert_eq!(ModeMatch::parse("+x"), Some(ModeMatch::Any(0o111)));
+        assert_eq!(ModeMatch::parse("a+x"), Some(ModeMatch::All(0o111)));
+        assert_eq!(ModeMatch::parse("go-w"), Some(ModeMatch::NoneOf(0o022)));
+        assert_eq!(ModeMatch::parse("u+rw"), Some(ModeMatch::All(0o600)));
+        assert_eq!(ModeMatch::parse("u+s"), Some(ModeMatch::All(0o4000)));
+        assert_eq!(ModeMatch::parse("o+t"), Some(ModeMatch::All(0o1000)));
+        assert_eq!(ModeMatch::parse("644"), Some(ModeMatch::Exactly(0o644)));
+        assert_eq!(ModeMatch::parse("4755"), Some(ModeMatch::Exactly(0o4755)));
+        assert_eq!(
+            ModeMatch::parse("World-Writable"),
+            Some(ModeMatch::All(0o002))
+        );
+        for invalid in ["x", "+", "u+q", "z+x", "g+t", "888", "77"] {
+            assert_eq!(ModeMatch::parse(invalid), None, "{}", invalid);
+        }
+
+        assert_eq!(format_mode(0o100644), "-rw-r--r--");
+        assert_eq!(format_mode(0o104755), "-rwsr-xr-x");
+        assert_eq!(format_mode(0o042750), "drwxr-s---");
+        assert_eq!(format_mode(0o041777), "drwxrwxrwt");
+        assert_eq!(format_mode(0o041776), "drwxrwxrwT");
+        assert_eq!(format_mode(0o120777), "lrwxrwxrwx");
+    }
+
+    #[tokio::test]
+    async fn filters_on_owner_group_and_mode() {
+        let (_dir, conn) = temp_database().await;
+
+        let record = |path: &str, uid: u32, gid: u32, mode: u32| FileRecord {
+            permissions: Some(Permissions { uid, gid, mode }),
+            ..record(path)
+        };
+        write(
+            &conn,
+            &[
+                record("/usr/bin/sudo", 0, 0, 0o104755),
+                record("/usr/bin/ls", 0, 0, 0o100755),
+                record("/home/theo/notes", 1000, 1000, 0o100644),
+                record("/home/theo/shared", 1000, 100, 0o100666),
+                // every symlink is lrwxrwxrwx, that doesn't make it world writable
+                record("/home/theo/link", 1000, 1000, 0o120777),
+            ],
+        )
+        .await;
+
+        for (query, expected) in [
+            ("owner:root", vec!["/usr/bin/ls", "/usr/bin/sudo"]),
+            ("owner:1000 perm:644", vec!["/home/theo/notes"]),
+            ("group:100", vec!["/home/theo/shared"]),
+            ("perm:world-writable", vec!["/home/theo/shared"]),
+            ("perm:+x", vec!["/usr/bin/ls", "/usr/bin/sudo"]),
+            ("setuid:yes", vec!["/usr/bin/sudo"]),
+            ("owner:root setuid:no", vec!["/usr/bin/ls"]),
+            (
+                "perm:go-w",
+                vec!["/home/theo/notes", "/usr/bin/ls", "/usr/bin/sudo"],
+            ),
+        ] {
+            assert_eq!(search_sorted(&conn, query).await, expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn unknown_users_and_bad_values_are_errors() {
+        let parse = |text: &str| SearchQuery::new().make_query(text);
+        assert_eq!(
+            parse("notes owner:nosuchuser"),
+            Err("unknown user 'nosuchuser' in owner:nosuchuser".to_string())
+        );
+        assert_eq!(
+            parse("group:typo"),
+            Err("unknown group 'typo' in group:typo".to_string())
+        );
+        for malformed in ["perm:u+q", "setuid:maybe", "size:big", "modified:someday"] {
+            assert!(parse(malformed).is_err(), "{}", malformed);
+        }
+        // only known keys are filters, anything else with a colon is text
+        assert_eq!(
+            parse("owner:root https://example.com"),
+            Ok("https://example.com".to_string())
+        );
+        assert_eq!(parse("todo:"), Ok("todo:".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod content_tests {
+    use std::path::Path;
+
+    use jwalk::WalkDirGeneric;
+    use libsql::Connection;
+
+    use crate::{
+        content::{sniff, ContentOptions},
+        database::FileRecord,
+        search::{search_files, SearchQuery},
+        tests::test_support::{index_with, open, record, search_sorted, temp_database, write},
+    };
+
+    // file names, every test file has its own
+    async fn search(conn: &Connection, query: &str) -> Vec<String> {
+        let mut names: Vec<String> = search_sorted(conn, query)
+            .await
+            .iter()
+            .map(|path| {
+                Path::new(path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn sniffs_types_and_skips_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        // no extensions, the type has to come from the contents
+        std::fs::write(tree.join("picture"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        std::fs::write(tree.join("notes"), "caf\u{e9} au lait\n").unwrap();
+        std::fs::write(tree.join("empty"), b"").unwrap();
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+        std::fs::write(tree.join("program"), &elf).unwrap();
+
+        assert_eq!(sniff(&tree.join("picture")).unwrap().mime, "image/png");
+        assert_eq!(sniff(&tree.join("notes")).unwrap().mime, "text/plain");
+        assert_eq!(sniff(&tree.join("empty")).unwrap().mime, "inode/x-empty");
+        assert!(sniff(&tree.join("program")).unwrap().binary);
+        assert_eq!(sniff(&tree.join("missing")), None);
+
+        // not every filesystem has user xattrs, tmpfs only has them on newer kernels
+        let xattrs = xattr::set(tree.join("notes"), "user.xdg.tags", b"work,recipes").is_ok();
+
+        let conn = open(&dir.path().join("search.db")).await;
+        index_with(
+            &conn,
+            WalkDirGeneric::<(usize, bool)>::new(&tree),
+            ContentOptions {
+                detect_mime: true,
+                skip_binary: true,
+                index_xattrs: true,
+            },
+        )
+        .await;
+
+        assert_eq!(search(&conn, "mime:image").await, ["picture"]);
+        assert_eq!(search(&conn, "mime:text/plain").await, ["notes"]);
+        assert!(search(&conn, "mime:application").await.is_empty());
+        if xattrs {
+            assert_eq!(search(&conn, "xattr:xdg.tags=work").await, ["notes"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_on_mime_and_xattrs() {
+        let (_dir, conn) = temp_database().await;
+
+        let record = |path: &str, mime: &str, xattrs: &[(&str, &str)]| FileRecord {
+            mime: Some(mime.to_string()),
+            xattrs: xattrs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..record(path)
+        };
+        write(
+            &conn,
+            &[
+                record(
+                    "/home/theo/holiday.jpg",
+                    "image/jpeg",
+                    &[("user.xdg.tags", "holiday,family")],
+                ),
+                record(
+                    "/home/theo/Downloads/logo.png",
+                    "image/png",
+                    &[("user.xdg.origin.url", "https://example.com/logo.png")],
+                ),
+                record(
+                    "/home/theo/report.pdf",
+                    "application/pdf",
+                    &[("user.xdg.tags", "work")],
+                ),
+                record("/home/theo/notes.txt", "text/plain", &[]),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            search(&conn, "mime:image/*").await,
+            ["holiday.jpg", "logo.png"]
+        );
+        assert_eq!(
+            search(&conn, "mime:IMAGE").await,
+            ["holiday.jpg", "logo.png"]
+        );
+        assert_eq!(search(&conn, "mime:image/png").await, ["logo.png"]);
+        assert_eq!(search(&conn, "mime:*/pdf").await, ["report.pdf"]);
+        assert_eq!(
+            search(&conn, "xattr:user.xdg.tags").await,
+            ["holiday.jpg", "report.pdf"]
+        );
+        assert_eq!(
+            search(&conn, "xattr:user.xdg.tags=work").await,
+            ["report.pdf"]
+        );
+        // one tag out of the list
+        assert_eq!(
+            search(&conn, "xattr:xdg.tags=family").await,
+            ["holiday.jpg"]
+        );
+        assert!(search(&conn, "xattr:xdg.tags=fam").await.is_empty());
+        assert_eq!(search(&conn, "xattr:xdg.origin.url").await, ["logo.png"]);
+        assert_eq!(
+            search(&conn, "mime:image xattr:xdg.tags=holiday").await,
+            ["holiday.jpg"]
+        );
+
+        let mut query = SearchQuery::new();
+        query.make_query("name:logo").unwrap();
+        let entry = search_files(&query, &conn).await.unwrap().remove(0);
+        assert_eq!(entry.mime.as_deref(), Some("image/png"));
+        assert_eq!(
+            entry.xattrs.get("user.xdg.origin.url").map(String::as_str),
+            Some("https://example.com/logo.png")
+        );
+    }
+}
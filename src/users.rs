@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+// Users and groups, from /etc/passwd and /etc/group
+//
+// only the local files are read, users and groups that come from LDAP or another NSS source
+// aren't known here
+
+pub const PASSWD_PATH: &str = "/etc/passwd";
+pub const GROUP_PATH: &str = "/etc/group";
+
+#[derive(Debug, Clone, Default)]
+pub struct Users {
+    users: HashMap<u32, String>,
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+impl Users {
+    // missing files just leave nobody known
+    pub fn load() -> Self {
+        let read = |path| std::fs::read_to_string(path).unwrap_or_default();
+        Self::parse(&read(PASSWD_PATH), &read(GROUP_PATH))
+    }
+
+    // name:password:uid:gid:gecos:home:shell and name:password:gid:member,member
+    pub fn parse(passwd: &str, group: &str) -> Self {
+        let users = passwd
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(':').collect();
+                Some((fields.get(2)?.parse().ok()?, fields.first()?.to_string()))
+            })
+            .collect();
+        let groups = group
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(':').collect();
+                Some(Group {
+                    name: fields.first()?.to_string(),
+                    gid: fields.get(2)?.parse().ok()?,
+                    members: fields
+                        .get(3)
+                        .map(|members| {
+                            members
+                                .split(',')
+                                .filter(|member| !member.is_empty())
+                                .map(String::from)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                })
+            })
+            .collect();
+        Self { users, groups }
+    }
+
+    pub fn user_name(&self, uid: u32) -> Option<&str> {
+        self.users.get(&uid).map(String::as_str)
+    }
+
+    pub fn group_name(&self, gid: u32) -> Option<&str> {
+        self.groups
+            .iter()
+            .find(|group| group.gid == gid)
+            .map(|group| group.name.as_str())
+    }
+
+    // a name, or a number like chown takes
+    pub fn uid_of(&self, user: &str) -> Option<u32> {
+        user.parse().ok().or_else(|| {
+            self.users
+                .iter()
+                .find(|(_, name)| name.as_str() == user)
+                .map(|(uid, _)| *uid)
+        })
+    }
+
+    pub fn gid_of(&self, group: &str) -> Option<u32> {
+        group.parse().ok().or_else(|| {
+            self.groups
+                .iter()
+                .find(|candidate| candidate.name == group)
+                .map(|candidate| candidate.gid)
+        })
+    }
+
+    // the primary group and every group that lists the user as a member, like getgrouplist
+    pub fn groups_of(&self, uid: u32, gid: u32) -> Vec<u32> {
+        let mut gids = vec![gid];
+        if let Some(name) = self.user_name(uid) {
+            for group in &self.groups {
+                if group.members.iter().any(|member| member == name) && !gids.contains(&group.gid) {
+                    gids.push(group.gid);
+                }
+            }
+        }
+        gids
+    }
+}